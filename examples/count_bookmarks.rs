@@ -16,6 +16,7 @@ fn main() {
                 query: None,
                 limit: None,
                 offset: Some(offset),
+                ..Default::default()
             })
             .expect("Couldn't fetch bookmarks");
         total_bookmarks += response.results.len();
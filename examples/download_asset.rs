@@ -1,4 +1,4 @@
-use std::{fs::File, io::Write};
+use std::fs::File;
 
 use linkding::LinkDingClient;
 
@@ -9,13 +9,15 @@ fn main() {
         std::env::var("LINKDING_TOKEN").expect("LINKDING_TOKEN env variable is not set");
     let linkding_client = LinkDingClient::new(&linkding_host, &linkding_token);
 
-    let result = linkding_client
-        .download_bookmark_asset(1, 1)
+    let asset_file =
+        File::create("asset.html").expect("Could not create/overwrite the file asset.html");
+    let download = linkding_client
+        .download_bookmark_asset_to(1, 1, asset_file)
         .expect("Could not download asset");
 
-    let mut asset_file =
-        File::create("asset.html").expect("Could not create/overwrite the file asset.html");
-    asset_file
-        .write(&result)
-        .expect("Could not write to asset file");
+    println!(
+        "Downloaded {} bytes ({})",
+        download.bytes_transferred,
+        download.content_type.as_deref().unwrap_or("unknown type")
+    );
 }
@@ -1,4 +1,4 @@
-use std::io::Read;
+use std::path::Path;
 
 use linkding::LinkDingClient;
 
@@ -9,9 +9,8 @@ fn main() {
         std::env::var("LINKDING_TOKEN").expect("LINKDING_TOKEN env variable is not set");
     let linkding_client = LinkDingClient::new(&linkding_host, &linkding_token);
 
-    let mut asset_file = std::fs::File::open("examples/asset.txt").unwrap();
-    let mut buffer: Vec<u8> = vec![];
-    asset_file.read_to_end(&mut buffer).unwrap();
-
-    linkding_client.upload_bookmark_asset(1, &buffer).unwrap();
+    let asset = linkding_client
+        .upload_bookmark_asset_from_path(1, Path::new("examples/asset.txt"), None)
+        .unwrap();
+    println!("Uploaded {} ({})", asset.display_name, asset.content_type);
 }
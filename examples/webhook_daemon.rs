@@ -0,0 +1,38 @@
+use linkding::{
+    BookmarkWatcher, LinkDingClient, WatchOptions, WebhookDispatcher, WebhookEndpoint,
+    WebhookOptions,
+};
+
+fn main() {
+    let linkding_host =
+        std::env::var("LINKDING_HOST").unwrap_or("http://localhost:9090".to_string());
+    let linkding_token =
+        std::env::var("LINKDING_TOKEN").expect("LINKDING_TOKEN env variable is not set");
+    let webhook_url = std::env::var("WEBHOOK_URL").expect("WEBHOOK_URL env variable is not set");
+    let linkding_client = LinkDingClient::new(&linkding_host, &linkding_token);
+
+    let endpoint = WebhookEndpoint {
+        secret: std::env::var("WEBHOOK_SECRET").ok(),
+        ..WebhookEndpoint::new(&webhook_url)
+    };
+    let dispatcher = WebhookDispatcher::new(
+        vec![endpoint],
+        WebhookOptions {
+            dead_letter_path: Some("webhooks.dead.jsonl".into()),
+            ..Default::default()
+        },
+    );
+    let mut watcher = BookmarkWatcher::new(
+        &linkding_client,
+        "webhooks.state.json",
+        WatchOptions::default(),
+    )
+    .expect("Could not load the watcher state");
+
+    dispatcher
+        .redeliver_dead_letters()
+        .expect("Could not read the dead-letter file");
+    dispatcher
+        .run(&mut watcher)
+        .expect("Stopped dispatching bookmark events");
+}
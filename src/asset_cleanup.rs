@@ -0,0 +1,346 @@
+use std::{cmp::Reverse, collections::HashMap, time::Duration};
+
+use crate::{
+    bookmark_assets::{BookmarkAsset, BookmarkAssetStatus, BookmarkAssetType},
+    bulk::{confirmed, run_bulk},
+    timestamp::UtcDateTime,
+    Bookmark, BulkOptions, BulkReport, LinkDingClient, LinkDingError, ListBookmarksArgs,
+};
+
+/// Which assets [`LinkDingClient::iter_all_assets`] yields
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AssetFilter {
+    pub asset_type: Option<BookmarkAssetType>,
+    pub status: Option<BookmarkAssetStatus>,
+    /// Only assets created at least this long ago, assets with an unparseable
+    /// creation date are left out
+    pub older_than: Option<Duration>,
+    /// Also include assets of archived bookmarks
+    pub include_archived: bool,
+}
+
+impl Default for AssetFilter {
+    fn default() -> Self {
+        AssetFilter {
+            asset_type: None,
+            status: None,
+            older_than: None,
+            include_archived: true,
+        }
+    }
+}
+
+impl AssetFilter {
+    /// Whether the asset matches the filter
+    pub fn matches(&self, asset: &BookmarkAsset) -> bool {
+        self.matches_before(asset, self.cutoff())
+    }
+
+    fn cutoff(&self) -> Option<UtcDateTime> {
+        let older_than = self.older_than?;
+        let seconds = i64::try_from(older_than.as_secs()).unwrap_or(i64::MAX);
+        Some(UtcDateTime::from_unix(
+            UtcDateTime::now().to_unix().saturating_sub(seconds),
+        ))
+    }
+
+    fn matches_before(&self, asset: &BookmarkAsset, cutoff: Option<UtcDateTime>) -> bool {
+        if self.asset_type.is_some_and(|t| t != asset.asset_type) {
+            return false;
+        }
+        if self.status.is_some_and(|s| s != asset.status) {
+            return false;
+        }
+        match cutoff {
+            Some(cutoff) => UtcDateTime::parse_rfc3339(&asset.date_created)
+                .is_some_and(|created| created <= cutoff),
+            None => true,
+        }
+    }
+}
+
+/// Iterator over the assets of all bookmarks, see [`LinkDingClient::iter_all_assets`]
+pub struct AssetIter<'a> {
+    client: &'a LinkDingClient,
+    bookmarks: Box<dyn Iterator<Item = Result<Bookmark, LinkDingError>> + 'a>,
+    current: Option<(Bookmark, std::vec::IntoIter<BookmarkAsset>)>,
+    filter: AssetFilter,
+    cutoff: Option<UtcDateTime>,
+}
+
+impl Iterator for AssetIter<'_> {
+    type Item = Result<(Bookmark, BookmarkAsset), LinkDingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((bookmark, assets)) = &mut self.current {
+                for asset in assets.by_ref() {
+                    if self.filter.matches_before(&asset, self.cutoff) {
+                        return Some(Ok((bookmark.clone(), asset)));
+                    }
+                }
+                self.current = None;
+            }
+            let bookmark = match self.bookmarks.next()? {
+                Ok(bookmark) => bookmark,
+                Err(error) => return Some(Err(error)),
+            };
+            match self.client.list_bookmark_assets(bookmark.id) {
+                Ok(response) => self.current = Some((bookmark, response.results.into_iter())),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+/// Why an asset is up for deletion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CleanupReason {
+    /// The asset's status is `Failure`
+    Failed,
+    /// The asset duplicates the given, newer asset of the same bookmark
+    DuplicateOf(i32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetCleanupCandidate {
+    pub bookmark: Bookmark,
+    pub asset: BookmarkAsset,
+    pub reason: CleanupReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AssetCleanupOptions {
+    /// Delete assets whose status is `Failure`
+    pub failed: bool,
+    /// Delete assets that duplicate a newer asset of the same bookmark
+    pub duplicates: bool,
+    /// Only assets matching the filter are considered
+    pub filter: AssetFilter,
+}
+
+impl Default for AssetCleanupOptions {
+    fn default() -> Self {
+        AssetCleanupOptions {
+            failed: true,
+            duplicates: true,
+            filter: AssetFilter::default(),
+        }
+    }
+}
+
+/// Assets to delete, see [`LinkDingClient::plan_asset_cleanup`]
+#[derive(Debug, Default)]
+pub struct AssetCleanupPlan {
+    pub candidates: Vec<AssetCleanupCandidate>,
+    /// Errors listing bookmarks or their assets, the assets that couldn't
+    /// be listed aren't part of the plan
+    pub errors: Vec<LinkDingError>,
+}
+
+impl AssetCleanupPlan {
+    /// Pick the assets to delete out of a list of assets
+    ///
+    /// Assets are duplicates when they belong to the same bookmark and have
+    /// the same type, name, content type and size. Assets without a known
+    /// size are never treated as duplicates. The newest one of them is kept,
+    /// so a bookmark keeps its latest snapshot. Only completed assets are
+    /// checked for duplicates.
+    pub fn from_assets(
+        assets: Vec<(Bookmark, BookmarkAsset)>,
+        options: &AssetCleanupOptions,
+    ) -> AssetCleanupPlan {
+        let mut assets = assets;
+        assets.sort_by_key(|(_, asset)| (asset.bookmark, Reverse(asset.id)));
+        let mut originals: HashMap<_, i32> = HashMap::new();
+        let mut candidates = Vec::new();
+        for (bookmark, asset) in assets {
+            let reason = match asset.status {
+                BookmarkAssetStatus::Failure if options.failed => Some(CleanupReason::Failed),
+                BookmarkAssetStatus::Complete
+                    if options.duplicates && asset.file_size.is_some() =>
+                {
+                    let key = (
+                        asset.bookmark,
+                        asset.asset_type,
+                        asset.display_name.clone(),
+                        asset.content_type.clone(),
+                        asset.file_size,
+                    );
+                    match originals.get(&key) {
+                        Some(original) => Some(CleanupReason::DuplicateOf(*original)),
+                        None => {
+                            originals.insert(key, asset.id);
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            };
+            if let Some(reason) = reason {
+                candidates.push(AssetCleanupCandidate {
+                    bookmark,
+                    asset,
+                    reason,
+                });
+            }
+        }
+        AssetCleanupPlan {
+            candidates,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+}
+
+/// Listing and cleaning up assets across all bookmarks
+impl LinkDingClient {
+    /// Iterate over the assets of all bookmarks matching `filter`
+    ///
+    /// Bookmarks and their assets are fetched lazily. An error listing the
+    /// assets of one bookmark is yielded and iteration continues with the
+    /// next bookmark.
+    pub fn iter_all_assets(&self, filter: AssetFilter) -> AssetIter<'_> {
+        let mut bookmarks: Box<dyn Iterator<Item = _>> =
+            Box::new(self.iter_bookmarks(ListBookmarksArgs::default()));
+        if filter.include_archived {
+            bookmarks = Box::new(
+                bookmarks.chain(self.iter_archived_bookmarks(ListBookmarksArgs::default())),
+            );
+        }
+        AssetIter {
+            client: self,
+            bookmarks,
+            current: None,
+            cutoff: filter.cutoff(),
+            filter,
+        }
+    }
+
+    /// Find the assets a cleanup would delete, without deleting anything
+    ///
+    /// Errors listing assets don't stop the planning, they are collected in
+    /// [`AssetCleanupPlan::errors`].
+    pub fn plan_asset_cleanup(&self, options: &AssetCleanupOptions) -> AssetCleanupPlan {
+        let mut assets = Vec::new();
+        let mut errors = Vec::new();
+        for result in self.iter_all_assets(options.filter.clone()) {
+            match result {
+                Ok(asset) => assets.push(asset),
+                Err(error) => errors.push(error),
+            }
+        }
+        AssetCleanupPlan {
+            errors,
+            ..AssetCleanupPlan::from_assets(assets, options)
+        }
+    }
+
+    /// Delete the assets of a cleanup plan, keyed by bookmark and asset ID
+    ///
+    /// With [`BulkOptions::dry_run`] set this only previews the deletions.
+    pub fn apply_asset_cleanup(
+        &self,
+        plan: &AssetCleanupPlan,
+        options: &BulkOptions,
+    ) -> BulkReport<(i32, i32), ()> {
+        let items = plan
+            .candidates
+            .iter()
+            .map(|candidate| ((candidate.asset.bookmark, candidate.asset.id), ()))
+            .collect();
+        run_bulk(items, options, |(bookmark_id, asset_id), _| {
+            confirmed(self.delete_bookmark_asset(*bookmark_id, *asset_id), || {
+                format!("delete asset {} of bookmark {}", asset_id, bookmark_id)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::bookmark;
+
+    fn asset(id: i32, bookmark: i32, name: &str, status: BookmarkAssetStatus) -> BookmarkAsset {
+        BookmarkAsset {
+            id,
+            bookmark,
+            asset_type: BookmarkAssetType::Upload,
+            date_created: "2025-01-01T00:00:00Z".to_string(),
+            content_type: "application/pdf".to_string(),
+            display_name: name.to_string(),
+            status,
+            file_size: Some(100),
+        }
+    }
+
+    fn unsized_asset(id: i32, bookmark: i32) -> BookmarkAsset {
+        BookmarkAsset {
+            file_size: None,
+            ..asset(id, bookmark, "upload.pdf", BookmarkAssetStatus::Complete)
+        }
+    }
+
+    #[test]
+    fn plans_failed_and_duplicate_assets() {
+        use BookmarkAssetStatus::*;
+        let assets = vec![
+            (bookmark(1), asset(3, 1, "paper.pdf", Complete)),
+            (bookmark(1), asset(2, 1, "paper.pdf", Complete)),
+            (bookmark(1), asset(4, 1, "other.pdf", Complete)),
+            (bookmark(1), asset(5, 1, "paper.pdf", Failure)),
+            (bookmark(2), asset(6, 2, "paper.pdf", Complete)),
+            (bookmark(2), asset(7, 2, "paper.pdf", Pending)),
+            (bookmark(3), unsized_asset(8, 3)),
+            (bookmark(3), unsized_asset(9, 3)),
+        ];
+        let plan = AssetCleanupPlan::from_assets(assets.clone(), &AssetCleanupOptions::default());
+        let planned: Vec<(i32, CleanupReason)> = plan
+            .candidates
+            .iter()
+            .map(|candidate| (candidate.asset.id, candidate.reason))
+            .collect();
+        assert_eq!(
+            planned,
+            vec![
+                (5, CleanupReason::Failed),
+                (2, CleanupReason::DuplicateOf(3))
+            ]
+        );
+
+        let options = AssetCleanupOptions {
+            duplicates: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            AssetCleanupPlan::from_assets(assets, &options)
+                .candidates
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn filters_by_type_status_and_age() {
+        let mut failed = asset(1, 1, "snapshot", BookmarkAssetStatus::Failure);
+        failed.asset_type = BookmarkAssetType::Snapshot;
+        let filter = AssetFilter {
+            asset_type: Some(BookmarkAssetType::Snapshot),
+            status: Some(BookmarkAssetStatus::Failure),
+            ..Default::default()
+        };
+        assert!(filter.matches(&failed));
+        assert!(!filter.matches(&asset(2, 1, "upload", BookmarkAssetStatus::Failure)));
+
+        let cutoff = UtcDateTime::parse_rfc3339("2025-06-01T00:00:00Z");
+        assert!(filter.matches_before(&failed, cutoff));
+        failed.date_created = "2025-07-01T00:00:00+02:00".to_string();
+        assert!(!filter.matches_before(&failed, cutoff));
+        failed.date_created = "yesterday".to_string();
+        assert!(!filter.matches_before(&failed, cutoff));
+    }
+}
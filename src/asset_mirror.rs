@@ -0,0 +1,414 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    asset_transfer::{checksum_path, sha256_file},
+    bookmark_assets::{BookmarkAsset, BookmarkAssetStatus},
+    LinkDingClient, LinkDingError, ListBookmarksArgs,
+};
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Where an asset is stored, relative to the mirror directory
+pub type AssetLayout = fn(&BookmarkAsset) -> PathBuf;
+
+/// The default layout, `<bookmark_id>/<asset_id>-<display_name>`
+pub fn default_asset_layout(asset: &BookmarkAsset) -> PathBuf {
+    let name = match sanitize_file_name(&asset.display_name) {
+        name if name.is_empty() => asset.id.to_string(),
+        name => format!("{}-{}", asset.id, name),
+    };
+    PathBuf::from(asset.bookmark.to_string()).join(name)
+}
+
+#[derive(Debug, Clone)]
+pub struct AssetMirrorOptions {
+    pub layout: AssetLayout,
+    /// Also mirror assets of archived bookmarks
+    pub include_archived: bool,
+    /// Delete local files of assets that no longer exist on the server
+    pub delete_removed: bool,
+    /// Re-hash local files instead of trusting their size to detect changes
+    pub verify_checksums: bool,
+}
+
+impl Default for AssetMirrorOptions {
+    fn default() -> Self {
+        AssetMirrorOptions {
+            layout: default_asset_layout,
+            include_archived: true,
+            delete_removed: true,
+            verify_checksums: false,
+        }
+    }
+}
+
+/// A mirrored asset as recorded in the manifest
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub bookmark_id: i32,
+    pub asset_id: i32,
+    /// Path of the file relative to the mirror directory
+    pub path: PathBuf,
+    pub display_name: String,
+    pub content_type: String,
+    pub date_created: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// The manifest kept in the mirror directory, keyed by asset ID
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct AssetManifest {
+    pub assets: BTreeMap<i32, ManifestEntry>,
+}
+
+impl AssetManifest {
+    /// Load the manifest of a mirror directory, empty if there is none yet
+    pub fn load(dir: &Path) -> Result<AssetManifest, LinkDingError> {
+        match File::open(dir.join(MANIFEST_FILE)) {
+            Ok(file) => Ok(serde_json::from_reader(io::BufReader::new(file))?),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(AssetManifest::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Delete the files of assets that weren't listed, returning their IDs
+    ///
+    /// Only assets of listed bookmarks are considered, unless all bookmarks
+    /// were listed and the asset's bookmark no longer exists.
+    fn prune(
+        &mut self,
+        dir: &Path,
+        walked: &Walked,
+        all_bookmarks: bool,
+    ) -> Result<Vec<i32>, LinkDingError> {
+        let removed: Vec<i32> = self
+            .assets
+            .values()
+            .filter(|entry| !walked.assets.contains(&entry.asset_id))
+            .filter(|entry| all_bookmarks || walked.bookmarks.contains(&entry.bookmark_id))
+            .map(|entry| entry.asset_id)
+            .collect();
+        for id in &removed {
+            if let Some(entry) = self.assets.remove(id) {
+                remove_file(dir, &entry.path)?;
+            }
+        }
+        Ok(removed)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), LinkDingError> {
+        let temp_path = dir.join(format!("{}.tmp", MANIFEST_FILE));
+        serde_json::to_writer_pretty(BufWriter::new(File::create(&temp_path)?), self)?;
+        fs::rename(temp_path, dir.join(MANIFEST_FILE))?;
+        Ok(())
+    }
+}
+
+/// Bookmarks and assets listed by a mirror run
+#[derive(Debug, Default)]
+struct Walked {
+    bookmarks: BTreeSet<i32>,
+    assets: BTreeSet<i32>,
+}
+
+#[derive(Debug, Default)]
+pub struct AssetMirrorReport {
+    pub downloaded: Vec<i32>,
+    pub unchanged: Vec<i32>,
+    pub deleted: Vec<i32>,
+    /// Assets that are still pending or failed on the server
+    pub not_ready: Vec<i32>,
+    pub failed: Vec<(i32, LinkDingError)>,
+}
+
+/// Mirroring bookmark assets to a local directory
+impl LinkDingClient {
+    /// Mirror the assets of all bookmarks into `dir`
+    ///
+    /// New and changed assets are downloaded, unchanged ones are skipped
+    /// based on the manifest kept in the directory, which also records each
+    /// file's checksum. Assets that failed to download are listed in the
+    /// report and retried on the next run. When listing bookmarks or assets
+    /// fails, the progress made so far is still saved to the manifest.
+    ///
+    /// With [`AssetMirrorOptions::delete_removed`] set, files are only deleted
+    /// for bookmarks this run listed, or for bookmarks that are gone
+    /// altogether when archived bookmarks were included as well. Files of
+    /// archived bookmarks mirrored by an earlier run are kept otherwise.
+    pub fn mirror_assets(
+        &self,
+        dir: &Path,
+        options: &AssetMirrorOptions,
+    ) -> Result<AssetMirrorReport, LinkDingError> {
+        fs::create_dir_all(dir)?;
+        let mut manifest = AssetManifest::load(dir)?;
+        let mut report = AssetMirrorReport::default();
+        let walked = self.mirror_walk(dir, options, &mut manifest, &mut report);
+        let pruned = walked.and_then(|walked| {
+            if options.delete_removed {
+                report.deleted = manifest.prune(dir, &walked, options.include_archived)?;
+            }
+            Ok(())
+        });
+        manifest.save(dir)?;
+        pruned?;
+        Ok(report)
+    }
+
+    /// Mirror the assets of all bookmarks, returning the listed bookmark and
+    /// asset IDs
+    fn mirror_walk(
+        &self,
+        dir: &Path,
+        options: &AssetMirrorOptions,
+        manifest: &mut AssetManifest,
+        report: &mut AssetMirrorReport,
+    ) -> Result<Walked, LinkDingError> {
+        let mut walked = Walked::default();
+        let mut bookmarks: Box<dyn Iterator<Item = _>> =
+            Box::new(self.iter_bookmarks(ListBookmarksArgs::default()));
+        if options.include_archived {
+            bookmarks = Box::new(
+                bookmarks.chain(self.iter_archived_bookmarks(ListBookmarksArgs::default())),
+            );
+        }
+        for bookmark in bookmarks {
+            let bookmark = bookmark?;
+            let assets = self.list_bookmark_assets(bookmark.id)?.results;
+            walked.bookmarks.insert(bookmark.id);
+            for asset in assets {
+                walked.assets.insert(asset.id);
+                if asset.status != BookmarkAssetStatus::Complete {
+                    report.not_ready.push(asset.id);
+                    continue;
+                }
+                let path = (options.layout)(&asset);
+                if let Some(entry) = manifest.assets.get(&asset.id) {
+                    if entry.path == path && is_unchanged(dir, entry, &asset, options)? {
+                        report.unchanged.push(asset.id);
+                        continue;
+                    }
+                }
+                match self.mirror_asset(dir, &asset, path) {
+                    Ok(entry) => {
+                        let new_path = entry.path.clone();
+                        if let Some(previous) = manifest.assets.insert(asset.id, entry) {
+                            if previous.path != new_path {
+                                remove_file(dir, &previous.path)?;
+                            }
+                        }
+                        report.downloaded.push(asset.id);
+                    }
+                    Err(error) => report.failed.push((asset.id, error)),
+                }
+            }
+        }
+        Ok(walked)
+    }
+
+    fn mirror_asset(
+        &self,
+        dir: &Path,
+        asset: &BookmarkAsset,
+        path: PathBuf,
+    ) -> Result<ManifestEntry, LinkDingError> {
+        let full_path = dir.join(&path);
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let download = self.download_resumable(asset.bookmark, asset.id, &full_path, 3)?;
+        Ok(ManifestEntry {
+            bookmark_id: asset.bookmark,
+            asset_id: asset.id,
+            path,
+            display_name: asset.display_name.clone(),
+            content_type: asset.content_type.clone(),
+            date_created: asset.date_created.clone(),
+            size: download.size,
+            sha256: download.sha256,
+        })
+    }
+}
+
+fn is_unchanged(
+    dir: &Path,
+    entry: &ManifestEntry,
+    asset: &BookmarkAsset,
+    options: &AssetMirrorOptions,
+) -> Result<bool, LinkDingError> {
+    if entry.date_created != asset.date_created || entry.display_name != asset.display_name {
+        return Ok(false);
+    }
+    let full_path = dir.join(&entry.path);
+    match fs::metadata(&full_path) {
+        Ok(metadata) if metadata.len() != entry.size => return Ok(false),
+        Ok(_) => {}
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(error) => return Err(error.into()),
+    }
+    if options.verify_checksums {
+        let (sha256, _) = sha256_file(&full_path)?;
+        return Ok(sha256 == entry.sha256);
+    }
+    Ok(true)
+}
+
+/// Remove a mirrored file, a checksum file left by older versions and its
+/// bookmark directory once that is empty
+fn remove_file(dir: &Path, path: &Path) -> Result<(), LinkDingError> {
+    let full_path = dir.join(path);
+    for file in [full_path.clone(), checksum_path(&full_path)] {
+        match fs::remove_file(&file) {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+    if let Some(parent) = full_path.parent().filter(|parent| *parent != dir) {
+        // fails when the directory isn't empty, which is fine
+        let _ = fs::remove_dir(parent);
+    }
+    Ok(())
+}
+
+/// Make a name safe to use as a single path component
+pub(crate) fn sanitize_file_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    sanitized.trim().trim_start_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bookmark_assets::BookmarkAssetType,
+        test_util::{bookmark, page, serve_api, test_dir, Route},
+    };
+
+    fn mirrored(dir: &Path, bookmark_id: i32, asset_id: i32) -> ManifestEntry {
+        let path = PathBuf::from(bookmark_id.to_string()).join(format!("{}-old.html", asset_id));
+        fs::create_dir_all(dir.join(bookmark_id.to_string())).unwrap();
+        fs::write(dir.join(&path), "old").unwrap();
+        ManifestEntry {
+            bookmark_id,
+            asset_id,
+            path,
+            display_name: "old.html".to_string(),
+            content_type: "text/html".to_string(),
+            date_created: "2024-01-01T00:00:00Z".to_string(),
+            size: 3,
+            sha256: "".to_string(),
+        }
+    }
+
+    fn asset(display_name: &str) -> BookmarkAsset {
+        BookmarkAsset {
+            id: 12,
+            bookmark: 3,
+            asset_type: BookmarkAssetType::Snapshot,
+            date_created: "2025-01-01T00:00:00Z".to_string(),
+            content_type: "text/html".to_string(),
+            display_name: display_name.to_string(),
+            status: BookmarkAssetStatus::Complete,
+            file_size: None,
+        }
+    }
+
+    #[test]
+    fn default_layout_uses_bookmark_dir() {
+        assert_eq!(
+            default_asset_layout(&asset("Snapshot 2025/01/01.html")),
+            PathBuf::from("3").join("12-Snapshot 2025_01_01.html")
+        );
+        assert_eq!(
+            default_asset_layout(&asset("..")),
+            PathBuf::from("3").join("12")
+        );
+    }
+
+    #[test]
+    fn mirrors_and_prunes_only_listed_bookmarks() {
+        let (url, _) = serve_api(vec![
+            Route::ok("GET /api/bookmarks/", page(&[bookmark(3)])),
+            Route::ok("GET /api/bookmarks/3/assets/", page(&[asset("page.html")])),
+            Route::ok("GET /api/bookmarks/3/assets/12/download/", "<html></html>"),
+        ]);
+        let client = LinkDingClient::new(&url, "token");
+        let dir = test_dir("asset-mirror");
+        let mut manifest = AssetManifest::default();
+        // removed from a listed bookmark, and kept for an archived bookmark
+        for entry in [mirrored(&dir, 3, 11), mirrored(&dir, 5, 20)] {
+            manifest.assets.insert(entry.asset_id, entry);
+        }
+        manifest.save(&dir).unwrap();
+        let options = AssetMirrorOptions {
+            include_archived: false,
+            ..Default::default()
+        };
+
+        let report = client.mirror_assets(&dir, &options).unwrap();
+        assert_eq!(report.downloaded, vec![12]);
+        assert_eq!(report.deleted, vec![11]);
+        let manifest = AssetManifest::load(&dir).unwrap();
+        assert_eq!(
+            manifest.assets.keys().copied().collect::<Vec<_>>(),
+            [12, 20]
+        );
+        let entry = &manifest.assets[&12];
+        assert_eq!(entry.size, 13);
+        assert_eq!(entry.sha256.len(), 64);
+        let file = dir.join(&entry.path);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "<html></html>");
+        assert!(!checksum_path(&file).exists());
+        assert!(!dir.join("3").join("11-old.html").exists());
+        assert!(dir.join("5").join("20-old.html").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn saves_manifest_when_listing_fails() {
+        let (url, _) = serve_api(vec![
+            Route::ok("GET /api/bookmarks/", page(&[bookmark(3), bookmark(4)])),
+            Route::ok("GET /api/bookmarks/3/assets/", page(&[asset("page.html")])),
+            Route::ok("GET /api/bookmarks/3/assets/12/download/", "<html></html>"),
+            Route {
+                request: "GET /api/bookmarks/4/assets/",
+                status: 500,
+                body: "".to_string(),
+            },
+        ]);
+        let client = LinkDingClient::new(&url, "token");
+        let dir = test_dir("asset-mirror");
+        let mut manifest = AssetManifest::default();
+        let removed = mirrored(&dir, 3, 11);
+        manifest.assets.insert(removed.asset_id, removed);
+        manifest.save(&dir).unwrap();
+
+        assert!(client
+            .mirror_assets(&dir, &AssetMirrorOptions::default())
+            .is_err());
+        // the download is recorded, but nothing is pruned after an incomplete listing
+        let manifest = AssetManifest::load(&dir).unwrap();
+        assert_eq!(
+            manifest.assets.keys().copied().collect::<Vec<_>>(),
+            [11, 12]
+        );
+        assert!(dir.join("3").join("11-old.html").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
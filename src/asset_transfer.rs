@@ -0,0 +1,620 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use reqwest::{
+    blocking::multipart::{Form, Part},
+    header::{ACCEPT_ENCODING, CONTENT_DISPOSITION, CONTENT_RANGE, CONTENT_TYPE, RANGE},
+    StatusCode,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    backoff::{is_retryable, Backoff},
+    bookmark_assets::BookmarkAsset,
+    bulk::run_bulk,
+    BulkOptions, BulkReport, Endpoint, LinkDingClient, LinkDingError,
+};
+
+/// Summary of a streamed asset download
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AssetDownload {
+    pub bytes_transferred: u64,
+    pub content_type: Option<String>,
+    pub filename: Option<String>,
+}
+
+/// A completed resumable download
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ResumableDownload {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Hex encoded SHA-256 checksum of the complete file
+    pub sha256: String,
+    /// How many bytes were already on disk from an earlier attempt
+    pub resumed_from: u64,
+    pub content_type: Option<String>,
+}
+
+/// A streaming reader over a bookmark asset's content
+///
+/// Reading fails with [`io::ErrorKind::UnexpectedEof`] if the stream ends
+/// before the length announced in the `Content-Length` header was received.
+#[derive(Debug)]
+pub struct AssetReader {
+    response: reqwest::blocking::Response,
+    content_type: Option<String>,
+    filename: Option<String>,
+    content_length: Option<u64>,
+    bytes_read: u64,
+}
+
+impl AssetReader {
+    fn new(response: reqwest::blocking::Response) -> Self {
+        let headers = response.headers();
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        let filename = headers
+            .get(CONTENT_DISPOSITION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(filename_from_content_disposition);
+        let content_length = response.content_length();
+        AssetReader {
+            response,
+            content_type,
+            filename,
+            content_length,
+            bytes_read: 0,
+        }
+    }
+
+    /// The content type the server sent for the asset
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// The filename from the `Content-Disposition` header
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// The expected length of the content, if the server announced it
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// How many bytes were read so far
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+}
+
+impl Read for AssetReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.response.read(buf)?;
+        self.bytes_read += read as u64;
+        if read == 0 && !buf.is_empty() {
+            if let Some(expected) = self.content_length {
+                if expected != self.bytes_read {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!(
+                            "Expected {} bytes but received {}",
+                            expected, self.bytes_read
+                        ),
+                    ));
+                }
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// Streaming asset downloads
+impl LinkDingClient {
+    /// Download a bookmark's asset as a stream
+    pub fn download_bookmark_asset_reader(
+        &self,
+        bookmark_id: i32,
+        asset_id: i32,
+    ) -> Result<AssetReader, LinkDingError> {
+        let endpoint = Endpoint::DownloadBookmarkAsset(bookmark_id, asset_id);
+        let request = self.prepare_request(endpoint)?.build()?;
+        let response = self.client.execute(request)?.error_for_status()?;
+        Ok(AssetReader::new(response))
+    }
+
+    /// Download a bookmark's asset into `writer` without buffering it in memory
+    ///
+    /// The number of bytes transferred is checked against the
+    /// `Content-Length` header if the server sent one.
+    pub fn download_bookmark_asset_to<W: Write>(
+        &self,
+        bookmark_id: i32,
+        asset_id: i32,
+        mut writer: W,
+    ) -> Result<AssetDownload, LinkDingError> {
+        let mut reader = self.download_bookmark_asset_reader(bookmark_id, asset_id)?;
+        let bytes_transferred = io::copy(&mut reader.response, &mut writer)?;
+        writer.flush()?;
+        if let Some(expected) = reader.content_length {
+            if expected != bytes_transferred {
+                return Err(LinkDingError::ContentLengthMismatch {
+                    expected,
+                    actual: bytes_transferred,
+                });
+            }
+        }
+        Ok(AssetDownload {
+            bytes_transferred,
+            content_type: reader.content_type,
+            filename: reader.filename,
+        })
+    }
+}
+
+/// Resumable asset downloads
+impl LinkDingClient {
+    /// Download a bookmark's asset to `path`, resuming partial downloads
+    ///
+    /// Data is written to `<path>.part` first, which is kept when the
+    /// transfer fails so the next call continues where it left off using an
+    /// HTTP `Range` request. Transfers failing on the connection, with a
+    /// server error, `408` or `429` are retried with a backoff up to
+    /// `max_attempts` times in total, other errors are returned right away. Once complete, the file is moved to
+    /// `path` and its SHA-256 checksum is written next to it in
+    /// `<path>.sha256`, in the format used by `sha256sum`, so it can be checked
+    /// later with [`verify_sha256`].
+    pub fn download_bookmark_asset_resumable(
+        &self,
+        bookmark_id: i32,
+        asset_id: i32,
+        path: &Path,
+        max_attempts: u32,
+    ) -> Result<ResumableDownload, LinkDingError> {
+        let download = self.download_resumable(bookmark_id, asset_id, path, max_attempts)?;
+        let filename = path.file_name().unwrap_or_default().to_string_lossy();
+        fs::write(
+            checksum_path(path),
+            format!("{}  {}\n", download.sha256, filename),
+        )?;
+        Ok(download)
+    }
+
+    /// Resumable download without writing the checksum file
+    pub(crate) fn download_resumable(
+        &self,
+        bookmark_id: i32,
+        asset_id: i32,
+        path: &Path,
+        max_attempts: u32,
+    ) -> Result<ResumableDownload, LinkDingError> {
+        let part = part_path(path);
+        let resumed_from = file_len(&part)?;
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+        let mut attempt = 1;
+        let content_type = loop {
+            match self.download_range(bookmark_id, asset_id, &part) {
+                Ok(content_type) => break content_type,
+                Err(error) if attempt < max_attempts && interrupted(&error) => {
+                    attempt += 1;
+                    backoff.wait();
+                }
+                Err(error) => return Err(error),
+            }
+        };
+        fs::rename(&part, path)?;
+
+        let (sha256, size) = sha256_file(path)?;
+        Ok(ResumableDownload {
+            path: path.to_path_buf(),
+            size,
+            sha256,
+            resumed_from,
+            content_type,
+        })
+    }
+
+    /// Download the rest of an asset into the partial file at `part`
+    fn download_range(
+        &self,
+        bookmark_id: i32,
+        asset_id: i32,
+        part: &Path,
+    ) -> Result<Option<String>, LinkDingError> {
+        let offset = file_len(part)?;
+        let endpoint = Endpoint::DownloadBookmarkAsset(bookmark_id, asset_id);
+        // byte ranges only make sense on the unencoded content
+        let mut builder = self
+            .prepare_request(endpoint)?
+            .header(ACCEPT_ENCODING, "identity");
+        if offset > 0 {
+            builder = builder.header(RANGE, format!("bytes={}-", offset));
+        }
+        let mut response = self.client.execute(builder.build()?)?;
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        let content_range = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_content_range);
+
+        let (mut file, expected_total) = match response.status() {
+            StatusCode::PARTIAL_CONTENT => match content_range {
+                Some((Some(start), total)) if start == offset => {
+                    (OpenOptions::new().append(true).open(part)?, total)
+                }
+                _ => {
+                    fs::remove_file(part)?;
+                    return Err(LinkDingError::ParseResponse(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Server returned an unexpected byte range",
+                    )));
+                }
+            },
+            StatusCode::RANGE_NOT_SATISFIABLE => {
+                if let Some((None, Some(total))) = content_range {
+                    if total == offset {
+                        return Ok(content_type);
+                    }
+                }
+                fs::remove_file(part)?;
+                return self.download_range(bookmark_id, asset_id, part);
+            }
+            _ => {
+                response = response.error_for_status()?;
+                let total = response.content_length();
+                (File::create(part)?, total)
+            }
+        };
+
+        io::copy(&mut response, &mut file)?;
+        file.sync_all()?;
+        let actual = file.metadata()?.len();
+        match expected_total {
+            Some(expected) if actual > expected => {
+                fs::remove_file(part)?;
+                Err(LinkDingError::ContentLengthMismatch { expected, actual })
+            }
+            Some(expected) if actual < expected => {
+                Err(LinkDingError::ContentLengthMismatch { expected, actual })
+            }
+            _ => Ok(content_type),
+        }
+    }
+}
+
+/// Whether a download failed on the way and can be resumed, unlike client
+/// errors or failing to write the file
+fn interrupted(error: &LinkDingError) -> bool {
+    match error {
+        LinkDingError::SendHttpError(error) => is_retryable(error),
+        // reading the body fails with the transport error wrapped
+        LinkDingError::ParseResponse(error) => error
+            .get_ref()
+            .is_some_and(|inner| inner.is::<reqwest::Error>()),
+        LinkDingError::ContentLengthMismatch { expected, actual } => actual < expected,
+        _ => false,
+    }
+}
+
+/// Check a file against the checksum written by [`LinkDingClient::download_bookmark_asset_resumable`]
+pub fn verify_sha256(path: &Path) -> Result<bool, LinkDingError> {
+    let recorded = fs::read_to_string(checksum_path(path))?;
+    let expected = recorded.split_whitespace().next().unwrap_or_default();
+    let (actual, _) = sha256_file(path)?;
+    Ok(actual.eq_ignore_ascii_case(expected))
+}
+
+/// SHA-256 checksum and size of a file
+pub(crate) fn sha256_file(path: &Path) -> Result<(String, u64), LinkDingError> {
+    let mut writer = HashingWriter::new(io::sink());
+    io::copy(&mut File::open(path)?, &mut writer)?;
+    let (_, sha256, size) = writer.finish();
+    Ok((sha256, size))
+}
+
+pub(crate) fn part_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".part")
+}
+
+pub(crate) fn checksum_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".sha256")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn file_len(path: &Path) -> Result<u64, LinkDingError> {
+    match fs::metadata(path) {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Parse a `Content-Range` header into the start offset and total length
+///
+/// `bytes 100-199/200` gives `(Some(100), Some(200))` and `bytes */200`, as
+/// sent with 416 responses, gives `(None, Some(200))`.
+fn parse_content_range(header: &str) -> Option<(Option<u64>, Option<u64>)> {
+    let (range, total) = header.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let total = match total {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    let start = match range {
+        "*" => None,
+        range => Some(range.split_once('-')?.0.parse().ok()?),
+    };
+    Some((start, total))
+}
+
+/// Streaming asset uploads
+impl LinkDingClient {
+    /// Upload an asset for a bookmark from any reader, streaming its content
+    ///
+    /// The `filename` becomes the asset's display name. When no
+    /// `content_type` is given it is guessed from the filename's extension.
+    pub fn upload_bookmark_asset_from_reader<R: Read + Send + 'static>(
+        &self,
+        bookmark_id: i32,
+        reader: R,
+        filename: &str,
+        content_type: Option<&str>,
+    ) -> Result<BookmarkAsset, LinkDingError> {
+        self.upload_part(bookmark_id, Part::reader(reader), filename, content_type)
+    }
+
+    /// Upload a file as an asset for a bookmark, streaming it from disk
+    ///
+    /// The file's name becomes the asset's display name. When no
+    /// `content_type` is given it is guessed from the file's extension.
+    pub fn upload_bookmark_asset_from_path(
+        &self,
+        bookmark_id: i32,
+        path: &Path,
+        content_type: Option<&str>,
+    ) -> Result<BookmarkAsset, LinkDingError> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let part = Part::reader_with_length(file, length);
+        self.upload_part(bookmark_id, part, &filename, content_type)
+    }
+
+    /// Upload every regular file in a directory as an asset for a bookmark
+    ///
+    /// Subdirectories are not descended into. Files are uploaded in name
+    /// order with the given bulk options, keyed by their path.
+    pub fn upload_bookmark_assets_from_dir(
+        &self,
+        bookmark_id: i32,
+        dir: &Path,
+        options: &BulkOptions,
+    ) -> Result<BulkReport<PathBuf, BookmarkAsset>, LinkDingError> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                paths.push((entry.path(), ()));
+            }
+        }
+        paths.sort();
+        Ok(run_bulk(paths, options, |path, _| {
+            self.upload_bookmark_asset_from_path(bookmark_id, path, None)
+        }))
+    }
+
+    fn upload_part(
+        &self,
+        bookmark_id: i32,
+        part: Part,
+        filename: &str,
+        content_type: Option<&str>,
+    ) -> Result<BookmarkAsset, LinkDingError> {
+        let content_type = content_type.unwrap_or_else(|| content_type_for(filename));
+        let part = part
+            .file_name(filename.to_string())
+            .mime_str(content_type)?;
+        let form = Form::new().part("file", part);
+        let endpoint = Endpoint::UploadBookmarkAsset(bookmark_id);
+        let request = self.prepare_request(endpoint)?.multipart(form).build()?;
+        let body: BookmarkAsset = self.client.execute(request)?.json()?;
+        Ok(body)
+    }
+}
+
+/// Writer computing the SHA-256 checksum of everything written through it
+pub(crate) struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    written: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        HashingWriter {
+            inner,
+            hasher: Sha256::new(),
+            written: 0,
+        }
+    }
+
+    /// The inner writer, the hex encoded checksum and the number of bytes written
+    pub(crate) fn finish(self) -> (W, String, u64) {
+        let checksum = format!("{:x}", self.hasher.finalize());
+        (self.inner, checksum, self.written)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Guess a content type from a filename's extension
+pub(crate) fn content_type_for(filename: &str) -> &'static str {
+    let extension = Path::new(filename)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "html" | "htm" => "text/html",
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "epub" => "application/epub+zip",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+fn filename_from_content_disposition(header: &str) -> Option<String> {
+    let mut filename = None;
+    for param in header.split(';').map(str::trim) {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+        match key.trim().to_lowercase().as_str() {
+            // RFC 5987 encoded value, e.g. UTF-8''na%C3%AFve.html, takes precedence
+            "filename*" => {
+                let Some(encoded) = value.trim().splitn(3, '\'').nth(2) else {
+                    continue;
+                };
+                let decoded = url::form_urlencoded::parse(
+                    format!("f={}", encoded.replace('+', "%2B")).as_bytes(),
+                )
+                .next()
+                .map(|(_, v)| v.into_owned());
+                if decoded.is_some() {
+                    return decoded;
+                }
+            }
+            "filename" => filename = Some(value.trim().trim_matches('"').to_string()),
+            _ => {}
+        }
+    }
+    filename.filter(|f| !f.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{serve_api, test_dir, Route};
+
+    #[test]
+    fn parse_content_disposition_filename() {
+        assert_eq!(
+            filename_from_content_disposition(r#"attachment; filename="snapshot.html.gz""#),
+            Some("snapshot.html.gz".to_string())
+        );
+        assert_eq!(
+            filename_from_content_disposition(
+                r#"attachment; filename="naive.pdf"; filename*=UTF-8''na%C3%AFve.pdf"#
+            ),
+            Some("naïve.pdf".to_string())
+        );
+        assert_eq!(filename_from_content_disposition("inline"), None);
+    }
+
+    #[test]
+    fn retries_only_interrupted_downloads() {
+        let path = test_dir("asset-transfer").join("snapshot.html");
+        let failing = |status| Route {
+            request: "GET /api/bookmarks/1/assets/2/download/",
+            status,
+            body: "".to_string(),
+        };
+        let (url, requests) = serve_api(vec![
+            failing(503),
+            Route::ok("GET /api/bookmarks/1/assets/2/download/", "<html>"),
+        ]);
+        let client = LinkDingClient::new(&url, "token");
+        let download = client.download_resumable(1, 2, &path, 3).unwrap();
+        assert_eq!(download.size, 6);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        let (url, requests) = serve_api(vec![failing(404)]);
+        let client = LinkDingClient::new(&url, "token");
+        assert!(client.download_resumable(1, 2, &path, 3).is_err());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn parse_content_range_header() {
+        assert_eq!(
+            parse_content_range("bytes 100-199/200"),
+            Some((Some(100), Some(200)))
+        );
+        assert_eq!(parse_content_range("bytes 0-99/*"), Some((Some(0), None)));
+        assert_eq!(parse_content_range("bytes */200"), Some((None, Some(200))));
+        assert_eq!(parse_content_range("items 0-1/2"), None);
+    }
+
+    #[test]
+    fn part_path_appends_extension() {
+        assert_eq!(
+            part_path(Path::new("3/12-page.html")),
+            PathBuf::from("3/12-page.html.part")
+        );
+    }
+
+    #[test]
+    fn hashing_writer_computes_sha256() {
+        let mut writer = HashingWriter::new(Vec::new());
+        writer.write_all(b"hello ").unwrap();
+        writer.write_all(b"world").unwrap();
+        let (inner, checksum, written) = writer.finish();
+        assert_eq!(inner, b"hello world");
+        assert_eq!(written, 11);
+        assert_eq!(
+            checksum,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+    }
+
+    #[test]
+    fn guess_content_type_from_extension() {
+        assert_eq!(content_type_for("paper.PDF"), "application/pdf");
+        assert_eq!(content_type_for("notes.txt"), "text/plain");
+        assert_eq!(content_type_for("archive"), "application/octet-stream");
+    }
+}
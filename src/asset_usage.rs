@@ -0,0 +1,449 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use reqwest::{
+    header::{ACCEPT_ENCODING, CONTENT_LENGTH},
+    Method,
+};
+use serde::Serialize;
+
+use crate::{
+    bookmark_assets::{BookmarkAsset, BookmarkAssetStatus, BookmarkAssetType},
+    Bookmark, Endpoint, LinkDingClient, LinkDingError, ListBookmarksArgs,
+};
+
+#[derive(Debug, Clone)]
+pub struct AssetUsageOptions {
+    /// Also include assets of archived bookmarks
+    pub include_archived: bool,
+    /// How many of the largest assets to list
+    pub largest: usize,
+    /// Send a `HEAD` request for assets the server doesn't report a size for,
+    /// older linkding versions report no sizes at all
+    pub measure_missing_sizes: bool,
+}
+
+impl Default for AssetUsageOptions {
+    fn default() -> Self {
+        AssetUsageOptions {
+            include_archived: true,
+            largest: 20,
+            measure_missing_sizes: true,
+        }
+    }
+}
+
+/// Number of assets and their combined size in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct UsageTotals {
+    pub count: u64,
+    pub size: u64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, size: Option<u64>) {
+        self.count += 1;
+        self.size += size.unwrap_or(0);
+    }
+}
+
+/// A single asset as listed in the report
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AssetUsageEntry {
+    pub bookmark_id: i32,
+    pub bookmark_url: String,
+    pub asset_id: i32,
+    pub asset_type: BookmarkAssetType,
+    pub status: BookmarkAssetStatus,
+    pub display_name: String,
+    pub content_type: String,
+    /// Size in bytes, `None` if it is unknown
+    pub size: Option<u64>,
+    /// Why measuring the size failed, see [`AssetUsageOptions::measure_missing_sizes`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_error: Option<String>,
+}
+
+impl AssetUsageEntry {
+    pub fn new(bookmark: &Bookmark, asset: &BookmarkAsset) -> AssetUsageEntry {
+        AssetUsageEntry {
+            bookmark_id: bookmark.id,
+            bookmark_url: bookmark.url.clone(),
+            asset_id: asset.id,
+            asset_type: asset.asset_type,
+            status: asset.status,
+            display_name: asset.display_name.clone(),
+            content_type: asset.content_type.clone(),
+            size: asset.file_size.and_then(|size| u64::try_from(size).ok()),
+            size_error: None,
+        }
+    }
+
+    /// The host of the bookmark's URL without a leading `www.`
+    pub fn domain(&self) -> String {
+        url::Url::parse(&self.bookmark_url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .map(|host| host.strip_prefix("www.").unwrap_or(&host).to_string())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+}
+
+/// Storage used by bookmark assets, aggregated in different ways
+///
+/// Sizes of assets without a known size count as zero, the number of such
+/// assets is reported in `unknown_size`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct AssetUsageReport {
+    pub total: UsageTotals,
+    pub by_type: BTreeMap<String, UsageTotals>,
+    pub by_status: BTreeMap<String, UsageTotals>,
+    pub by_content_type: BTreeMap<String, UsageTotals>,
+    pub by_domain: BTreeMap<String, UsageTotals>,
+    /// The largest assets, biggest first
+    pub largest: Vec<AssetUsageEntry>,
+    pub failed_snapshots: Vec<AssetUsageEntry>,
+    pub unknown_size: u64,
+    /// Assets whose size couldn't be measured, with the error
+    pub measure_failures: Vec<AssetUsageEntry>,
+}
+
+impl AssetUsageReport {
+    /// Aggregate a list of assets, keeping the `largest` biggest ones
+    pub fn from_entries(entries: Vec<AssetUsageEntry>, largest: usize) -> AssetUsageReport {
+        let mut report = AssetUsageReport::default();
+        for entry in &entries {
+            report.total.add(entry.size);
+            report
+                .by_type
+                .entry(entry.asset_type.as_str().to_string())
+                .or_default()
+                .add(entry.size);
+            report
+                .by_status
+                .entry(entry.status.as_str().to_string())
+                .or_default()
+                .add(entry.size);
+            let content_type = match entry.content_type.split(';').next().unwrap_or("").trim() {
+                "" => "unknown".to_string(),
+                content_type => content_type.to_lowercase(),
+            };
+            report
+                .by_content_type
+                .entry(content_type)
+                .or_default()
+                .add(entry.size);
+            report
+                .by_domain
+                .entry(entry.domain())
+                .or_default()
+                .add(entry.size);
+            if entry.size.is_none() {
+                report.unknown_size += 1;
+            }
+            if entry.size_error.is_some() {
+                report.measure_failures.push(entry.clone());
+            }
+            if entry.asset_type == BookmarkAssetType::Snapshot
+                && entry.status == BookmarkAssetStatus::Failure
+            {
+                report.failed_snapshots.push(entry.clone());
+            }
+        }
+
+        let mut sized: Vec<AssetUsageEntry> = entries
+            .into_iter()
+            .filter(|entry| entry.size.is_some())
+            .collect();
+        sized.sort_by(|a, b| b.size.cmp(&a.size).then(a.asset_id.cmp(&b.asset_id)));
+        sized.truncate(largest);
+        report.largest = sized;
+        report
+    }
+
+    pub fn to_json(&self) -> Result<String, LinkDingError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Render the report as plain text tables
+    pub fn to_table(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "Total: {} assets, {}",
+            self.total.count,
+            format_size(self.total.size)
+        );
+        if self.unknown_size > 0 {
+            let _ = writeln!(
+                out,
+                "Assets without a known size: {}, counted as 0 B in all sizes",
+                self.unknown_size
+            );
+        }
+
+        for (title, totals) in [
+            ("Type", &self.by_type),
+            ("Status", &self.by_status),
+            ("Content type", &self.by_content_type),
+            ("Domain", &self.by_domain),
+        ] {
+            let mut rows: Vec<(&String, &UsageTotals)> = totals.iter().collect();
+            rows.sort_by(|a, b| b.1.size.cmp(&a.1.size).then(a.0.cmp(b.0)));
+            let width = rows
+                .iter()
+                .map(|(key, _)| key.chars().count())
+                .chain([title.len()])
+                .max()
+                .unwrap_or(0);
+            let _ = writeln!(out, "\n{:<width$}  {:>7}  {:>10}", title, "Count", "Size");
+            for (key, totals) in rows {
+                let _ = writeln!(
+                    out,
+                    "{:<width$}  {:>7}  {:>10}",
+                    key,
+                    totals.count,
+                    format_size(totals.size)
+                );
+            }
+        }
+
+        if !self.largest.is_empty() {
+            let _ = writeln!(out, "\nLargest assets");
+            for entry in &self.largest {
+                let _ = writeln!(
+                    out,
+                    "{:>10}  {}/{}  {}  {}",
+                    format_size(entry.size.unwrap_or(0)),
+                    entry.bookmark_id,
+                    entry.asset_id,
+                    entry.display_name,
+                    entry.bookmark_url
+                );
+            }
+        }
+
+        if !self.failed_snapshots.is_empty() {
+            let _ = writeln!(out, "\nFailed snapshots");
+            for entry in &self.failed_snapshots {
+                let _ = writeln!(
+                    out,
+                    "{}/{}  {}",
+                    entry.bookmark_id, entry.asset_id, entry.bookmark_url
+                );
+            }
+        }
+
+        if !self.measure_failures.is_empty() {
+            let _ = writeln!(out, "\nCould not measure");
+            for entry in &self.measure_failures {
+                let _ = writeln!(
+                    out,
+                    "{}/{}  {}",
+                    entry.bookmark_id,
+                    entry.asset_id,
+                    entry.size_error.as_deref().unwrap_or_default()
+                );
+            }
+        }
+        out
+    }
+}
+
+/// Format a size in bytes using binary units
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Reporting the storage used by bookmark assets
+impl LinkDingClient {
+    /// Walk the assets of all bookmarks and aggregate their storage usage
+    ///
+    /// Assets whose size can't be measured keep an unknown size and are
+    /// listed in [`AssetUsageReport::measure_failures`].
+    pub fn asset_usage_report(
+        &self,
+        options: &AssetUsageOptions,
+    ) -> Result<AssetUsageReport, LinkDingError> {
+        let mut bookmarks: Box<dyn Iterator<Item = _>> =
+            Box::new(self.iter_bookmarks(ListBookmarksArgs::default()));
+        if options.include_archived {
+            bookmarks = Box::new(
+                bookmarks.chain(self.iter_archived_bookmarks(ListBookmarksArgs::default())),
+            );
+        }
+
+        let mut entries = Vec::new();
+        for bookmark in bookmarks {
+            let bookmark = bookmark?;
+            for asset in self.list_bookmark_assets(bookmark.id)?.results {
+                let mut entry = AssetUsageEntry::new(&bookmark, &asset);
+                if entry.size.is_none()
+                    && options.measure_missing_sizes
+                    && asset.status == BookmarkAssetStatus::Complete
+                {
+                    match self.measure_asset_size(asset.bookmark, asset.id) {
+                        Ok(size) => entry.size = size,
+                        Err(error) => entry.size_error = Some(error.to_string()),
+                    }
+                }
+                entries.push(entry);
+            }
+        }
+        Ok(AssetUsageReport::from_entries(entries, options.largest))
+    }
+
+    /// Ask the server for an asset's size without downloading it
+    fn measure_asset_size(
+        &self,
+        bookmark_id: i32,
+        asset_id: i32,
+    ) -> Result<Option<u64>, LinkDingError> {
+        let endpoint = Endpoint::DownloadBookmarkAsset(bookmark_id, asset_id);
+        let mut request = self
+            .prepare_request(endpoint)?
+            .header(ACCEPT_ENCODING, "identity")
+            .build()?;
+        *request.method_mut() = Method::HEAD;
+        let response = self.client.execute(request)?.error_for_status()?;
+        Ok(response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{bookmark, page, serve_api, Route};
+
+    fn entry(
+        asset_id: i32,
+        url: &str,
+        asset_type: BookmarkAssetType,
+        status: BookmarkAssetStatus,
+        size: Option<u64>,
+    ) -> AssetUsageEntry {
+        AssetUsageEntry {
+            bookmark_id: asset_id * 10,
+            bookmark_url: url.to_string(),
+            asset_id,
+            asset_type,
+            status,
+            display_name: format!("asset {}", asset_id),
+            content_type: "text/html; charset=utf-8".to_string(),
+            size,
+            size_error: None,
+        }
+    }
+
+    #[test]
+    fn aggregates_usage() {
+        use BookmarkAssetStatus::*;
+        use BookmarkAssetType::*;
+        let report = AssetUsageReport::from_entries(
+            vec![
+                entry(
+                    1,
+                    "https://www.example.com/a",
+                    Snapshot,
+                    Complete,
+                    Some(300),
+                ),
+                entry(2, "https://example.com/b", Upload, Complete, Some(500)),
+                entry(3, "https://other.org/", Snapshot, Failure, None),
+                entry(4, "https://other.org/x", Snapshot, Pending, Some(100)),
+            ],
+            2,
+        );
+        assert_eq!(
+            report.total,
+            UsageTotals {
+                count: 4,
+                size: 900
+            }
+        );
+        assert_eq!(report.unknown_size, 1);
+        assert_eq!(
+            report.by_type["snapshot"],
+            UsageTotals {
+                count: 3,
+                size: 400
+            }
+        );
+        assert_eq!(
+            report.by_status["failure"],
+            UsageTotals { count: 1, size: 0 }
+        );
+        assert_eq!(
+            report.by_content_type["text/html"],
+            UsageTotals {
+                count: 4,
+                size: 900
+            }
+        );
+        assert_eq!(
+            report.by_domain["example.com"],
+            UsageTotals {
+                count: 2,
+                size: 800
+            }
+        );
+        let largest: Vec<i32> = report.largest.iter().map(|entry| entry.asset_id).collect();
+        assert_eq!(largest, vec![2, 1]);
+        assert_eq!(report.failed_snapshots.len(), 1);
+        assert_eq!(report.failed_snapshots[0].asset_id, 3);
+        let table = report.to_table();
+        assert!(table.contains("Assets without a known size: 1, counted as 0 B"));
+        assert!(table.contains("Failed snapshots\n30/3  https://other.org/"));
+    }
+
+    #[test]
+    fn failed_measurements_dont_stop_the_report() {
+        let asset = |id: i32| BookmarkAsset {
+            id,
+            bookmark: 1,
+            asset_type: BookmarkAssetType::Snapshot,
+            date_created: "2025-01-01T00:00:00Z".to_string(),
+            content_type: "text/html".to_string(),
+            display_name: "Snapshot".to_string(),
+            status: BookmarkAssetStatus::Complete,
+            file_size: None,
+        };
+        let (url, _) = serve_api(vec![
+            Route::ok("GET /api/bookmarks/", page(&[bookmark(1)])),
+            Route::ok("GET /api/bookmarks/1/assets/", page(&[asset(2), asset(3)])),
+            Route::ok("HEAD /api/bookmarks/1/assets/3/download/", "12345"),
+        ]);
+        let client = LinkDingClient::new(&url, "token");
+        let options = AssetUsageOptions {
+            include_archived: false,
+            ..Default::default()
+        };
+
+        let report = client.asset_usage_report(&options).unwrap();
+        assert_eq!(report.total, UsageTotals { count: 2, size: 5 });
+        assert_eq!(report.unknown_size, 1);
+        assert_eq!(report.measure_failures.len(), 1);
+        assert_eq!(report.measure_failures[0].asset_id, 2);
+        assert!(report.measure_failures[0].size_error.is_some());
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+    }
+}
@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+
+use reqwest::StatusCode;
+
+/// Exponential backoff between attempts, bounded by an optional deadline
+#[derive(Debug, Clone)]
+pub(crate) struct Backoff {
+    delay: Duration,
+    max_delay: Duration,
+    deadline: Option<Instant>,
+}
+
+impl Backoff {
+    pub(crate) fn new(initial: Duration, max_delay: Duration) -> Self {
+        Backoff {
+            delay: initial,
+            max_delay,
+            deadline: None,
+        }
+    }
+
+    pub(crate) fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+
+    /// The delay before the next attempt, `None` once the deadline has passed
+    pub(crate) fn next_delay(&mut self) -> Option<Duration> {
+        let mut delay = self.delay;
+        if let Some(deadline) = self.deadline {
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            if remaining.is_zero() {
+                return None;
+            }
+            delay = delay.min(remaining);
+        }
+        self.delay = (self.delay * 2).min(self.max_delay);
+        Some(delay)
+    }
+
+    /// Sleep until the next attempt, returns `false` once the deadline has passed
+    pub(crate) fn wait(&mut self) -> bool {
+        match self.next_delay() {
+            Some(delay) => {
+                std::thread::sleep(delay);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Whether a failed request is worth retrying: connection errors, server
+/// errors, `408` and `429`
+pub(crate) fn is_retryable(error: &reqwest::Error) -> bool {
+    error.status().is_none_or(|status| {
+        status.is_server_error()
+            || status == StatusCode::REQUEST_TIMEOUT
+            || status == StatusCode::TOO_MANY_REQUESTS
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_doubles_up_to_max() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(300));
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(100)));
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(200)));
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(300)));
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(300)));
+    }
+
+    #[test]
+    fn no_delay_after_deadline() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(1))
+            .with_timeout(Duration::ZERO);
+        assert_eq!(backoff.next_delay(), None);
+    }
+}
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 #[serde(rename_all = "snake_case")]
 #[cfg_attr(feature = "ffi", derive(uniffi::Enum))]
 pub enum BookmarkAssetType {
@@ -9,7 +9,7 @@ pub enum BookmarkAssetType {
     Snapshot,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 #[serde(rename_all = "snake_case")]
 #[cfg_attr(feature = "ffi", derive(uniffi::Enum))]
 pub enum BookmarkAssetStatus {
@@ -19,7 +19,28 @@ pub enum BookmarkAssetStatus {
     Failure,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl BookmarkAssetType {
+    /// The name linkding uses for the type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BookmarkAssetType::Upload => "upload",
+            BookmarkAssetType::Snapshot => "snapshot",
+        }
+    }
+}
+
+impl BookmarkAssetStatus {
+    /// The name linkding uses for the status
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BookmarkAssetStatus::Pending => "pending",
+            BookmarkAssetStatus::Complete => "complete",
+            BookmarkAssetStatus::Failure => "failure",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[cfg_attr(feature = "ffi", derive(uniffi::Record))]
 pub struct BookmarkAsset {
     pub id: i32,
@@ -29,9 +50,12 @@ pub struct BookmarkAsset {
     pub content_type: String,
     pub display_name: String,
     pub status: BookmarkAssetStatus,
+    /// Size of the stored file in bytes, only reported by newer linkding versions
+    #[serde(default)]
+    pub file_size: Option<i64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[cfg_attr(feature = "ffi", derive(uniffi::Record))]
 pub struct ListBookmarkAssetsResponse {
     pub count: i32,
@@ -39,3 +63,25 @@ pub struct ListBookmarkAssetsResponse {
     pub previous: Option<String>,
     pub results: Vec<BookmarkAsset>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_asset_without_file_size() {
+        let json = r#"{
+            "id": 1,
+            "bookmark": 2,
+            "asset_type": "snapshot",
+            "date_created": "2025-01-01T00:00:00Z",
+            "content_type": "text/html",
+            "display_name": "HTML snapshot",
+            "status": "failure"
+        }"#;
+        let asset: BookmarkAsset = serde_json::from_str(json).unwrap();
+        assert_eq!(asset.asset_type, BookmarkAssetType::Snapshot);
+        assert_eq!(asset.status, BookmarkAssetStatus::Failure);
+        assert_eq!(asset.file_size, None);
+    }
+}
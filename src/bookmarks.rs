@@ -1,8 +1,12 @@
 use serde::{Deserialize, Serialize};
 
-use crate::QueryString;
+use crate::{
+    tag_changes::{contains_tag, same_tags},
+    timestamp::UtcDateTime,
+    Endpoint, LinkDingClient, LinkDingError, QueryString,
+};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[cfg_attr(feature = "ffi", derive(uniffi::Record))]
 pub struct Bookmark {
     pub id: i32,
@@ -23,7 +27,7 @@ pub struct Bookmark {
     pub website_description: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[cfg_attr(feature = "ffi", derive(uniffi::Record))]
 pub struct ListBookmarksResponse {
     pub count: i32,
@@ -32,7 +36,7 @@ pub struct ListBookmarksResponse {
     pub results: Vec<Bookmark>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[cfg_attr(feature = "ffi", derive(uniffi::Record))]
 pub struct PageMetadata {
     pub url: String,
@@ -41,7 +45,7 @@ pub struct PageMetadata {
     pub preview_image: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[cfg_attr(feature = "ffi", derive(uniffi::Record))]
 pub struct CheckUrlResponse {
     pub bookmark: Option<Bookmark>,
@@ -49,7 +53,7 @@ pub struct CheckUrlResponse {
     pub auto_tags: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 #[cfg_attr(feature = "ffi", derive(uniffi::Record))]
 pub struct CreateBookmarkBody {
     pub url: String,
@@ -83,9 +87,11 @@ pub struct CreateBookmarkBody {
     pub website_description: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 #[cfg_attr(feature = "ffi", derive(uniffi::Record))]
 pub struct UpdateBookmarkBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -116,20 +122,314 @@ pub struct UpdateBookmarkBody {
     pub website_description: Option<String>,
 }
 
-#[derive(Debug, Default, Clone)]
+impl Bookmark {
+    /// Compute the update needed to turn this bookmark into `other`
+    ///
+    /// Only the fields that differ are set in the returned body. The
+    /// `date_modified` field is managed by the server and is never included.
+    /// Optional fields that are set here but not in `other` can't be cleared
+    /// through an update and are left out as well. Tags only count as changed
+    /// when the set of tags differs, linkding ignores their order and case.
+    pub fn diff(&self, other: &Bookmark) -> UpdateBookmarkBody {
+        fn changed<T: PartialEq + Clone>(old: &T, new: &T) -> Option<T> {
+            (old != new).then(|| new.clone())
+        }
+
+        UpdateBookmarkBody {
+            url: changed(&self.url, &other.url),
+            title: changed(&self.title, &other.title),
+            description: changed(&self.description, &other.description),
+            notes: changed(&self.notes, &other.notes),
+            web_archive_snapshot_url: changed(
+                &self.web_archive_snapshot_url,
+                &other.web_archive_snapshot_url,
+            ),
+            favicon_url: changed(&self.favicon_url, &other.favicon_url).flatten(),
+            preview_image_url: changed(&self.preview_image_url, &other.preview_image_url).flatten(),
+            is_archived: changed(&self.is_archived, &other.is_archived),
+            unread: changed(&self.unread, &other.unread),
+            shared: changed(&self.shared, &other.shared),
+            tag_names: (!same_tags(&self.tag_names, &other.tag_names))
+                .then(|| other.tag_names.clone()),
+            date_added: changed(&self.date_added, &other.date_added),
+            date_modified: None,
+            website_title: changed(&self.website_title, &other.website_title).flatten(),
+            website_description: changed(&self.website_description, &other.website_description)
+                .flatten(),
+        }
+    }
+
+    /// Apply an update locally, setting the fields the body has set
+    pub fn apply(&mut self, body: &UpdateBookmarkBody) {
+        fn set<T: Clone>(field: &mut T, value: &Option<T>) {
+            if let Some(value) = value {
+                *field = value.clone();
+            }
+        }
+        fn set_optional<T: Clone>(field: &mut Option<T>, value: &Option<T>) {
+            if value.is_some() {
+                *field = value.clone();
+            }
+        }
+
+        set(&mut self.url, &body.url);
+        set(&mut self.title, &body.title);
+        set(&mut self.description, &body.description);
+        set(&mut self.notes, &body.notes);
+        set(
+            &mut self.web_archive_snapshot_url,
+            &body.web_archive_snapshot_url,
+        );
+        set_optional(&mut self.favicon_url, &body.favicon_url);
+        set_optional(&mut self.preview_image_url, &body.preview_image_url);
+        set(&mut self.is_archived, &body.is_archived);
+        set(&mut self.unread, &body.unread);
+        set(&mut self.shared, &body.shared);
+        set(&mut self.tag_names, &body.tag_names);
+        set(&mut self.date_added, &body.date_added);
+        set(&mut self.date_modified, &body.date_modified);
+        set_optional(&mut self.website_title, &body.website_title);
+        set_optional(&mut self.website_description, &body.website_description);
+    }
+
+    /// Build a body that recreates this bookmark, e.g. on another instance
+    pub fn to_create_body(&self) -> CreateBookmarkBody {
+        CreateBookmarkBody {
+            url: self.url.clone(),
+            title: Some(self.title.clone()),
+            description: Some(self.description.clone()),
+            notes: Some(self.notes.clone()),
+            web_archive_snapshot_url: Some(self.web_archive_snapshot_url.clone()),
+            favicon_url: self.favicon_url.clone(),
+            preview_image_url: self.preview_image_url.clone(),
+            is_archived: Some(self.is_archived),
+            unread: Some(self.unread),
+            shared: Some(self.shared),
+            tag_names: Some(self.tag_names.clone()),
+            date_added: Some(self.date_added.clone()),
+            date_modified: Some(self.date_modified.clone()),
+            website_title: self.website_title.clone(),
+            website_description: self.website_description.clone(),
+        }
+    }
+}
+
+impl UpdateBookmarkBody {
+    /// Whether the body has no fields set, i.e. sending it would change nothing
+    pub fn is_empty(&self) -> bool {
+        self == &UpdateBookmarkBody::default()
+    }
+
+    /// Three-way merge of this update with a bookmark that was modified
+    /// concurrently
+    ///
+    /// `base` is the version the update was made against, `current` is the
+    /// version now on the server. Tags added or removed on either side are
+    /// added or removed in the result. Notes changed on only one side keep
+    /// that side's text, and when both sides only appended to the base notes
+    /// (or both added notes to a bookmark without any) both additions are
+    /// kept. Any other concurrent change of the notes is a conflict and
+    /// returns `None`. All other fields of this update take precedence. This is meant to be passed to
+    /// [`crate::LinkDingClient::update_bookmark_with_merge`] as
+    /// `|current, update| UpdateBookmarkBody::merge_tags_and_notes(&base, current, update)`.
+    pub fn merge_tags_and_notes(
+        base: &Bookmark,
+        current: &Bookmark,
+        mut update: UpdateBookmarkBody,
+    ) -> Option<UpdateBookmarkBody> {
+        if let Some(tag_names) = update.tag_names.as_mut() {
+            // keep a local tag unless the server removed it since the base
+            tag_names.retain(|tag| {
+                !contains_tag(&base.tag_names, tag) || contains_tag(&current.tag_names, tag)
+            });
+            for tag in &current.tag_names {
+                if !contains_tag(&base.tag_names, tag) && !contains_tag(tag_names, tag) {
+                    tag_names.push(tag.clone());
+                }
+            }
+        }
+        if let Some(notes) = update.notes.as_mut() {
+            let (base, server) = (base.notes.as_str(), current.notes.as_str());
+            if notes.as_str() == base {
+                *notes = server.to_string();
+            } else if server != base && server != notes.as_str() {
+                *notes = if base.is_empty() {
+                    format!("{}\n\n{}", server, notes)
+                } else {
+                    server.strip_prefix(base)?;
+                    format!("{}{}", server, notes.strip_prefix(base)?)
+                };
+            }
+        }
+        Some(update)
+    }
+}
+
+impl From<CreateBookmarkBody> for UpdateBookmarkBody {
+    fn from(body: CreateBookmarkBody) -> Self {
+        UpdateBookmarkBody {
+            // the URL identifies the bookmark the update is sent to
+            url: None,
+            title: body.title,
+            description: body.description,
+            notes: body.notes,
+            web_archive_snapshot_url: body.web_archive_snapshot_url,
+            favicon_url: body.favicon_url,
+            preview_image_url: body.preview_image_url,
+            is_archived: body.is_archived,
+            unread: body.unread,
+            shared: body.shared,
+            tag_names: body.tag_names,
+            date_added: body.date_added,
+            date_modified: body.date_modified,
+            website_title: body.website_title,
+            website_description: body.website_description,
+        }
+    }
+}
+
+/// What to do when ensuring a bookmark for a URL that is already bookmarked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "ffi", derive(uniffi::Enum))]
+pub enum EnsurePolicy {
+    /// Leave the existing bookmark untouched
+    #[default]
+    SkipIfExists,
+    /// Add the new tags to the existing bookmark, nothing else is changed
+    MergeTags,
+    /// Only set fields that are empty on the existing bookmark
+    FillEmpty,
+    /// Replace the existing bookmark's fields with the new ones
+    Overwrite,
+}
+
+/// What ensuring a bookmark ended up doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "ffi", derive(uniffi::Enum))]
+pub enum EnsureOutcome {
+    Created,
+    Merged,
+    Overwritten,
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "ffi", derive(uniffi::Record))]
+pub struct EnsureBookmarkResult {
+    pub outcome: EnsureOutcome,
+    pub bookmark: Bookmark,
+    pub metadata: PageMetadata,
+    pub auto_tags: Vec<String>,
+}
+
+impl EnsurePolicy {
+    /// The update to apply to `existing` to bring in `body` under this policy
+    pub(crate) fn update_for(
+        &self,
+        existing: &Bookmark,
+        body: &CreateBookmarkBody,
+    ) -> UpdateBookmarkBody {
+        fn fill(existing: &str, new: &Option<String>) -> Option<String> {
+            new.clone().filter(|v| existing.is_empty() && !v.is_empty())
+        }
+        fn fill_optional(existing: &Option<String>, new: &Option<String>) -> Option<String> {
+            fill(existing.as_deref().unwrap_or_default(), new)
+        }
+
+        match self {
+            EnsurePolicy::SkipIfExists => UpdateBookmarkBody::default(),
+            EnsurePolicy::MergeTags => {
+                let mut tag_names = existing.tag_names.clone();
+                for tag in body.tag_names.iter().flatten() {
+                    if !contains_tag(&tag_names, tag) {
+                        tag_names.push(tag.clone());
+                    }
+                }
+                UpdateBookmarkBody {
+                    tag_names: (tag_names != existing.tag_names).then_some(tag_names),
+                    ..Default::default()
+                }
+            }
+            EnsurePolicy::FillEmpty => UpdateBookmarkBody {
+                title: fill(&existing.title, &body.title),
+                description: fill(&existing.description, &body.description),
+                notes: fill(&existing.notes, &body.notes),
+                web_archive_snapshot_url: fill(
+                    &existing.web_archive_snapshot_url,
+                    &body.web_archive_snapshot_url,
+                ),
+                favicon_url: fill_optional(&existing.favicon_url, &body.favicon_url),
+                preview_image_url: fill_optional(
+                    &existing.preview_image_url,
+                    &body.preview_image_url,
+                ),
+                tag_names: body
+                    .tag_names
+                    .clone()
+                    .filter(|v| existing.tag_names.is_empty() && !v.is_empty()),
+                website_title: fill_optional(&existing.website_title, &body.website_title),
+                website_description: fill_optional(
+                    &existing.website_description,
+                    &body.website_description,
+                ),
+                ..Default::default()
+            },
+            EnsurePolicy::Overwrite => body.clone().into(),
+        }
+    }
+}
+
+/// Arguments for listing bookmarks
+///
+/// More filters are added as linkding's API grows, so set the ones needed
+/// and fill in the rest with `..Default::default()`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "ffi", derive(uniffi::Record))]
 pub struct ListBookmarksArgs {
     pub query: Option<String>,
     pub limit: Option<i32>,
     pub offset: Option<i32>,
+    /// Only bookmarks modified after this RFC 3339 timestamp
+    ///
+    /// linkding versions without this filter ignore it and list all
+    /// bookmarks, see [`ListBookmarksArgs::predates_modified_since`].
+    pub modified_since: Option<String>,
+}
+
+impl ListBookmarksArgs {
+    /// Whether `bookmark` was modified before [`ListBookmarksArgs::modified_since`],
+    /// so a server listing it ignored the filter
+    pub fn predates_modified_since(&self, bookmark: &Bookmark) -> bool {
+        let Some(since) = self.modified_since.as_deref() else {
+            return false;
+        };
+        match (
+            UtcDateTime::parse_rfc3339(&bookmark.date_modified),
+            UtcDateTime::parse_rfc3339(since),
+        ) {
+            (Some(modified), Some(since)) => modified < since,
+            _ => false,
+        }
+    }
 }
 
 impl QueryString for ListBookmarksArgs {
     fn query_string(&self) -> String {
         [
-            ("q", self.query.as_ref().map(|v| v.to_string())),
+            (
+                "q",
+                self.query
+                    .as_ref()
+                    .map(|v| url::form_urlencoded::byte_serialize(v.as_bytes()).collect()),
+            ),
             ("limit", self.limit.as_ref().map(|v| v.to_string())),
             ("offset", self.offset.as_ref().map(|v| v.to_string())),
+            (
+                "modified_since",
+                self.modified_since
+                    .as_ref()
+                    .map(|v| url::form_urlencoded::byte_serialize(v.as_bytes()).collect()),
+            ),
         ]
         .iter()
         .filter_map(|(k, v)| v.as_ref().map(|v| format!("{}={}", k, v)))
@@ -137,3 +437,255 @@ impl QueryString for ListBookmarksArgs {
         .join("&")
     }
 }
+
+/// Iterator over all bookmarks matching a query, fetching pages as needed
+///
+/// Created with [`LinkDingClient::iter_bookmarks`] or
+/// [`LinkDingClient::iter_archived_bookmarks`].
+pub struct BookmarkIter<'a> {
+    client: &'a LinkDingClient,
+    archived: bool,
+    args: ListBookmarksArgs,
+    page: std::vec::IntoIter<Bookmark>,
+    done: bool,
+}
+
+impl<'a> BookmarkIter<'a> {
+    pub(crate) fn new(client: &'a LinkDingClient, args: ListBookmarksArgs, archived: bool) -> Self {
+        BookmarkIter {
+            client,
+            archived,
+            args,
+            page: Vec::new().into_iter(),
+            done: false,
+        }
+    }
+
+    fn fetch_page(&mut self) -> Result<(), LinkDingError> {
+        let endpoint = match self.archived {
+            true => Endpoint::ListArchivedBookmarks(self.args.clone()),
+            false => Endpoint::ListBookmarks(self.args.clone()),
+        };
+        let request = self.client.prepare_request(endpoint)?.build()?;
+        let response: ListBookmarksResponse = self.client.client.execute(request)?.json()?;
+        self.args.offset = Some(self.args.offset.unwrap_or(0) + response.results.len() as i32);
+        self.done = response.next.is_none() || response.results.is_empty();
+        self.page = response.results.into_iter();
+        Ok(())
+    }
+}
+
+impl Iterator for BookmarkIter<'_> {
+    type Item = Result<Bookmark, LinkDingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(bookmark) = self.page.next() {
+                return Some(Ok(bookmark));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(error) = self.fetch_page() {
+                self.done = true;
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, tags};
+
+    fn bookmark() -> Bookmark {
+        Bookmark {
+            title: "Example".to_string(),
+            unread: true,
+            tag_names: tags(&["rust"]),
+            ..test_util::bookmark(1)
+        }
+    }
+
+    #[test]
+    fn diff_identical_bookmarks_is_empty() {
+        let original = bookmark();
+        let mut modified = original.clone();
+        modified.date_modified = "2025-02-01T00:00:00Z".to_string();
+        assert!(original.diff(&modified).is_empty());
+    }
+
+    #[test]
+    fn apply_inverts_diff() {
+        let original = bookmark();
+        let mut modified = original.clone();
+        modified.title = "New title".to_string();
+        modified.url = "https://example.com/moved".to_string();
+        modified.tag_names = vec!["rust".to_string(), "cli".to_string()];
+        modified.preview_image_url = Some("https://example.com/image.png".to_string());
+        let mut applied = original.clone();
+        applied.apply(&original.diff(&modified));
+        assert_eq!(applied, modified);
+    }
+
+    #[test]
+    fn diff_only_contains_changed_fields() {
+        let original = bookmark();
+        let mut modified = original.clone();
+        modified.title = "New title".to_string();
+        modified.unread = false;
+        modified.tag_names.push("programming".to_string());
+        modified.favicon_url = Some("https://example.com/favicon.ico".to_string());
+
+        let diff = original.diff(&modified);
+        assert_eq!(diff.title, Some("New title".to_string()));
+        assert_eq!(diff.unread, Some(false));
+        assert_eq!(
+            diff.tag_names,
+            Some(vec!["rust".to_string(), "programming".to_string()])
+        );
+        assert_eq!(
+            diff.favicon_url,
+            Some("https://example.com/favicon.ico".to_string())
+        );
+        assert_eq!(diff.description, None);
+        assert_eq!(diff.is_archived, None);
+        assert_eq!(diff.date_added, None);
+    }
+
+    #[test]
+    fn diff_reports_url_changes_but_not_tag_order() {
+        let original = bookmark();
+        let mut modified = original.clone();
+        modified.url = "https://example.org/".to_string();
+        modified.tag_names.reverse();
+        modified.tag_names[0] = modified.tag_names[0].to_uppercase();
+
+        let diff = original.diff(&modified);
+        assert_eq!(diff.url, Some("https://example.org/".to_string()));
+        assert_eq!(diff.tag_names, None);
+    }
+
+    #[test]
+    fn merge_tags_and_notes_keeps_both_sides() {
+        let mut base = bookmark();
+        base.tag_names = vec!["rust".to_string(), "old".to_string()];
+        base.notes = "Base note".to_string();
+        let mut current = base.clone();
+        current.tag_names = vec!["rust".to_string(), "web".to_string()];
+        current.notes = "Base note\nServer addition".to_string();
+        let update = UpdateBookmarkBody {
+            tag_names: Some(vec![
+                "old".to_string(),
+                "cli".to_string(),
+                "rust".to_string(),
+            ]),
+            notes: Some("Base note\nLocal addition".to_string()),
+            ..Default::default()
+        };
+
+        let merged = UpdateBookmarkBody::merge_tags_and_notes(&base, &current, update).unwrap();
+        assert_eq!(
+            merged.tag_names,
+            Some(vec![
+                "cli".to_string(),
+                "rust".to_string(),
+                "web".to_string()
+            ])
+        );
+        assert_eq!(
+            merged.notes,
+            Some("Base note\nServer addition\nLocal addition".to_string())
+        );
+    }
+
+    #[test]
+    fn merge_tags_and_notes_rejects_conflicting_notes() {
+        let mut base = bookmark();
+        base.notes = "Base note".to_string();
+        let mut current = base.clone();
+        current.notes = "Server note".to_string();
+        let local_only = UpdateBookmarkBody {
+            notes: Some(base.notes.clone()),
+            ..Default::default()
+        };
+        let merged = UpdateBookmarkBody::merge_tags_and_notes(&base, &current, local_only);
+        assert_eq!(merged.unwrap().notes, Some("Server note".to_string()));
+
+        let rewritten = UpdateBookmarkBody {
+            notes: Some("Local note".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            UpdateBookmarkBody::merge_tags_and_notes(&base, &current, rewritten),
+            None
+        );
+    }
+
+    #[test]
+    fn ensure_policy_fill_empty_only_sets_empty_fields() {
+        let mut existing = bookmark();
+        existing.tag_names.clear();
+        let body = CreateBookmarkBody {
+            url: existing.url.clone(),
+            title: Some("Other title".to_string()),
+            description: Some("A description".to_string()),
+            tag_names: Some(vec!["web".to_string()]),
+            ..Default::default()
+        };
+
+        let update = EnsurePolicy::FillEmpty.update_for(&existing, &body);
+        assert_eq!(update.title, None);
+        assert_eq!(update.description, Some("A description".to_string()));
+        assert_eq!(update.tag_names, Some(vec!["web".to_string()]));
+    }
+
+    #[test]
+    fn ensure_policy_merge_tags_skips_known_tags() {
+        let existing = bookmark();
+        let body = CreateBookmarkBody {
+            url: existing.url.clone(),
+            title: Some("Other title".to_string()),
+            tag_names: Some(vec!["Rust".to_string()]),
+            ..Default::default()
+        };
+
+        assert!(EnsurePolicy::MergeTags
+            .update_for(&existing, &body)
+            .is_empty());
+    }
+
+    #[test]
+    fn list_bookmarks_query_is_encoded() {
+        let args = ListBookmarksArgs {
+            query: Some("#rust & cli".to_string()),
+            limit: Some(10),
+            offset: None,
+            modified_since: Some("2025-01-01T00:00:00+01:00".to_string()),
+        };
+        assert_eq!(
+            args.query_string(),
+            "q=%23rust+%26+cli&limit=10&modified_since=2025-01-01T00%3A00%3A00%2B01%3A00"
+        );
+    }
+
+    #[test]
+    fn detects_an_ignored_modified_since_filter() {
+        let mut args = ListBookmarksArgs::default();
+        assert!(!args.predates_modified_since(&bookmark()));
+        args.modified_since = Some("2025-01-01T00:00:00Z".to_string());
+        assert!(!args.predates_modified_since(&bookmark()));
+        args.modified_since = Some("2025-01-01T00:00:01+00:00".to_string());
+        assert!(args.predates_modified_since(&bookmark()));
+    }
+
+    #[test]
+    fn to_create_body_keeps_url_and_tags() {
+        let body = bookmark().to_create_body();
+        assert_eq!(body.url, "https://example.com/1");
+        assert_eq!(body.title, Some("Example".to_string()));
+        assert_eq!(body.tag_names, Some(vec!["rust".to_string()]));
+        assert_eq!(body.unread, Some(true));
+    }
+}
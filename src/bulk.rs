@@ -0,0 +1,318 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+};
+
+use crate::{Bookmark, CreateBookmarkBody, LinkDingClient, LinkDingError, UpdateBookmarkBody};
+
+/// What a bulk operation does after an item fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OnError {
+    /// Keep processing the remaining items
+    #[default]
+    Continue,
+    /// Don't start any more items, items already in flight still finish
+    Stop,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BulkOptions {
+    /// How many requests are sent in parallel, at least one
+    pub concurrency: usize,
+    /// Report what would be done without sending any requests
+    pub dry_run: bool,
+    pub on_error: OnError,
+}
+
+impl Default for BulkOptions {
+    fn default() -> Self {
+        BulkOptions {
+            concurrency: 4,
+            dry_run: false,
+            on_error: OnError::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BulkItemOutcome<T> {
+    Done(T),
+    Failed(LinkDingError),
+    /// Not attempted because an earlier item failed with [`OnError::Stop`]
+    Skipped,
+    /// Not attempted because the operation ran in dry-run mode
+    DryRun,
+}
+
+#[derive(Debug)]
+pub struct BulkItem<K, T> {
+    /// Identifies the item, the bookmark ID or the URL for creates
+    pub key: K,
+    pub outcome: BulkItemOutcome<T>,
+}
+
+/// Per-item results of a bulk operation, in the order the items were given
+#[derive(Debug)]
+pub struct BulkReport<K, T> {
+    pub items: Vec<BulkItem<K, T>>,
+}
+
+impl<K, T> BulkReport<K, T> {
+    /// Items that completed successfully
+    pub fn succeeded(&self) -> impl Iterator<Item = (&K, &T)> {
+        self.items.iter().filter_map(|item| match &item.outcome {
+            BulkItemOutcome::Done(value) => Some((&item.key, value)),
+            _ => None,
+        })
+    }
+
+    /// Items that failed along with their errors
+    pub fn failed(&self) -> impl Iterator<Item = (&K, &LinkDingError)> {
+        self.items.iter().filter_map(|item| match &item.outcome {
+            BulkItemOutcome::Failed(error) => Some((&item.key, error)),
+            _ => None,
+        })
+    }
+
+    /// Whether no item failed or got skipped
+    pub fn is_success(&self) -> bool {
+        self.items.iter().all(|item| {
+            matches!(
+                item.outcome,
+                BulkItemOutcome::Done(_) | BulkItemOutcome::DryRun
+            )
+        })
+    }
+}
+
+/// Bulk operations running requests with bounded parallelism
+impl LinkDingClient {
+    /// Update many bookmarks, each with its own body
+    pub fn bulk_update(
+        &self,
+        updates: Vec<(i32, UpdateBookmarkBody)>,
+        options: &BulkOptions,
+    ) -> BulkReport<i32, Bookmark> {
+        run_bulk(updates, options, |id, body| self.update_bookmark(*id, body))
+    }
+
+    /// Archive many bookmarks
+    pub fn bulk_archive(&self, ids: &[i32], options: &BulkOptions) -> BulkReport<i32, ()> {
+        run_bulk(with_ids(ids), options, |id, _| {
+            confirmed(self.archive_bookmark(*id), || {
+                format!("archive bookmark {}", id)
+            })
+        })
+    }
+
+    /// Take many bookmarks out of the archive
+    pub fn bulk_unarchive(&self, ids: &[i32], options: &BulkOptions) -> BulkReport<i32, ()> {
+        run_bulk(with_ids(ids), options, |id, _| {
+            confirmed(self.unarchive_bookmark(*id), || {
+                format!("unarchive bookmark {}", id)
+            })
+        })
+    }
+
+    /// Delete many bookmarks
+    pub fn bulk_delete(&self, ids: &[i32], options: &BulkOptions) -> BulkReport<i32, ()> {
+        run_bulk(with_ids(ids), options, |id, _| {
+            confirmed(self.delete_bookmark(*id), || {
+                format!("delete bookmark {}", id)
+            })
+        })
+    }
+
+    /// Create many bookmarks, the items are keyed by URL
+    pub fn bulk_create(
+        &self,
+        bodies: Vec<CreateBookmarkBody>,
+        options: &BulkOptions,
+    ) -> BulkReport<String, Bookmark> {
+        let items = bodies
+            .into_iter()
+            .map(|body| (body.url.clone(), body))
+            .collect();
+        run_bulk(items, options, |_, body| self.create_bookmark(body))
+    }
+}
+
+fn with_ids(ids: &[i32]) -> Vec<(i32, ())> {
+    ids.iter().map(|id| (*id, ())).collect()
+}
+
+pub(crate) fn run_bulk<K, I, T, F>(
+    items: Vec<(K, I)>,
+    options: &BulkOptions,
+    operation: F,
+) -> BulkReport<K, T>
+where
+    K: Sync,
+    I: Send,
+    T: Send,
+    F: Fn(&K, I) -> Result<T, LinkDingError> + Sync,
+{
+    if options.dry_run {
+        return BulkReport {
+            items: items
+                .into_iter()
+                .map(|(key, _)| BulkItem {
+                    key,
+                    outcome: BulkItemOutcome::DryRun,
+                })
+                .collect(),
+        };
+    }
+
+    let (keys, inputs): (Vec<K>, Vec<I>) = items.into_iter().unzip();
+    let inputs: Vec<Mutex<Option<I>>> = inputs.into_iter().map(|i| Mutex::new(Some(i))).collect();
+    let next = AtomicUsize::new(0);
+    let stopped = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    let workers = options.concurrency.clamp(1, keys.len().max(1));
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let (keys, inputs, next, stopped, operation) =
+                (&keys, &inputs, &next, &stopped, &operation);
+            scope.spawn(move || loop {
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(input) = inputs.get(index) else {
+                    break;
+                };
+                let input = input
+                    .lock()
+                    .expect("Bulk input lock poisoned")
+                    .take()
+                    .expect("Bulk input taken twice");
+                let result = operation(&keys[index], input);
+                if result.is_err() && options.on_error == OnError::Stop {
+                    stopped.store(true, Ordering::SeqCst);
+                }
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+    });
+    drop(sender);
+
+    let mut outcomes: Vec<BulkItemOutcome<T>> =
+        keys.iter().map(|_| BulkItemOutcome::Skipped).collect();
+    for (index, result) in receiver {
+        outcomes[index] = match result {
+            Ok(value) => BulkItemOutcome::Done(value),
+            Err(error) => BulkItemOutcome::Failed(error),
+        };
+    }
+
+    BulkReport {
+        items: keys
+            .into_iter()
+            .zip(outcomes)
+            .map(|(key, outcome)| BulkItem { key, outcome })
+            .collect(),
+    }
+}
+
+/// Treat a request the server didn't confirm, e.g. because the bookmark
+/// doesn't exist, as failed
+pub(crate) fn confirmed(
+    result: Result<bool, LinkDingError>,
+    action: impl FnOnce() -> String,
+) -> Result<(), LinkDingError> {
+    match result? {
+        true => Ok(()),
+        false => Err(LinkDingError::Unconfirmed(action())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failing_on(failing: i32) -> impl Fn(&i32, ()) -> Result<i32, LinkDingError> + Sync {
+        move |id, _| {
+            if *id == failing {
+                Err(LinkDingError::ParseUrl(url::ParseError::EmptyHost))
+            } else {
+                Ok(id * 10)
+            }
+        }
+    }
+
+    #[test]
+    fn results_keep_input_order() {
+        let options = BulkOptions {
+            concurrency: 3,
+            ..Default::default()
+        };
+        let report = run_bulk(with_ids(&[1, 2, 3, 4, 5]), &options, failing_on(3));
+
+        let keys: Vec<i32> = report.items.iter().map(|item| item.key).collect();
+        assert_eq!(keys, vec![1, 2, 3, 4, 5]);
+        assert_eq!(report.succeeded().count(), 4);
+        assert_eq!(
+            report.failed().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![3]
+        );
+        assert!(!report.is_success());
+    }
+
+    #[test]
+    fn stop_on_error_skips_remaining_items() {
+        let options = BulkOptions {
+            concurrency: 1,
+            on_error: OnError::Stop,
+            ..Default::default()
+        };
+        let report = run_bulk(with_ids(&[1, 2, 3, 4]), &options, failing_on(2));
+
+        assert!(matches!(report.items[0].outcome, BulkItemOutcome::Done(10)));
+        assert!(matches!(
+            report.items[1].outcome,
+            BulkItemOutcome::Failed(_)
+        ));
+        assert!(matches!(report.items[2].outcome, BulkItemOutcome::Skipped));
+        assert!(matches!(report.items[3].outcome, BulkItemOutcome::Skipped));
+    }
+
+    #[test]
+    fn dry_run_does_not_call_operation() {
+        let options = BulkOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let report = run_bulk(with_ids(&[1, 2]), &options, |_, _| -> Result<(), _> {
+            panic!("Operation called in dry-run mode")
+        });
+
+        assert!(report.is_success());
+        assert!(report
+            .items
+            .iter()
+            .all(|item| matches!(item.outcome, BulkItemOutcome::DryRun)));
+    }
+
+    #[test]
+    fn unconfirmed_requests_fail() {
+        let report = run_bulk(with_ids(&[1, 2]), &BulkOptions::default(), |id, _| {
+            confirmed(Ok(*id == 1), || format!("archive bookmark {}", id))
+        });
+
+        assert_eq!(report.succeeded().count(), 1);
+        let (id, error) = report.failed().next().unwrap();
+        assert_eq!(*id, 2);
+        assert!(
+            matches!(error, LinkDingError::Unconfirmed(action) if action == "archive bookmark 2")
+        );
+        assert!(!report.is_success());
+    }
+}
@@ -0,0 +1,624 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bulk::{confirmed, run_bulk},
+    normalize_url,
+    tag_changes::{contains_tag, same_tags},
+    Bookmark, BulkOptions, BulkReport, CreateBookmarkBody, LinkDingClient, LinkDingError,
+    UpdateBookmarkBody,
+};
+
+/// Marker tag used when a definitions file doesn't name its own
+pub const DEFAULT_MANAGED_TAG: &str = "managed-by-linkding-rs";
+
+/// The desired state of a single bookmark
+///
+/// Fields left out are not managed, so a missing title keeps whatever
+/// linkding or a user set. Tags and the archived state are always managed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BookmarkDefinition {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+impl BookmarkDefinition {
+    /// The definition's tags with the marker tag added
+    fn tag_names(&self, managed_tag: &str) -> Vec<String> {
+        let mut tags = self.tags.clone();
+        if !contains_tag(&tags, managed_tag) {
+            tags.push(managed_tag.to_string());
+        }
+        tags
+    }
+
+    fn to_create_body(&self, managed_tag: &str) -> CreateBookmarkBody {
+        CreateBookmarkBody {
+            url: self.url.clone(),
+            title: self.title.clone(),
+            description: self.description.clone(),
+            notes: self.notes.clone(),
+            is_archived: Some(self.archived),
+            tag_names: Some(self.tag_names(managed_tag)),
+            ..Default::default()
+        }
+    }
+
+    /// The update turning `current` into this definition, and the archived
+    /// state to switch to if it differs
+    ///
+    /// When `adopting` a bookmark that isn't managed yet its own tags are
+    /// kept next to the defined ones, later updates replace them.
+    fn update_for(
+        &self,
+        current: &Bookmark,
+        managed_tag: &str,
+        adopting: bool,
+    ) -> (UpdateBookmarkBody, Option<bool>) {
+        let changed = |desired: &Option<String>, current: &str| {
+            desired.clone().filter(|desired| desired != current)
+        };
+        let mut tags = self.tag_names(managed_tag);
+        if adopting {
+            let defined = tags;
+            tags = current.tag_names.clone();
+            for tag in defined {
+                if !contains_tag(&tags, &tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+        let body = UpdateBookmarkBody {
+            title: changed(&self.title, &current.title),
+            description: changed(&self.description, &current.description),
+            notes: changed(&self.notes, &current.notes),
+            tag_names: (!same_tags(&tags, &current.tag_names)).then_some(tags),
+            ..Default::default()
+        };
+        let archive = (self.archived != current.is_archived).then_some(self.archived);
+        (body, archive)
+    }
+}
+
+/// A file of bookmark definitions, usually kept in version control
+///
+/// In YAML:
+///
+/// ```yaml
+/// managed_tag: team-links
+/// bookmarks:
+///   - url: https://linkding.link/
+///     title: linkding
+///     tags: [bookmarks, self-hosted]
+/// ```
+///
+/// or the same in TOML with a `[[bookmarks]]` table per bookmark.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BookmarkDefinitions {
+    /// Tag marking the bookmarks these definitions manage
+    #[serde(default = "default_managed_tag")]
+    pub managed_tag: String,
+    #[serde(default)]
+    pub bookmarks: Vec<BookmarkDefinition>,
+}
+
+fn default_managed_tag() -> String {
+    DEFAULT_MANAGED_TAG.to_string()
+}
+
+impl BookmarkDefinitions {
+    pub fn from_yaml(yaml: &str) -> Result<BookmarkDefinitions, LinkDingError> {
+        let definitions: BookmarkDefinitions = serde_yaml::from_str(yaml)
+            .map_err(|e| LinkDingError::InvalidDefinitions(e.to_string()))?;
+        definitions.validate()?;
+        Ok(definitions)
+    }
+
+    pub fn from_toml(toml: &str) -> Result<BookmarkDefinitions, LinkDingError> {
+        let definitions: BookmarkDefinitions =
+            toml::from_str(toml).map_err(|e| LinkDingError::InvalidDefinitions(e.to_string()))?;
+        definitions.validate()?;
+        Ok(definitions)
+    }
+
+    /// Read definitions from a file, picking the format by its extension
+    pub fn load(path: &Path) -> Result<BookmarkDefinitions, LinkDingError> {
+        let contents = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => BookmarkDefinitions::from_yaml(&contents),
+            Some("toml") => BookmarkDefinitions::from_toml(&contents),
+            _ => Err(LinkDingError::InvalidDefinitions(format!(
+                "{} is neither a YAML nor a TOML file",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Check the marker tag and that no URL is defined twice
+    pub fn validate(&self) -> Result<(), LinkDingError> {
+        let invalid = |message: String| Err(LinkDingError::InvalidDefinitions(message));
+        if self.managed_tag.is_empty() || self.managed_tag.contains(char::is_whitespace) {
+            return invalid(format!("invalid managed tag {:?}", self.managed_tag));
+        }
+        let mut urls = BTreeSet::new();
+        for definition in &self.bookmarks {
+            if definition.url.trim().is_empty() {
+                return invalid("bookmark without a URL".to_string());
+            }
+            if !urls.insert(normalize_url(&definition.url)) {
+                return invalid(format!("{} is defined more than once", definition.url));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What happens to managed bookmarks that are no longer defined
+///
+/// Defaults to [`PrunePolicy::Keep`], so an empty or truncated definitions
+/// file never removes anything unless deleting is asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PrunePolicy {
+    Delete,
+    Archive,
+    /// Leave them as they are
+    #[default]
+    Keep,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct DeclarativeOptions {
+    /// Take over existing bookmarks for defined URLs that don't carry the
+    /// marker tag yet, instead of leaving them alone
+    pub adopt_existing: bool,
+    pub prune: PrunePolicy,
+}
+
+/// A single change of a [`DeclarativePlan`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DeclaredChange {
+    Create {
+        definition: BookmarkDefinition,
+    },
+    Update {
+        current: Box<Bookmark>,
+        body: Box<UpdateBookmarkBody>,
+        /// The archived state to switch to
+        archive: Option<bool>,
+    },
+    Delete {
+        current: Box<Bookmark>,
+    },
+}
+
+impl DeclaredChange {
+    pub fn url(&self) -> &str {
+        match self {
+            DeclaredChange::Create { definition } => &definition.url,
+            DeclaredChange::Update { current, .. } | DeclaredChange::Delete { current } => {
+                &current.url
+            }
+        }
+    }
+}
+
+/// The changes needed to bring an instance in line with bookmark definitions
+///
+/// Created by [`LinkDingClient::plan_declared_bookmarks`] without changing
+/// anything on the server. Its `Display` output is a diff to review before
+/// applying it with [`LinkDingClient::apply_declared_bookmarks`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct DeclarativePlan {
+    pub managed_tag: String,
+    pub changes: Vec<DeclaredChange>,
+    /// Existing bookmarks for defined URLs that were left alone because they
+    /// are not managed
+    pub unmanaged: Vec<Bookmark>,
+}
+
+impl DeclarativePlan {
+    /// Plan against the `managed` bookmarks, using `lookup` to find existing
+    /// bookmarks for URLs that aren't managed yet
+    fn new<F>(
+        definitions: &BookmarkDefinitions,
+        managed: Vec<Bookmark>,
+        options: &DeclarativeOptions,
+        mut lookup: F,
+    ) -> Result<DeclarativePlan, LinkDingError>
+    where
+        F: FnMut(&str) -> Result<Option<Bookmark>, LinkDingError>,
+    {
+        let managed_tag = definitions.managed_tag.as_str();
+        let mut managed: HashMap<String, Bookmark> = managed
+            .into_iter()
+            .map(|bookmark| (normalize_url(&bookmark.url), bookmark))
+            .collect();
+        let mut plan = DeclarativePlan {
+            managed_tag: managed_tag.to_string(),
+            ..Default::default()
+        };
+
+        for definition in &definitions.bookmarks {
+            let mut adopting = false;
+            let current = match managed.remove(&normalize_url(&definition.url)) {
+                Some(current) => current,
+                None => match lookup(&definition.url)? {
+                    Some(existing) if !options.adopt_existing => {
+                        plan.unmanaged.push(existing);
+                        continue;
+                    }
+                    Some(existing) => {
+                        adopting = true;
+                        existing
+                    }
+                    None => {
+                        plan.changes.push(DeclaredChange::Create {
+                            definition: definition.clone(),
+                        });
+                        continue;
+                    }
+                },
+            };
+            let (body, archive) = definition.update_for(&current, managed_tag, adopting);
+            if !body.is_empty() || archive.is_some() {
+                plan.changes.push(DeclaredChange::Update {
+                    current: Box::new(current),
+                    body: Box::new(body),
+                    archive,
+                });
+            }
+        }
+
+        let mut removed: Vec<Bookmark> = managed.into_values().collect();
+        removed.sort_by_key(|bookmark| bookmark.id);
+        for current in removed {
+            match options.prune {
+                PrunePolicy::Delete => plan.changes.push(DeclaredChange::Delete {
+                    current: Box::new(current),
+                }),
+                PrunePolicy::Archive if !current.is_archived => {
+                    plan.changes.push(DeclaredChange::Update {
+                        current: Box::new(current),
+                        body: Box::default(),
+                        archive: Some(true),
+                    })
+                }
+                PrunePolicy::Archive | PrunePolicy::Keep => {}
+            }
+        }
+        Ok(plan)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for DeclarativePlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            match change {
+                DeclaredChange::Create { definition } => {
+                    writeln!(f, "+ {}", definition.url)?;
+                    if let Some(title) = &definition.title {
+                        writeln!(f, "    title: {:?}", title)?;
+                    }
+                    if let Some(description) = &definition.description {
+                        writeln!(f, "    description: {:?}", description)?;
+                    }
+                    if let Some(notes) = &definition.notes {
+                        writeln!(f, "    notes: {:?}", notes)?;
+                    }
+                    let tags = definition.tag_names(&self.managed_tag);
+                    writeln!(f, "    tags: {}", tags.join(", "))?;
+                    if definition.archived {
+                        writeln!(f, "    archived: true")?;
+                    }
+                }
+                DeclaredChange::Update {
+                    current,
+                    body,
+                    archive,
+                } => {
+                    writeln!(f, "~ {}", current.url)?;
+                    let fields = [
+                        ("title", &current.title, &body.title),
+                        ("description", &current.description, &body.description),
+                        ("notes", &current.notes, &body.notes),
+                    ];
+                    for (name, before, after) in fields {
+                        if let Some(after) = after {
+                            writeln!(f, "    {}: {:?} -> {:?}", name, before, after)?;
+                        }
+                    }
+                    if let Some(tags) = &body.tag_names {
+                        writeln!(
+                            f,
+                            "    tags: {} -> {}",
+                            current.tag_names.join(", "),
+                            tags.join(", ")
+                        )?;
+                    }
+                    if let Some(archive) = archive {
+                        writeln!(f, "    archived: {} -> {}", current.is_archived, archive)?;
+                    }
+                }
+                DeclaredChange::Delete { current } => writeln!(f, "- {}", current.url)?,
+            }
+        }
+        for bookmark in &self.unmanaged {
+            writeln!(
+                f,
+                "! {} exists without the {} tag, skipped",
+                bookmark.url, self.managed_tag
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Managing bookmarks declaratively from definition files
+impl LinkDingClient {
+    /// Plan the changes bringing the instance in line with `definitions`
+    ///
+    /// Only bookmarks carrying the marker tag are updated or pruned. Defined
+    /// URLs without a managed bookmark are looked up with
+    /// [`LinkDingClient::check_url`] so existing bookmarks aren't duplicated.
+    pub fn plan_declared_bookmarks(
+        &self,
+        definitions: &BookmarkDefinitions,
+        options: &DeclarativeOptions,
+    ) -> Result<DeclarativePlan, LinkDingError> {
+        let managed = self.bookmarks_tagged(&[definitions.managed_tag.as_str()])?;
+        DeclarativePlan::new(definitions, managed, options, |url| {
+            Ok(self.check_url(url)?.bookmark)
+        })
+    }
+
+    /// Apply a plan, keyed by URL with the resulting bookmark for creates and
+    /// updates
+    ///
+    /// With [`BulkOptions::dry_run`] set this only previews the changes.
+    pub fn apply_declared_bookmarks(
+        &self,
+        plan: &DeclarativePlan,
+        options: &BulkOptions,
+    ) -> BulkReport<String, Option<Bookmark>> {
+        let items = plan
+            .changes
+            .iter()
+            .map(|change| (change.url().to_string(), change))
+            .collect();
+        run_bulk(items, options, |_, change| match change {
+            DeclaredChange::Create { definition } => self
+                .create_bookmark(definition.to_create_body(&plan.managed_tag))
+                .map(Some),
+            DeclaredChange::Update {
+                current,
+                body,
+                archive,
+            } => {
+                if !body.is_empty() {
+                    self.update_bookmark(current.id, (**body).clone())?;
+                }
+                match archive {
+                    Some(true) => {
+                        confirmed(self.archive_bookmark(current.id), || {
+                            format!("archive bookmark {}", current.id)
+                        })?;
+                    }
+                    Some(false) => {
+                        confirmed(self.unarchive_bookmark(current.id), || {
+                            format!("unarchive bookmark {}", current.id)
+                        })?;
+                    }
+                    None => {}
+                }
+                self.get_bookmark(current.id).map(Some)
+            }
+            DeclaredChange::Delete { current } => {
+                confirmed(self.delete_bookmark(current.id), || {
+                    format!("delete bookmark {}", current.id)
+                })?;
+                Ok(None)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, serve_api, tags};
+
+    const YAML: &str = r#"
+managed_tag: team
+bookmarks:
+  - url: https://linkding.link/
+    title: linkding
+    tags: [bookmarks]
+  - url: https://example.com/old
+    archived: true
+  - url: https://example.com/new
+    notes: Read later
+"#;
+
+    fn bookmark(id: i32, url: &str, tag_names: &[&str]) -> Bookmark {
+        Bookmark {
+            url: url.to_string(),
+            tag_names: tags(tag_names),
+            ..test_util::bookmark(id)
+        }
+    }
+
+    #[test]
+    fn yaml_and_toml_read_the_same() {
+        let toml = r#"
+managed_tag = "team"
+
+[[bookmarks]]
+url = "https://linkding.link/"
+title = "linkding"
+tags = ["bookmarks"]
+
+[[bookmarks]]
+url = "https://example.com/old"
+archived = true
+
+[[bookmarks]]
+url = "https://example.com/new"
+notes = "Read later"
+"#;
+        let definitions = BookmarkDefinitions::from_yaml(YAML).unwrap();
+        assert_eq!(BookmarkDefinitions::from_toml(toml).unwrap(), definitions);
+        assert_eq!(definitions.bookmarks[1].tags, Vec::<String>::new());
+        assert_eq!(
+            BookmarkDefinitions::from_yaml("bookmarks: []")
+                .unwrap()
+                .managed_tag,
+            DEFAULT_MANAGED_TAG
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_definitions() {
+        for yaml in [
+            "bookmarks: [{url: https://a.com/}, {url: 'https://www.a.com'}]",
+            "managed_tag: ''",
+            "bookmarks: [{url: https://a.com/, tag: x}]",
+        ] {
+            assert!(matches!(
+                BookmarkDefinitions::from_yaml(yaml),
+                Err(LinkDingError::InvalidDefinitions(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn plans_creates_updates_and_deletes() {
+        let definitions = BookmarkDefinitions::from_yaml(YAML).unwrap();
+        let mut current = bookmark(1, "https://linkding.link", &["team", "bookmarks"]);
+        current.title = "Old title".to_string();
+        let managed = vec![
+            current,
+            bookmark(2, "https://example.com/old", &["team"]),
+            bookmark(3, "https://example.com/gone", &["team"]),
+        ];
+        let options = DeclarativeOptions {
+            prune: PrunePolicy::Delete,
+            ..Default::default()
+        };
+        let plan =
+            DeclarativePlan::new(&definitions, managed.clone(), &options, |_| Ok(None)).unwrap();
+
+        let urls: Vec<&str> = plan.changes.iter().map(|c| c.url()).collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://linkding.link",
+                "https://example.com/old",
+                "https://example.com/new",
+                "https://example.com/gone",
+            ]
+        );
+        let DeclaredChange::Update { body, archive, .. } = &plan.changes[0] else {
+            panic!("expected an update");
+        };
+        assert_eq!(body.title.as_deref(), Some("linkding"));
+        assert_eq!(body.tag_names, None);
+        assert_eq!(*archive, None);
+        assert!(matches!(
+            &plan.changes[1],
+            DeclaredChange::Update { body, archive: Some(true), .. } if body.is_empty()
+        ));
+        let DeclaredChange::Create { definition } = &plan.changes[2] else {
+            panic!("expected a create");
+        };
+        assert_eq!(
+            definition.to_create_body("team").tag_names,
+            Some(vec!["team".to_string()])
+        );
+        assert!(plan
+            .to_string()
+            .contains("~ https://linkding.link\n    title: \"Old title\" -> \"linkding\"\n"));
+        assert!(plan.to_string().ends_with("- https://example.com/gone\n"));
+
+        let plan = DeclarativePlan::new(
+            &definitions,
+            managed,
+            &DeclarativeOptions::default(),
+            |_| Ok(None),
+        )
+        .unwrap();
+        assert_eq!(plan.changes.len(), 3);
+        assert!(!plan.to_string().contains("- "));
+    }
+
+    #[test]
+    fn leaves_unmanaged_bookmarks_alone_unless_adopting() {
+        let definitions = BookmarkDefinitions::from_yaml(
+            "managed_tag: team\nbookmarks: [{url: https://a.com/, tags: [x]}]",
+        )
+        .unwrap();
+        let lookup = |url: &str| Ok(Some(bookmark(5, url, &["x", "mine"])));
+
+        let options = DeclarativeOptions::default();
+        let plan = DeclarativePlan::new(&definitions, vec![], &options, lookup).unwrap();
+        assert!(plan.is_empty());
+        assert_eq!(plan.unmanaged[0].id, 5);
+
+        let options = DeclarativeOptions {
+            adopt_existing: true,
+            ..options
+        };
+        let plan = DeclarativePlan::new(&definitions, vec![], &options, lookup).unwrap();
+        let DeclaredChange::Update { body, .. } = &plan.changes[0] else {
+            panic!("expected an update");
+        };
+        assert_eq!(
+            body.tag_names,
+            Some(vec![
+                "x".to_string(),
+                "mine".to_string(),
+                "team".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn unconfirmed_deletes_fail() {
+        let (url, requests) = serve_api(vec![]);
+        let client = LinkDingClient::new(&url, "token");
+        let plan = DeclarativePlan {
+            managed_tag: "team".to_string(),
+            changes: vec![DeclaredChange::Delete {
+                current: Box::new(bookmark(3, "https://example.com/gone", &["team"])),
+            }],
+            ..Default::default()
+        };
+
+        let report = client.apply_declared_bookmarks(&plan, &BulkOptions::default());
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["DELETE /api/bookmarks/3/".to_string()]
+        );
+        let (_, error) = report.failed().next().unwrap();
+        assert!(matches!(error, LinkDingError::Unconfirmed(_)));
+    }
+}
@@ -0,0 +1,394 @@
+use std::io::{Seek, Write};
+
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    bookmark_assets::{BookmarkAssetStatus, BookmarkAssetType},
+    extract::{extract_article, Article},
+    timestamp::UtcDateTime,
+    xml::escape,
+    Bookmark, BulkOptions, BulkReport, LinkDingClient, LinkDingError, ListBookmarksArgs,
+    UpdateBookmarkBody,
+};
+
+const STYLESHEET: &str = "body { font-family: serif; line-height: 1.5; }
+h1 { font-size: 1.5em; }
+.source { font-size: 0.85em; word-break: break-all; }
+.meta { font-size: 0.85em; font-style: italic; }
+";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReadingListOptions {
+    /// The linkding search query selecting the bookmarks
+    pub query: String,
+    pub title: String,
+    /// Language of the book as a BCP 47 tag
+    pub language: String,
+    /// Include at most this many bookmarks
+    pub limit: Option<usize>,
+    /// Mark the included bookmarks as read once the book was written
+    pub mark_as_read: bool,
+}
+
+impl Default for ReadingListOptions {
+    fn default() -> Self {
+        ReadingListOptions {
+            query: "!unread".to_string(),
+            title: "Reading list".to_string(),
+            language: "en".to_string(),
+            limit: None,
+            mark_as_read: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ReadingListReport {
+    /// Bookmarks included as chapters, in order
+    pub included: Vec<i32>,
+    /// Bookmarks left out because they have no completed snapshot
+    pub without_snapshot: Vec<i32>,
+    /// Result of marking the included bookmarks as read, if requested
+    pub marked_as_read: Option<BulkReport<i32, Bookmark>>,
+}
+
+/// A chapter of a reading list
+struct Chapter {
+    bookmark: Bookmark,
+    article: Article,
+}
+
+/// Generating EPUB reading lists
+impl LinkDingClient {
+    /// Write an EPUB with one chapter per bookmark matching the options' query
+    ///
+    /// The chapters contain the readable article extracted from each
+    /// bookmark's latest completed snapshot, headed by the source URL and
+    /// tags. Bookmarks without a completed snapshot are left out.
+    pub fn write_reading_list_epub<W: Write + Seek>(
+        &self,
+        writer: W,
+        options: &ReadingListOptions,
+    ) -> Result<ReadingListReport, LinkDingError> {
+        let mut chapters = Vec::new();
+        let mut without_snapshot = Vec::new();
+        let args = ListBookmarksArgs {
+            query: Some(options.query.clone()),
+            ..Default::default()
+        };
+        for bookmark in self.iter_bookmarks(args) {
+            if options.limit.is_some_and(|limit| chapters.len() >= limit) {
+                break;
+            }
+            let bookmark = bookmark?;
+            let snapshot = self
+                .list_bookmark_assets(bookmark.id)?
+                .results
+                .into_iter()
+                .filter(|asset| {
+                    asset.asset_type == BookmarkAssetType::Snapshot
+                        && asset.status == BookmarkAssetStatus::Complete
+                })
+                .max_by_key(|asset| asset.id);
+            let Some(snapshot) = snapshot else {
+                without_snapshot.push(bookmark.id);
+                continue;
+            };
+            let content = self.download_bookmark_asset(bookmark.id, snapshot.id)?;
+            let article = extract_article(&content)?;
+            chapters.push(Chapter { bookmark, article });
+        }
+
+        write_epub(writer, options, &chapters)?;
+
+        let included: Vec<i32> = chapters.iter().map(|c| c.bookmark.id).collect();
+        let marked_as_read = options.mark_as_read.then(|| {
+            let updates = included
+                .iter()
+                .map(|id| {
+                    let body = UpdateBookmarkBody {
+                        unread: Some(false),
+                        ..Default::default()
+                    };
+                    (*id, body)
+                })
+                .collect();
+            self.bulk_update(updates, &BulkOptions::default())
+        });
+        Ok(ReadingListReport {
+            included,
+            without_snapshot,
+            marked_as_read,
+        })
+    }
+}
+
+fn write_epub<W: Write + Seek>(
+    writer: W,
+    options: &ReadingListOptions,
+    chapters: &[Chapter],
+) -> Result<(), LinkDingError> {
+    let mut zip = ZipWriter::new(writer);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // the mimetype has to come first and uncompressed
+    zip.start_file("mimetype", stored).map_err(zip_error)?;
+    zip.write_all(b"application/epub+zip")?;
+    zip.start_file("META-INF/container.xml", deflated)
+        .map_err(zip_error)?;
+    zip.write_all(CONTAINER_XML.as_bytes())?;
+
+    let modified = UtcDateTime::now().to_rfc3339();
+    let files = [
+        (
+            "OEBPS/content.opf",
+            package_document(options, chapters, &modified),
+        ),
+        ("OEBPS/nav.xhtml", navigation_document(options, chapters)),
+        ("OEBPS/toc.ncx", ncx_document(options, chapters, &modified)),
+        ("OEBPS/style.css", STYLESHEET.to_string()),
+    ];
+    for (name, content) in files {
+        zip.start_file(name, deflated).map_err(zip_error)?;
+        zip.write_all(content.as_bytes())?;
+    }
+    for (index, chapter) in chapters.iter().enumerate() {
+        zip.start_file(format!("OEBPS/{}", chapter_file(index)), deflated)
+            .map_err(zip_error)?;
+        zip.write_all(chapter_document(options, chapter).as_bytes())?;
+    }
+    zip.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+fn zip_error(error: zip::result::ZipError) -> LinkDingError {
+    match error {
+        zip::result::ZipError::Io(error) => error.into(),
+        error => std::io::Error::other(error).into(),
+    }
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+fn chapter_file(index: usize) -> String {
+    format!("chapter-{:04}.xhtml", index + 1)
+}
+
+fn chapter_title(chapter: &Chapter) -> &str {
+    match chapter.bookmark.title.trim() {
+        "" => chapter
+            .article
+            .title
+            .as_deref()
+            .unwrap_or(&chapter.bookmark.url),
+        title => title,
+    }
+}
+
+fn book_identifier(modified: &str) -> String {
+    format!("urn:linkding:reading-list:{}", modified)
+}
+
+fn package_document(options: &ReadingListOptions, chapters: &[Chapter], modified: &str) -> String {
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    for index in 0..chapters.len() {
+        let file = chapter_file(index);
+        manifest.push_str(&format!(
+            "    <item id=\"chapter-{index}\" href=\"{file}\" media-type=\"application/xhtml+xml\"/>\n"
+        ));
+        spine.push_str(&format!("    <itemref idref=\"chapter-{index}\"/>\n"));
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">{identifier}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>{language}</dc:language>
+    <meta property="dcterms:modified">{modified}</meta>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+    <item id="style" href="style.css" media-type="text/css"/>
+{manifest}  </manifest>
+  <spine toc="ncx">
+{spine}  </spine>
+</package>
+"#,
+        identifier = escape(&book_identifier(modified)),
+        title = escape(&options.title),
+        language = escape(&options.language),
+    )
+}
+
+fn navigation_document(options: &ReadingListOptions, chapters: &[Chapter]) -> String {
+    let items: String = chapters
+        .iter()
+        .enumerate()
+        .map(|(index, chapter)| {
+            format!(
+                "      <li><a href=\"{}\">{}</a></li>\n",
+                chapter_file(index),
+                escape(chapter_title(chapter))
+            )
+        })
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{language}">
+<head><title>{title}</title></head>
+<body>
+  <nav epub:type="toc" id="toc">
+    <h1>{title}</h1>
+    <ol>
+{items}    </ol>
+  </nav>
+</body>
+</html>
+"#,
+        language = escape(&options.language),
+        title = escape(&options.title),
+    )
+}
+
+fn ncx_document(options: &ReadingListOptions, chapters: &[Chapter], modified: &str) -> String {
+    let points: String = chapters
+        .iter()
+        .enumerate()
+        .map(|(index, chapter)| {
+            format!(
+                "    <navPoint id=\"point-{order}\" playOrder=\"{order}\"><navLabel><text>{}</text></navLabel><content src=\"{}\"/></navPoint>\n",
+                escape(chapter_title(chapter)),
+                chapter_file(index),
+                order = index + 1,
+            )
+        })
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <head><meta name="dtb:uid" content="{identifier}"/></head>
+  <docTitle><text>{title}</text></docTitle>
+  <navMap>
+{points}  </navMap>
+</ncx>
+"#,
+        identifier = escape(&book_identifier(modified)),
+        title = escape(&options.title),
+    )
+}
+
+fn chapter_document(options: &ReadingListOptions, chapter: &Chapter) -> String {
+    let bookmark = &chapter.bookmark;
+    let mut header = format!(
+        "<h1>{}</h1>\n<p class=\"source\"><a href=\"{url}\">{url}</a></p>\n",
+        escape(chapter_title(chapter)),
+        url = escape(&bookmark.url),
+    );
+    if !bookmark.tag_names.is_empty() {
+        let tags: Vec<String> = bookmark
+            .tag_names
+            .iter()
+            .map(|tag| format!("#{}", escape(tag)))
+            .collect();
+        header.push_str(&format!("<p class=\"meta\">{}</p>\n", tags.join(" ")));
+    }
+    let mut meta = Vec::new();
+    if let Some(byline) = &chapter.article.byline {
+        meta.push(escape(byline));
+    }
+    meta.push(format!("{} min read", chapter.article.reading_time_minutes));
+    header.push_str(&format!("<p class=\"meta\">{}</p>\n", meta.join(" · ")));
+
+    let body: String = chapter
+        .article
+        .text
+        .split("\n\n")
+        .map(|paragraph| {
+            let lines: Vec<String> = paragraph.lines().map(escape).collect();
+            format!("<p>{}</p>\n", lines.join("<br/>"))
+        })
+        .collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="{language}">
+<head>
+  <title>{title}</title>
+  <link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+<body>
+{header}<hr/>
+{body}</body>
+</html>
+"#,
+        language = escape(&options.language),
+        title = escape(chapter_title(chapter)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::*;
+    use crate::test_util::{bookmark, tags};
+
+    fn chapter() -> Chapter {
+        Chapter {
+            bookmark: Bookmark {
+                url: "https://example.com/?a=1&b=2".to_string(),
+                unread: true,
+                tag_names: tags(&["rust"]),
+                ..bookmark(1)
+            },
+            article: Article {
+                title: Some("Fish & Chips".to_string()),
+                byline: None,
+                text: "First <paragraph>.\n\nSecond\nline".to_string(),
+                markdown: "".to_string(),
+                word_count: 3,
+                reading_time_minutes: 1,
+            },
+        }
+    }
+
+    #[test]
+    fn chapter_has_source_and_tags() {
+        let document = chapter_document(&ReadingListOptions::default(), &chapter());
+        assert!(document.contains("<h1>Fish &amp; Chips</h1>"));
+        assert!(document.contains("href=\"https://example.com/?a=1&amp;b=2\""));
+        assert!(document.contains("#rust"));
+        assert!(document.contains("<p>First &lt;paragraph&gt;.</p>"));
+        assert!(document.contains("<p>Second<br/>line</p>"));
+    }
+
+    #[test]
+    fn epub_starts_with_uncompressed_mimetype() {
+        let mut buffer = Cursor::new(Vec::new());
+        write_epub(&mut buffer, &ReadingListOptions::default(), &[chapter()]).unwrap();
+
+        let bytes = buffer.into_inner();
+        assert_eq!(&bytes[30..38], b"mimetype");
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut opf = String::new();
+        archive
+            .by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut opf)
+            .unwrap();
+        assert!(opf.contains("href=\"chapter-0001.xhtml\""));
+        assert!(archive.by_name("OEBPS/chapter-0001.xhtml").is_ok());
+    }
+}
@@ -0,0 +1,530 @@
+use scraper::{ElementRef, Html, Node, Selector};
+
+use crate::{
+    snapshot_text::parse_snapshot, Bookmark, LinkDingClient, LinkDingError, UpdateBookmarkBody,
+};
+
+const WORDS_PER_MINUTE: usize = 200;
+
+/// Elements that never contain article content
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "svg", "nav", "aside", "footer", "form",
+    "button", "iframe", "object", "select", "input", "textarea",
+];
+
+/// Class or ID fragments of elements around the article rather than in it
+const UNLIKELY_CONTENT: &[&str] = &[
+    "comment",
+    "share",
+    "social",
+    "related",
+    "sidebar",
+    "footer",
+    "navbar",
+    "menu",
+    "promo",
+    "advert",
+    "cookie",
+    "newsletter",
+    "subscribe",
+    "breadcrumb",
+    "popup",
+    "modal",
+];
+
+/// The readable content of a snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Article {
+    pub title: Option<String>,
+    pub byline: Option<String>,
+    /// The main content as plain text, paragraphs separated by blank lines
+    pub text: String,
+    /// The main content as Markdown
+    pub markdown: String,
+    pub word_count: usize,
+    /// Estimated reading time at 200 words per minute, rounded up
+    pub reading_time_minutes: u32,
+}
+
+impl Article {
+    /// The article as a Markdown document including title and byline
+    pub fn to_markdown_document(&self) -> String {
+        let mut document = String::new();
+        if let Some(title) = &self.title {
+            document.push_str(&format!("# {}\n\n", title));
+        }
+        if let Some(byline) = &self.byline {
+            document.push_str(&format!("*{}*\n\n", byline));
+        }
+        document.push_str(&self.markdown);
+        document
+    }
+}
+
+/// How extracted article text is written into a bookmark's notes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum NotesUpdate {
+    /// Add the article after the existing notes
+    #[default]
+    Append,
+    Replace,
+}
+
+/// Extract the readable article from a snapshot asset's content
+///
+/// The content is the single-file HTML as downloaded with
+/// [`LinkDingClient::download_bookmark_asset`], gzipped or not. Navigation,
+/// comments, sidebars and similar page furniture are left out as far as they
+/// can be detected.
+pub fn extract_article(content: &[u8]) -> Result<Article, LinkDingError> {
+    let document = parse_snapshot(content)?;
+    let root = main_content(&document);
+
+    // the root is never skipped, its class names say nothing about its children
+    let mut markdown = Output::default();
+    render_element(root, &mut markdown, true);
+    let mut text = Output::default();
+    render_element(root, &mut text, false);
+    let text = text.finish();
+
+    let word_count = text
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count();
+    Ok(Article {
+        title: title(&document),
+        byline: byline(&document),
+        markdown: markdown.finish(),
+        text,
+        word_count,
+        reading_time_minutes: word_count.div_ceil(WORDS_PER_MINUTE) as u32,
+    })
+}
+
+/// Extracting articles from snapshots
+impl LinkDingClient {
+    /// Download a snapshot asset and extract its readable article
+    pub fn extract_snapshot_article(
+        &self,
+        bookmark_id: i32,
+        asset_id: i32,
+    ) -> Result<Article, LinkDingError> {
+        let content = self.download_bookmark_asset(bookmark_id, asset_id)?;
+        extract_article(&content)
+    }
+
+    /// Write an extracted article into a bookmark's notes as Markdown
+    ///
+    /// When appending, notes changed on the server in the meantime are read
+    /// again and the article is appended to them. A
+    /// [`LinkDingError::Conflict`] is returned if they change once more.
+    pub fn write_article_to_notes(
+        &self,
+        bookmark_id: i32,
+        article: &Article,
+        mode: NotesUpdate,
+    ) -> Result<Bookmark, LinkDingError> {
+        let document = article.to_markdown_document();
+        let appended = |current: &Bookmark| UpdateBookmarkBody {
+            notes: Some(match current.notes.trim() {
+                "" => document.clone(),
+                existing => format!("{}\n\n---\n\n{}", existing, document),
+            }),
+            ..Default::default()
+        };
+        match mode {
+            NotesUpdate::Replace => {
+                let body = UpdateBookmarkBody {
+                    notes: Some(document.clone()),
+                    ..Default::default()
+                };
+                self.update_bookmark(bookmark_id, body)
+            }
+            NotesUpdate::Append => {
+                let bookmark = self.get_bookmark(bookmark_id)?;
+                self.update_bookmark_with_merge(
+                    bookmark_id,
+                    &bookmark.date_modified,
+                    appended(&bookmark),
+                    |current, _| Some(appended(current)),
+                )
+            }
+        }
+    }
+}
+
+fn selector(selector: &str) -> Selector {
+    Selector::parse(selector).expect("Could not parse selector")
+}
+
+fn element_text(element: ElementRef) -> String {
+    element.text().collect::<Vec<_>>().join(" ")
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn non_empty(text: String) -> Option<String> {
+    let text = collapse_whitespace(&text);
+    (!text.is_empty()).then_some(text)
+}
+
+fn meta_content(document: &Html, query: &str) -> Option<String> {
+    document
+        .select(&selector(query))
+        .find_map(|meta| meta.value().attr("content").map(|v| v.to_string()))
+        .and_then(non_empty)
+}
+
+fn title(document: &Html) -> Option<String> {
+    meta_content(document, r#"meta[property="og:title"]"#)
+        .or_else(|| {
+            document
+                .select(&selector("title"))
+                .next()
+                .and_then(|title| non_empty(element_text(title)))
+        })
+        .or_else(|| {
+            document
+                .select(&selector("h1"))
+                .next()
+                .and_then(|h1| non_empty(element_text(h1)))
+        })
+}
+
+fn byline(document: &Html) -> Option<String> {
+    meta_content(document, r#"meta[name="author"]"#)
+        .or_else(|| {
+            meta_content(document, r#"meta[property="article:author"]"#)
+                .filter(|author| !author.starts_with("http"))
+        })
+        .or_else(|| {
+            document
+                .select(&selector(
+                    r#"[rel="author"], [itemprop="author"], .byline, .author"#,
+                ))
+                .find_map(|element| non_empty(element_text(element)))
+                .filter(|byline| byline.chars().count() <= 100)
+        })
+}
+
+/// Length of the text in an element that would end up in the article
+fn content_length(element: ElementRef) -> usize {
+    element
+        .children()
+        .map(|child| match child.value() {
+            Node::Text(text) => text.trim().len(),
+            Node::Element(_) => ElementRef::wrap(child)
+                .filter(|child| !is_skipped(*child))
+                .map(content_length)
+                .unwrap_or_default(),
+            _ => 0,
+        })
+        .sum()
+}
+
+fn is_skipped(element: ElementRef) -> bool {
+    let element = element.value();
+    if SKIPPED_ELEMENTS.contains(&element.name()) || element.attr("hidden").is_some() {
+        return true;
+    }
+    let names = format!(
+        "{} {}",
+        element.attr("class").unwrap_or_default(),
+        element.attr("id").unwrap_or_default()
+    )
+    .to_lowercase();
+    UNLIKELY_CONTENT
+        .iter()
+        .any(|unlikely| names.contains(unlikely))
+}
+
+/// Find the element holding the main content of the page
+///
+/// Prefers semantic `article` and `main` elements, otherwise picks the
+/// container with the most paragraph text directly inside it.
+fn main_content(document: &Html) -> ElementRef<'_> {
+    let semantic = document
+        .select(&selector(r#"article, main, [role="main"]"#))
+        .map(|element| (content_length(element), element))
+        .max_by_key(|(length, _)| *length);
+    if let Some((length, element)) = semantic {
+        if length > 0 {
+            return element;
+        }
+    }
+
+    let paragraph = selector("p");
+    document
+        .select(&selector("div, section, td"))
+        .filter(|element| !is_skipped(*element))
+        .map(|element| {
+            let length: usize = element
+                .children()
+                .filter_map(ElementRef::wrap)
+                .filter(|child| paragraph.matches(child))
+                .map(content_length)
+                .sum();
+            (length, element)
+        })
+        .filter(|(length, _)| *length > 0)
+        .max_by_key(|(length, _)| *length)
+        .map(|(_, element)| element)
+        .or_else(|| document.select(&selector("body")).next())
+        .unwrap_or_else(|| document.root_element())
+}
+
+/// Blocks of rendered text, either Markdown or plain
+#[derive(Default)]
+struct Output {
+    blocks: Vec<String>,
+    current: String,
+}
+
+impl Output {
+    fn push_text(&mut self, text: &str) {
+        let starts_with_space = text.starts_with(char::is_whitespace);
+        let ends_with_space = text.ends_with(char::is_whitespace);
+        let text = collapse_whitespace(text);
+        if (starts_with_space || text.is_empty())
+            && !self.current.is_empty()
+            && !self.current.ends_with([' ', '\n'])
+        {
+            self.current.push(' ');
+        }
+        self.current.push_str(&text);
+        if ends_with_space && !text.is_empty() {
+            self.current.push(' ');
+        }
+    }
+
+    fn push_raw(&mut self, text: &str) {
+        self.current.push_str(text);
+    }
+
+    fn end_block(&mut self) {
+        let block = self.current.trim().to_string();
+        self.current.clear();
+        if !block.is_empty() {
+            self.blocks.push(block);
+        }
+    }
+
+    fn push_block(&mut self, block: String) {
+        self.end_block();
+        if !block.trim().is_empty() {
+            self.blocks.push(block);
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.end_block();
+        self.blocks.join("\n\n")
+    }
+}
+
+fn render_children(element: ElementRef, out: &mut Output, markdown: bool) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => out.push_text(text),
+            Node::Element(_) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    render(child, out, markdown);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Render an element into a separate output, e.g. for list items
+fn render_nested(element: ElementRef, markdown: bool) -> String {
+    let mut nested = Output::default();
+    render_children(element, &mut nested, markdown);
+    nested.end_block();
+    nested.blocks.join("\n")
+}
+
+fn render(element: ElementRef, out: &mut Output, markdown: bool) {
+    if !is_skipped(element) {
+        render_element(element, out, markdown);
+    }
+}
+
+fn render_element(element: ElementRef, out: &mut Output, markdown: bool) {
+    let name = element.value().name();
+    match name {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            out.end_block();
+            if markdown {
+                let level = name[1..].parse().unwrap_or(1);
+                out.push_raw(&format!("{} ", "#".repeat(level)));
+            }
+            render_children(element, out, markdown);
+            out.end_block();
+        }
+        "p" | "div" | "section" | "article" | "main" | "header" | "figure" | "figcaption"
+        | "table" | "tr" | "dl" | "dt" | "dd" => {
+            out.end_block();
+            render_children(element, out, markdown);
+            out.end_block();
+        }
+        "ul" | "ol" => {
+            let items: Vec<String> = element
+                .children()
+                .filter_map(ElementRef::wrap)
+                .filter(|child| child.value().name() == "li")
+                .enumerate()
+                .map(|(index, item)| {
+                    let marker = match name {
+                        "ol" => format!("{}. ", index + 1),
+                        _ => "- ".to_string(),
+                    };
+                    let content = render_nested(item, markdown);
+                    format!("{}{}", marker, content.replace('\n', "\n   "))
+                })
+                .collect();
+            out.push_block(items.join("\n"));
+        }
+        "blockquote" => {
+            let content = render_nested(element, markdown);
+            let content = match markdown {
+                true => content
+                    .lines()
+                    .map(|line| format!("> {}", line))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                false => content,
+            };
+            out.push_block(content);
+        }
+        "pre" => {
+            let code: String = element.text().collect();
+            let code = code.trim_matches('\n');
+            match markdown {
+                true => out.push_block(format!("```\n{}\n```", code)),
+                false => out.push_block(code.to_string()),
+            }
+        }
+        "br" => out.push_raw("\n"),
+        "hr" if markdown => out.push_block("---".to_string()),
+        "img" if markdown => {
+            let src = element.value().attr("src").unwrap_or_default();
+            let alt = element.value().attr("alt").unwrap_or_default();
+            // single-file snapshots inline images as data URLs, which are too large for notes
+            if !src.is_empty() && !src.starts_with("data:") {
+                out.push_raw(&format!("![{}]({})", alt.trim(), src));
+            }
+        }
+        "a" if markdown => {
+            let href = element.value().attr("href").unwrap_or_default();
+            let text = render_nested(element, markdown);
+            if href.starts_with("http") && !text.is_empty() {
+                out.push_text(" ");
+                out.push_raw(&format!("[{}]({})", text, href));
+            } else {
+                out.push_text(&text);
+            }
+        }
+        "strong" | "b" | "em" | "i" | "code" if markdown => {
+            let marker = match name {
+                "strong" | "b" => "**",
+                "code" => "`",
+                _ => "*",
+            };
+            let text = render_nested(element, markdown);
+            if !text.is_empty() {
+                out.push_text(" ");
+                out.push_raw(&format!("{}{}{}", marker, text, marker));
+            }
+        }
+        _ => render_children(element, out, markdown),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HTML: &str = r#"<html>
+        <head>
+            <title>Fallback title</title>
+            <meta property="og:title" content="Understanding Ownership">
+            <meta name="author" content="Jane Doe">
+            <style>p { margin: 0; }</style>
+        </head>
+        <body>
+            <nav><a href="/">Home</a> <a href="/blog">Blog</a></nav>
+            <article>
+                <h1>Understanding Ownership</h1>
+                <p>Ownership is <em>the</em> most unique feature of
+                <a href="https://www.rust-lang.org">Rust</a>.</p>
+                <ul><li>Each value has an owner.</li><li>There can only be one owner.</li></ul>
+                <pre>let s = String::from("hello");</pre>
+                <div class="share-buttons">Share on social media</div>
+            </article>
+            <div id="comments"><p>First!</p></div>
+            <footer>Copyright</footer>
+        </body>
+    </html>"#;
+
+    #[test]
+    fn extracts_metadata() {
+        let article = extract_article(HTML.as_bytes()).unwrap();
+        assert_eq!(article.title, Some("Understanding Ownership".to_string()));
+        assert_eq!(article.byline, Some("Jane Doe".to_string()));
+        assert_eq!(article.reading_time_minutes, 1);
+    }
+
+    #[test]
+    fn extracts_main_content_as_text() {
+        let article = extract_article(HTML.as_bytes()).unwrap();
+        assert_eq!(
+            article.text,
+            "Understanding Ownership\n\n\
+             Ownership is the most unique feature of Rust.\n\n\
+             - Each value has an owner.\n- There can only be one owner.\n\n\
+             let s = String::from(\"hello\");"
+        );
+        assert_eq!(article.word_count, 24);
+    }
+
+    #[test]
+    fn extracts_main_content_as_markdown() {
+        let article = extract_article(HTML.as_bytes()).unwrap();
+        assert_eq!(
+            article.markdown,
+            "# Understanding Ownership\n\n\
+             Ownership is *the* most unique feature of [Rust](https://www.rust-lang.org).\n\n\
+             - Each value has an owner.\n- There can only be one owner.\n\n\
+             ```\nlet s = String::from(\"hello\");\n```"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_paragraph_density() {
+        let html = r#"<body>
+            <div class="menu"><p>Menu</p></div>
+            <div id="content"><p>First paragraph.</p><p>Second paragraph.</p></div>
+        </body>"#;
+        let article = extract_article(html.as_bytes()).unwrap();
+        assert_eq!(article.text, "First paragraph.\n\nSecond paragraph.");
+        assert_eq!(article.title, None);
+    }
+
+    #[test]
+    fn never_skips_the_root() {
+        let html = r#"<body class="has-sidebar">
+            <article class="post related-links">
+                <p>The article.</p>
+                <aside class="sidebar"><p>Elsewhere</p></aside>
+            </article>
+        </body>"#;
+        let article = extract_article(html.as_bytes()).unwrap();
+        assert_eq!(article.text, "The article.");
+
+        let html = r#"<body class="has-sidebar"><p>Only text.</p></body>"#;
+        assert_eq!(extract_article(html.as_bytes()).unwrap().text, "Only text.");
+    }
+}
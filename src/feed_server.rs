@@ -0,0 +1,161 @@
+use std::{io, net::SocketAddr};
+
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::{FeedFormat, FeedOptions, LinkDingClient, LinkDingError};
+
+/// A search query published as a feed
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SavedFeed {
+    /// Used in the feed's URL, so it should be URL safe
+    pub name: String,
+    pub query: String,
+    pub options: FeedOptions,
+}
+
+impl SavedFeed {
+    pub fn new(name: &str, query: &str) -> SavedFeed {
+        SavedFeed {
+            name: name.to_string(),
+            query: query.to_string(),
+            options: FeedOptions::default(),
+        }
+    }
+}
+
+/// A small HTTP server publishing saved searches as Atom and RSS feeds
+///
+/// Feeds are served at `/feeds/<name>.atom` and `/feeds/<name>.rss` and are
+/// only returned when the URL carries the server's token as `token` query
+/// parameter, see [`FeedServer::feed_path`]. Use a separate token, not the
+/// linkding API token, as feed readers store the URLs in plain text.
+pub struct FeedServer<'a> {
+    client: &'a LinkDingClient,
+    feeds: Vec<SavedFeed>,
+    token: String,
+    server: Server,
+}
+
+impl<'a> FeedServer<'a> {
+    /// Listen on `address`, e.g. `127.0.0.1:8080`
+    ///
+    /// Fails for an empty `token`, which would publish the feeds to anyone.
+    pub fn bind(
+        client: &'a LinkDingClient,
+        address: &str,
+        token: &str,
+        feeds: Vec<SavedFeed>,
+    ) -> Result<FeedServer<'a>, LinkDingError> {
+        if token.is_empty() {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidInput, "the feed token is empty").into(),
+            );
+        }
+        let server = Server::http(address).map_err(io::Error::other)?;
+        Ok(FeedServer {
+            client,
+            feeds,
+            token: token.to_string(),
+            server,
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Path and query of a feed's URL, including the token
+    pub fn feed_path(&self, name: &str, format: FeedFormat) -> String {
+        let query: String = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("token", &self.token)
+            .finish();
+        format!("/feeds/{}.{}?{}", name, format.extension(), query)
+    }
+
+    /// Answer requests until the process ends
+    pub fn serve(&self) {
+        for request in self.server.incoming_requests() {
+            let (status, content_type, body) = self.respond(request.method(), request.url());
+            let content_type = Header::from_bytes("Content-Type", content_type)
+                .expect("Content type is a valid header");
+            let response = Response::from_string(body)
+                .with_status_code(status)
+                .with_header(content_type);
+            // a feed reader hanging up early is no reason to stop serving
+            let _ = request.respond(response);
+        }
+    }
+
+    /// Status, content type and body answering a request for `url`
+    fn respond(&self, method: &Method, url: &str) -> (u16, &'static str, String) {
+        const TEXT: &str = "text/plain; charset=utf-8";
+        if *method != Method::Get {
+            return (405, TEXT, "Method not allowed".to_string());
+        }
+        let Ok(url) = url::Url::parse("http://localhost/").and_then(|base| base.join(url)) else {
+            return (400, TEXT, "Bad request".to_string());
+        };
+        let token = url
+            .query_pairs()
+            .find(|(key, _)| key == "token")
+            .map(|(_, value)| value.into_owned())
+            .unwrap_or_default();
+        if !tokens_match(&token, &self.token) {
+            return (403, TEXT, "Forbidden".to_string());
+        }
+        let feed = url.path().strip_prefix("/feeds/").and_then(|file| {
+            let (name, extension) = file.rsplit_once('.')?;
+            let format = match extension {
+                "atom" => FeedFormat::Atom,
+                "rss" => FeedFormat::Rss,
+                _ => return None,
+            };
+            let feed = self.feeds.iter().find(|feed| feed.name == name)?;
+            Some((feed, format))
+        });
+        let Some((feed, format)) = feed else {
+            return (404, TEXT, "Not found".to_string());
+        };
+        match self.client.bookmark_feed(&feed.query, &feed.options) {
+            Ok(rendered) => (200, format.content_type(), rendered.render(format)),
+            Err(error) => (502, TEXT, format!("Could not fetch bookmarks: {}", error)),
+        }
+    }
+}
+
+/// Compare tokens without returning early on the first difference
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_the_token_and_feed_name() {
+        let client = LinkDingClient::new("http://127.0.0.1:9", "token");
+        let feeds = vec![SavedFeed::new("team", "#team-reading")];
+        assert!(FeedServer::bind(&client, "127.0.0.1:0", "", feeds.clone()).is_err());
+        let server = FeedServer::bind(&client, "127.0.0.1:0", "s3cret&", feeds).unwrap();
+        assert!(server.local_addr().is_some());
+
+        let path = server.feed_path("team", FeedFormat::Rss);
+        assert_eq!(path, "/feeds/team.rss?token=s3cret%26");
+        let status = |method: Method, url: &str| server.respond(&method, url).0;
+        assert_eq!(status(Method::Get, "/feeds/team.rss"), 403);
+        assert_eq!(status(Method::Get, "/feeds/team.rss?token=s3cret"), 403);
+        assert_eq!(status(Method::Post, &path), 405);
+        assert_eq!(
+            status(Method::Get, &server.feed_path("other", FeedFormat::Atom)),
+            404
+        );
+        // the linkding instance isn't reachable
+        assert_eq!(status(Method::Get, &path), 502);
+    }
+}
@@ -0,0 +1,328 @@
+use std::fmt::Write;
+
+use crate::{
+    timestamp::UtcDateTime, xml::escape, Bookmark, LinkDingClient, LinkDingError, ListBookmarksArgs,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeedFormat {
+    Atom,
+    /// RSS 2.0
+    Rss,
+}
+
+impl FeedFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "atom",
+            FeedFormat::Rss => "rss",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FeedOptions {
+    /// Title of the feed, named after the query if not set
+    pub title: Option<String>,
+    /// How many of the newest bookmarks the feed lists
+    pub limit: i32,
+}
+
+impl Default for FeedOptions {
+    fn default() -> Self {
+        FeedOptions {
+            title: None,
+            limit: 50,
+        }
+    }
+}
+
+/// A feed of bookmarks, rendered as Atom or RSS
+///
+/// Each entry links to the bookmarked page, with the description as summary,
+/// the notes as content, the tags as categories and the date the bookmark was
+/// added as publication date.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Feed {
+    pub title: String,
+    /// Link to the feed's website, usually the linkding instance, also the
+    /// base of the entry IDs
+    pub link: String,
+    /// Unique ID of the feed
+    pub id: String,
+    /// Newest first
+    pub bookmarks: Vec<Bookmark>,
+}
+
+impl Feed {
+    pub fn render(&self, format: FeedFormat) -> String {
+        match format {
+            FeedFormat::Atom => self.to_atom(),
+            FeedFormat::Rss => self.to_rss(),
+        }
+    }
+
+    pub fn to_atom(&self) -> String {
+        let updated = self
+            .bookmarks
+            .iter()
+            .filter_map(|bookmark| UtcDateTime::parse_rfc3339(&bookmark.date_modified))
+            .max()
+            .unwrap_or_else(UtcDateTime::now);
+        let mut atom = String::new();
+        atom.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        atom.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+        let _ = writeln!(atom, "  <title>{}</title>", escape(&self.title));
+        let _ = writeln!(atom, "  <link href=\"{}\"/>", escape(&self.link));
+        let _ = writeln!(atom, "  <id>{}</id>", escape(&self.id));
+        let _ = writeln!(atom, "  <updated>{}</updated>", updated.to_rfc3339());
+        atom.push_str("  <author><name>linkding</name></author>\n");
+        for bookmark in &self.bookmarks {
+            atom.push_str("  <entry>\n");
+            let _ = writeln!(atom, "    <title>{}</title>", escape(entry_title(bookmark)));
+            let _ = writeln!(atom, "    <link href=\"{}\"/>", escape(&bookmark.url));
+            let _ = writeln!(atom, "    <id>{}</id>", escape(&self.entry_id(bookmark)));
+            let _ = writeln!(
+                atom,
+                "    <published>{}</published>",
+                rfc3339(&bookmark.date_added)
+            );
+            let _ = writeln!(
+                atom,
+                "    <updated>{}</updated>",
+                rfc3339(&bookmark.date_modified)
+            );
+            if let Some(description) = entry_description(bookmark) {
+                let _ = writeln!(atom, "    <summary>{}</summary>", escape(description));
+            }
+            if !bookmark.notes.is_empty() {
+                let _ = writeln!(
+                    atom,
+                    "    <content type=\"text\">{}</content>",
+                    escape(&bookmark.notes)
+                );
+            }
+            for tag in &bookmark.tag_names {
+                let _ = writeln!(atom, "    <category term=\"{}\"/>", escape(tag));
+            }
+            atom.push_str("  </entry>\n");
+        }
+        atom.push_str("</feed>\n");
+        atom
+    }
+
+    pub fn to_rss(&self) -> String {
+        let mut rss = String::new();
+        rss.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        rss.push_str(
+            "<rss version=\"2.0\" xmlns:content=\"http://purl.org/rss/1.0/modules/content/\">\n",
+        );
+        rss.push_str("  <channel>\n");
+        let _ = writeln!(rss, "    <title>{}</title>", escape(&self.title));
+        let _ = writeln!(rss, "    <link>{}</link>", escape(&self.link));
+        let _ = writeln!(
+            rss,
+            "    <description>{}</description>",
+            escape(&self.title)
+        );
+        if let Some(newest) = self.bookmarks.first() {
+            let _ = writeln!(
+                rss,
+                "    <lastBuildDate>{}</lastBuildDate>",
+                rfc2822(&newest.date_added)
+            );
+        }
+        for bookmark in &self.bookmarks {
+            rss.push_str("    <item>\n");
+            let _ = writeln!(
+                rss,
+                "      <title>{}</title>",
+                escape(entry_title(bookmark))
+            );
+            let _ = writeln!(rss, "      <link>{}</link>", escape(&bookmark.url));
+            let _ = writeln!(
+                rss,
+                "      <guid isPermaLink=\"false\">{}</guid>",
+                escape(&self.entry_id(bookmark))
+            );
+            let _ = writeln!(
+                rss,
+                "      <pubDate>{}</pubDate>",
+                rfc2822(&bookmark.date_added)
+            );
+            if let Some(description) = entry_description(bookmark) {
+                let _ = writeln!(
+                    rss,
+                    "      <description>{}</description>",
+                    escape(description)
+                );
+            }
+            if !bookmark.notes.is_empty() {
+                let _ = writeln!(
+                    rss,
+                    "      <content:encoded>{}</content:encoded>",
+                    escape(&bookmark.notes)
+                );
+            }
+            for tag in &bookmark.tag_names {
+                let _ = writeln!(rss, "      <category>{}</category>", escape(tag));
+            }
+            rss.push_str("    </item>\n");
+        }
+        rss.push_str("  </channel>\n</rss>\n");
+        rss
+    }
+
+    fn entry_id(&self, bookmark: &Bookmark) -> String {
+        format!(
+            "{}/bookmarks/{}",
+            self.link.trim_end_matches('/'),
+            bookmark.id
+        )
+    }
+}
+
+/// The bookmark's title, falling back to the website's title and the URL
+fn entry_title(bookmark: &Bookmark) -> &str {
+    [bookmark.title.as_str()]
+        .into_iter()
+        .chain(bookmark.website_title.as_deref())
+        .find(|title| !title.is_empty())
+        .unwrap_or(&bookmark.url)
+}
+
+fn entry_description(bookmark: &Bookmark) -> Option<&str> {
+    [bookmark.description.as_str()]
+        .into_iter()
+        .chain(bookmark.website_description.as_deref())
+        .find(|description| !description.is_empty())
+}
+
+/// Normalize a linkding date to UTC, keeping it as is if it can't be parsed
+fn rfc3339(date: &str) -> String {
+    UtcDateTime::parse_rfc3339(date).map_or_else(|| date.to_string(), |d| d.to_rfc3339())
+}
+
+fn rfc2822(date: &str) -> String {
+    UtcDateTime::parse_rfc3339(date).map_or_else(|| date.to_string(), |d| d.to_rfc2822())
+}
+
+/// Feeds of bookmark searches
+impl LinkDingClient {
+    /// Build a feed of the newest unarchived bookmarks matching a search query
+    ///
+    /// The feed's ID is made from the instance URL and the query, so feeds of
+    /// different queries never share one.
+    pub fn bookmark_feed(&self, query: &str, options: &FeedOptions) -> Result<Feed, LinkDingError> {
+        let args = ListBookmarksArgs {
+            query: Some(query.to_string()),
+            limit: Some(options.limit),
+            ..Default::default()
+        };
+        let mut bookmarks = self.list_bookmarks(args)?.results;
+        bookmarks.sort_by_cached_key(|bookmark| {
+            std::cmp::Reverse(UtcDateTime::parse_rfc3339(&bookmark.date_added))
+        });
+        let link = self.url.trim_end_matches('/').to_string();
+        let encoded_query: String =
+            url::form_urlencoded::byte_serialize(query.as_bytes()).collect();
+        Ok(Feed {
+            title: options
+                .title
+                .clone()
+                .unwrap_or_else(|| format!("linkding: {}", query)),
+            id: format!("{}/feeds/{}", link, encoded_query),
+            link,
+            bookmarks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, page, serve_api, tags, Route};
+
+    fn feed() -> Feed {
+        let bookmark = Bookmark {
+            url: "https://example.com/?a=1&b=2".to_string(),
+            description: "Tips & tricks".to_string(),
+            notes: "Read <soon>".to_string(),
+            tag_names: tags(&["team-reading", "rust"]),
+            date_added: "2024-02-29T13:34:56.123456+01:00".to_string(),
+            date_modified: "2024-03-01T00:00:00Z".to_string(),
+            website_title: Some("Example".to_string()),
+            ..test_util::bookmark(7)
+        };
+        Feed {
+            title: "linkding: #team-reading".to_string(),
+            link: "https://links.example.org".to_string(),
+            id: "https://links.example.org/feeds/%23team-reading".to_string(),
+            bookmarks: vec![bookmark],
+        }
+    }
+
+    #[test]
+    fn renders_atom() {
+        let atom = feed().to_atom();
+        for expected in [
+            "<title>linkding: #team-reading</title>",
+            "<updated>2024-03-01T00:00:00Z</updated>",
+            "<title>Example</title>",
+            "<link href=\"https://example.com/?a=1&amp;b=2\"/>",
+            "  <id>https://links.example.org/feeds/%23team-reading</id>",
+            "<id>https://links.example.org/bookmarks/7</id>",
+            "<published>2024-02-29T12:34:56Z</published>",
+            "<summary>Tips &amp; tricks</summary>",
+            "<content type=\"text\">Read &lt;soon&gt;</content>",
+            "<category term=\"team-reading\"/>",
+            "<category term=\"rust\"/>",
+        ] {
+            assert!(
+                atom.contains(expected),
+                "{} missing from\n{}",
+                expected,
+                atom
+            );
+        }
+    }
+
+    #[test]
+    fn renders_rss() {
+        let rss = feed().to_rss();
+        for expected in [
+            "<link>https://links.example.org</link>",
+            "<link>https://example.com/?a=1&amp;b=2</link>",
+            "<guid isPermaLink=\"false\">https://links.example.org/bookmarks/7</guid>",
+            "<pubDate>Thu, 29 Feb 2024 12:34:56 +0000</pubDate>",
+            "<description>Tips &amp; tricks</description>",
+            "<content:encoded>Read &lt;soon&gt;</content:encoded>",
+            "<category>rust</category>",
+        ] {
+            assert!(rss.contains(expected), "{} missing from\n{}", expected, rss);
+        }
+    }
+
+    #[test]
+    fn feeds_of_different_queries_have_different_ids() {
+        let (url, _) = serve_api(vec![Route::ok(
+            "GET /api/bookmarks/",
+            page::<Bookmark>(&[]),
+        )]);
+        let client = LinkDingClient::new(&url, "token");
+        let feed = |query| {
+            client
+                .bookmark_feed(query, &FeedOptions::default())
+                .unwrap()
+        };
+        assert_eq!(feed("#rust").id, format!("{}/feeds/%23rust", url));
+        assert_ne!(feed("#rust").id, feed("#go").id);
+    }
+}
@@ -0,0 +1,722 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bookmark_assets::{BookmarkAssetStatus, BookmarkAssetType},
+    tag_changes::same_tags,
+    timestamp::UtcDateTime,
+    Bookmark, LinkDingClient, LinkDingError, ListBookmarksArgs, UpdateBookmarkBody,
+};
+
+/// One of the two instances taking part in a sync
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Side {
+    A,
+    B,
+}
+
+impl Side {
+    pub fn other(&self) -> Side {
+        match self {
+            Side::A => Side::B,
+            Side::B => Side::A,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SyncDirection {
+    AToB,
+    BToA,
+    #[default]
+    Both,
+}
+
+impl SyncDirection {
+    fn allows(&self, to: Side) -> bool {
+        match self {
+            SyncDirection::AToB => to == Side::B,
+            SyncDirection::BToA => to == Side::A,
+            SyncDirection::Both => true,
+        }
+    }
+}
+
+/// What to do with a bookmark that changed on both sides since the last sync
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ConflictPolicy {
+    PreferA,
+    PreferB,
+    /// Keep the copy that was modified last
+    #[default]
+    NewestWins,
+    /// Leave both copies alone and report the conflict
+    Skip,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InstanceSyncOptions {
+    /// Only sync bookmarks matching this query on either side, e.g. `#team`
+    pub query: Option<String>,
+    pub direction: SyncDirection,
+    pub conflict_policy: ConflictPolicy,
+    /// Also sync archived bookmarks
+    pub include_archived: bool,
+    /// Copy uploaded assets the other side doesn't have yet
+    pub assets: bool,
+}
+
+impl Default for InstanceSyncOptions {
+    fn default() -> Self {
+        InstanceSyncOptions {
+            query: None,
+            direction: SyncDirection::default(),
+            conflict_policy: ConflictPolicy::default(),
+            include_archived: true,
+            assets: false,
+        }
+    }
+}
+
+/// A bookmark pair as of the last sync
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SyncedPair {
+    pub a_id: i32,
+    pub b_id: i32,
+    pub a_date_modified: String,
+    pub b_date_modified: String,
+}
+
+/// State kept between syncs, keyed by normalized URL
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct InstanceSyncState {
+    pub pairs: BTreeMap<String, SyncedPair>,
+}
+
+impl InstanceSyncState {
+    /// Load the state from a file, empty if the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<InstanceSyncState, LinkDingError> {
+        match File::open(path) {
+            Ok(file) => Ok(serde_json::from_reader(io::BufReader::new(file))?),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                Ok(InstanceSyncState::default())
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), LinkDingError> {
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        serde_json::to_writer_pretty(BufWriter::new(File::create(&temp_path)?), self)?;
+        fs::rename(temp_path, path)?;
+        Ok(())
+    }
+}
+
+impl SyncedPair {
+    fn new(a: &Bookmark, b: &Bookmark) -> SyncedPair {
+        SyncedPair {
+            a_id: a.id,
+            b_id: b.id,
+            a_date_modified: a.date_modified.clone(),
+            b_date_modified: b.date_modified.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncAction {
+    /// Create a copy of `source` on the side `to`
+    Create { to: Side, source: Bookmark },
+    /// Bring the copy on the side `to` in line with `source`
+    Update {
+        to: Side,
+        target_id: i32,
+        source: Bookmark,
+        body: Box<UpdateBookmarkBody>,
+        /// The archived state to set, if it differs
+        archive: Option<bool>,
+    },
+    /// Both copies match already
+    Unchanged(SyncedPair),
+    /// Both copies changed and the conflict policy is [`ConflictPolicy::Skip`]
+    Conflict { a_id: i32, b_id: i32 },
+}
+
+/// The actions a sync would take, keyed by normalized URL
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct InstanceSyncPlan {
+    pub actions: Vec<(String, SyncAction)>,
+}
+
+impl InstanceSyncPlan {
+    /// Plan a sync between the bookmarks of both sides
+    ///
+    /// Bookmarks are matched by [`normalize_url`]. A bookmark that was synced
+    /// before and is now missing on one side was deleted there or no longer
+    /// matches the query, it isn't created again. Creates are planned for all
+    /// other bookmarks missing on one side, [`InstanceSync::plan`] turns them
+    /// into updates when the other side has the URL outside of the query.
+    pub fn new(
+        a: Vec<Bookmark>,
+        b: Vec<Bookmark>,
+        state: &InstanceSyncState,
+        options: &InstanceSyncOptions,
+    ) -> InstanceSyncPlan {
+        let a: BTreeMap<String, Bookmark> = a
+            .into_iter()
+            .map(|bookmark| (normalize_url(&bookmark.url), bookmark))
+            .collect();
+        let b: BTreeMap<String, Bookmark> = b
+            .into_iter()
+            .map(|bookmark| (normalize_url(&bookmark.url), bookmark))
+            .collect();
+        let urls: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+
+        let mut actions = Vec::new();
+        for url in urls {
+            let synced = state.pairs.get(url);
+            let action =
+                match (a.get(url), b.get(url)) {
+                    (Some(source), None) if synced.is_none() => options
+                        .direction
+                        .allows(Side::B)
+                        .then(|| SyncAction::Create {
+                            to: Side::B,
+                            source: source.clone(),
+                        }),
+                    (None, Some(source)) if synced.is_none() => options
+                        .direction
+                        .allows(Side::A)
+                        .then(|| SyncAction::Create {
+                            to: Side::A,
+                            source: source.clone(),
+                        }),
+                    (Some(a), Some(b)) => Some(plan_pair(a, b, synced, options)),
+                    _ => None,
+                };
+            if let Some(action) = action {
+                actions.push((url.clone(), action));
+            }
+        }
+        InstanceSyncPlan { actions }
+    }
+
+    /// The actions that change anything
+    pub fn changes(&self) -> impl Iterator<Item = &(String, SyncAction)> {
+        self.actions.iter().filter(|(_, action)| {
+            matches!(
+                action,
+                SyncAction::Create { .. } | SyncAction::Update { .. }
+            )
+        })
+    }
+}
+
+fn plan_pair(
+    a: &Bookmark,
+    b: &Bookmark,
+    synced: Option<&SyncedPair>,
+    options: &InstanceSyncOptions,
+) -> SyncAction {
+    let unchanged = SyncAction::Unchanged(SyncedPair::new(a, b));
+    if sync_update(a, b) == (UpdateBookmarkBody::default(), None) {
+        return unchanged;
+    }
+    let a_changed = synced.is_none_or(|pair| pair.a_date_modified != a.date_modified);
+    let b_changed = synced.is_none_or(|pair| pair.b_date_modified != b.date_modified);
+    let winner = match (a_changed, b_changed) {
+        (true, false) => Side::A,
+        (false, true) => Side::B,
+        _ => match options.direction {
+            SyncDirection::AToB => Side::A,
+            SyncDirection::BToA => Side::B,
+            SyncDirection::Both => match options.conflict_policy {
+                ConflictPolicy::PreferA => Side::A,
+                ConflictPolicy::PreferB => Side::B,
+                ConflictPolicy::NewestWins => {
+                    let a_modified = UtcDateTime::parse_rfc3339(&a.date_modified);
+                    let b_modified = UtcDateTime::parse_rfc3339(&b.date_modified);
+                    if b_modified > a_modified {
+                        Side::B
+                    } else {
+                        Side::A
+                    }
+                }
+                ConflictPolicy::Skip => {
+                    return SyncAction::Conflict {
+                        a_id: a.id,
+                        b_id: b.id,
+                    }
+                }
+            },
+        },
+    };
+    let to = winner.other();
+    if !options.direction.allows(to) {
+        // changes on the receiving side of a one-way sync are left alone
+        return unchanged;
+    }
+    let (source, target) = match winner {
+        Side::A => (a, b),
+        Side::B => (b, a),
+    };
+    let (body, archive) = sync_update(target, source);
+    SyncAction::Update {
+        to,
+        target_id: target.id,
+        source: source.clone(),
+        body: Box::new(body),
+        archive,
+    }
+}
+
+/// The update bringing the synced fields of `target` in line with `source`
+fn sync_update(target: &Bookmark, source: &Bookmark) -> (UpdateBookmarkBody, Option<bool>) {
+    let diff = target.diff(source);
+    let same_tags = same_tags(&target.tag_names, &source.tag_names);
+    let body = UpdateBookmarkBody {
+        title: diff.title,
+        description: diff.description,
+        notes: diff.notes,
+        unread: diff.unread,
+        tag_names: diff.tag_names.filter(|_| !same_tags),
+        ..Default::default()
+    };
+    (body, diff.is_archived)
+}
+
+/// Normalize a URL for matching bookmarks across instances
+///
+/// The scheme and host are lowercased, a leading `www.`, default ports,
+/// fragments, `utm_*` parameters and trailing slashes are dropped, and the
+/// remaining query parameters are sorted. URLs that can't be parsed are only
+/// trimmed.
+pub fn normalize_url(url: &str) -> String {
+    let Ok(mut parsed) = url::Url::parse(url.trim()) else {
+        return url.trim().to_string();
+    };
+    parsed.set_fragment(None);
+    if let Some(host) = parsed.host_str().map(str::to_lowercase) {
+        if let Some(stripped) = host.strip_prefix("www.") {
+            let _ = parsed.set_host(Some(stripped));
+        }
+    }
+    let mut query: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(key, _)| !key.starts_with("utm_"))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    query.sort();
+    if query.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(query);
+    }
+    let path = parsed.path().to_string();
+    if path.len() > 1 && path.ends_with('/') {
+        parsed.set_path(path.trim_end_matches('/'));
+    }
+    parsed.to_string()
+}
+
+#[derive(Debug, Default)]
+pub struct InstanceSyncReport {
+    pub created: Vec<(Side, String)>,
+    pub updated: Vec<(Side, String)>,
+    pub conflicts: Vec<String>,
+    pub assets_copied: usize,
+    pub failed: Vec<(String, LinkDingError)>,
+}
+
+/// Syncs bookmarks between two linkding instances
+pub struct InstanceSync<'a> {
+    a: &'a LinkDingClient,
+    b: &'a LinkDingClient,
+    options: InstanceSyncOptions,
+}
+
+impl<'a> InstanceSync<'a> {
+    pub fn new(a: &'a LinkDingClient, b: &'a LinkDingClient, options: InstanceSyncOptions) -> Self {
+        InstanceSync { a, b, options }
+    }
+
+    fn client(&self, side: Side) -> &'a LinkDingClient {
+        match side {
+            Side::A => self.a,
+            Side::B => self.b,
+        }
+    }
+
+    /// Fetch the bookmarks of both sides and plan the sync without changing anything
+    ///
+    /// Bookmarks to create are looked up on the receiving side first, as one
+    /// with the same URL may exist there without matching the query. The
+    /// two are then synced like a pair that was never synced before.
+    pub fn plan(&self, state: &InstanceSyncState) -> Result<InstanceSyncPlan, LinkDingError> {
+        let a = self.fetch(Side::A)?;
+        let b = self.fetch(Side::B)?;
+        let mut plan = InstanceSyncPlan::new(a, b, state, &self.options);
+        for (_, action) in &mut plan.actions {
+            if let SyncAction::Create { to, source } = action {
+                if let Some(existing) = self.existing(*to, source)? {
+                    *action = self.pair_with_existing(*to, source, &existing);
+                }
+            }
+        }
+        Ok(plan)
+    }
+
+    /// The bookmark with the source's URL on the side `to`, if there is one
+    fn existing(&self, to: Side, source: &Bookmark) -> Result<Option<Bookmark>, LinkDingError> {
+        Ok(self.client(to).check_url(&source.url)?.bookmark)
+    }
+
+    fn pair_with_existing(&self, to: Side, source: &Bookmark, existing: &Bookmark) -> SyncAction {
+        match to {
+            Side::A => plan_pair(existing, source, None, &self.options),
+            Side::B => plan_pair(source, existing, None, &self.options),
+        }
+    }
+
+    /// Carry out a plan, recording the synced pairs in `state`
+    ///
+    /// Failed actions are listed in the report and tried again by the next
+    /// sync.
+    pub fn apply(
+        &self,
+        plan: InstanceSyncPlan,
+        state: &mut InstanceSyncState,
+    ) -> InstanceSyncReport {
+        let mut report = InstanceSyncReport::default();
+        for (url, action) in plan.actions {
+            match self.apply_action(action, &mut report) {
+                Ok(Some(pair)) => _ = state.pairs.insert(url, pair),
+                Ok(None) => report.conflicts.push(url.clone()),
+                Err(error) => report.failed.push((url, error)),
+            }
+        }
+        report
+    }
+
+    /// Plan and apply a sync, keeping its state in the file at `state_path`
+    pub fn run(&self, state_path: &Path) -> Result<InstanceSyncReport, LinkDingError> {
+        let mut state = InstanceSyncState::load(state_path)?;
+        let plan = self.plan(&state)?;
+        let report = self.apply(plan, &mut state);
+        state.save(state_path)?;
+        Ok(report)
+    }
+
+    /// Apply an action, returning the resulting pair unless it is a conflict
+    fn apply_action(
+        &self,
+        action: SyncAction,
+        report: &mut InstanceSyncReport,
+    ) -> Result<Option<SyncedPair>, LinkDingError> {
+        let (to, source, target_id) = match action {
+            SyncAction::Unchanged(pair) => return Ok(Some(pair)),
+            SyncAction::Conflict { .. } => return Ok(None),
+            SyncAction::Create { to, source } => {
+                // creating a bookmark for an existing URL would overwrite it
+                if let Some(existing) = self.existing(to, &source)? {
+                    let action = self.pair_with_existing(to, &source, &existing);
+                    return self.apply_action(action, report);
+                }
+                let target = self.client(to).create_bookmark(source.to_create_body())?;
+                report.created.push((to, source.url.clone()));
+                (to, source, target.id)
+            }
+            SyncAction::Update {
+                to,
+                target_id,
+                source,
+                body,
+                archive,
+            } => {
+                let client = self.client(to);
+                if !body.is_empty() {
+                    client.update_bookmark(target_id, *body)?;
+                }
+                match archive {
+                    Some(true) => _ = client.archive_bookmark(target_id)?,
+                    Some(false) => _ = client.unarchive_bookmark(target_id)?,
+                    None => {}
+                }
+                report.updated.push((to, source.url.clone()));
+                (to, source, target_id)
+            }
+        };
+        if self.options.assets {
+            report.assets_copied += self.copy_assets(to.other(), source.id, to, target_id)?;
+        }
+        // archiving and uploads change the modification date as well
+        let target = self.client(to).get_bookmark(target_id)?;
+        Ok(Some(match to {
+            Side::A => SyncedPair::new(&target, &source),
+            Side::B => SyncedPair::new(&source, &target),
+        }))
+    }
+
+    /// Copy uploaded assets the target doesn't have, matched by name
+    fn copy_assets(
+        &self,
+        from: Side,
+        from_id: i32,
+        to: Side,
+        to_id: i32,
+    ) -> Result<usize, LinkDingError> {
+        let is_upload = |asset: &crate::bookmark_assets::BookmarkAsset| {
+            asset.asset_type == BookmarkAssetType::Upload
+                && asset.status == BookmarkAssetStatus::Complete
+        };
+        let existing: HashSet<String> = self
+            .client(to)
+            .list_bookmark_assets(to_id)?
+            .results
+            .into_iter()
+            .filter(is_upload)
+            .map(|asset| asset.display_name)
+            .collect();
+        let mut copied = 0;
+        for asset in self.client(from).list_bookmark_assets(from_id)?.results {
+            if !is_upload(&asset) || existing.contains(&asset.display_name) {
+                continue;
+            }
+            let reader = self
+                .client(from)
+                .download_bookmark_asset_reader(from_id, asset.id)?;
+            self.client(to).upload_bookmark_asset_from_reader(
+                to_id,
+                reader,
+                &asset.display_name,
+                Some(&asset.content_type),
+            )?;
+            copied += 1;
+        }
+        Ok(copied)
+    }
+
+    fn fetch(&self, side: Side) -> Result<Vec<Bookmark>, LinkDingError> {
+        let client = self.client(side);
+        let args = ListBookmarksArgs {
+            query: self.options.query.clone(),
+            ..Default::default()
+        };
+        let mut bookmarks: Box<dyn Iterator<Item = _>> =
+            Box::new(client.iter_bookmarks(args.clone()));
+        if self.options.include_archived {
+            bookmarks = Box::new(bookmarks.chain(client.iter_archived_bookmarks(args)));
+        }
+        bookmarks.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, page, serve_api, tags, Route};
+
+    fn bookmark(id: i32, url: &str, date_modified: &str) -> Bookmark {
+        Bookmark {
+            url: url.to_string(),
+            title: "Title".to_string(),
+            tag_names: tags(&["team", "rust"]),
+            date_modified: date_modified.to_string(),
+            ..test_util::bookmark(id)
+        }
+    }
+
+    fn state(a: &Bookmark, b: &Bookmark) -> InstanceSyncState {
+        let mut state = InstanceSyncState::default();
+        state
+            .pairs
+            .insert(normalize_url(&a.url), SyncedPair::new(a, b));
+        state
+    }
+
+    #[test]
+    fn normalizes_urls() {
+        assert_eq!(
+            normalize_url("HTTPS://www.Example.com:443/path/?b=2&utm_source=x&a=1#top"),
+            "https://example.com/path?a=1&b=2"
+        );
+        assert_eq!(
+            normalize_url("https://example.com/path/"),
+            "https://example.com/path"
+        );
+        assert_eq!(normalize_url("https://example.com"), "https://example.com/");
+        assert_eq!(normalize_url(" not a url "), "not a url");
+    }
+
+    #[test]
+    fn plans_creates_for_new_bookmarks() {
+        let a = bookmark(1, "https://example.com/a", "2025-01-01T00:00:00Z");
+        let b = bookmark(2, "https://www.example.com/a/", "2025-01-01T00:00:00Z");
+        let only_b = bookmark(3, "https://example.com/b", "2025-01-01T00:00:00Z");
+        let options = InstanceSyncOptions::default();
+        let plan = InstanceSyncPlan::new(
+            vec![a.clone()],
+            vec![b.clone(), only_b.clone()],
+            &InstanceSyncState::default(),
+            &options,
+        );
+        assert_eq!(
+            plan.actions,
+            vec![
+                (
+                    "https://example.com/a".to_string(),
+                    SyncAction::Unchanged(SyncedPair::new(&a, &b))
+                ),
+                (
+                    "https://example.com/b".to_string(),
+                    SyncAction::Create {
+                        to: Side::A,
+                        source: only_b.clone()
+                    }
+                ),
+            ]
+        );
+
+        let one_way = InstanceSyncOptions {
+            direction: SyncDirection::AToB,
+            ..Default::default()
+        };
+        let plan = InstanceSyncPlan::new(
+            vec![],
+            vec![only_b.clone()],
+            &InstanceSyncState::default(),
+            &one_way,
+        );
+        assert!(plan.actions.is_empty());
+
+        // synced before and gone from A since, so it isn't created again
+        let synced = state(&only_b, &only_b);
+        let plan = InstanceSyncPlan::new(vec![], vec![only_b], &synced, &options);
+        assert!(plan.actions.is_empty());
+    }
+
+    #[test]
+    fn propagates_changes_from_the_changed_side() {
+        let a = bookmark(1, "https://example.com", "2025-01-01T00:00:00Z");
+        let b = bookmark(2, "https://example.com", "2025-01-01T00:00:00Z");
+        let synced = state(&a, &b);
+        let mut changed = b.clone();
+        changed.notes = "Read this".to_string();
+        changed.is_archived = true;
+        changed.tag_names = vec!["rust".to_string(), "team".to_string(), "new".to_string()];
+        changed.date_modified = "2025-02-01T00:00:00Z".to_string();
+
+        let plan = InstanceSyncPlan::new(
+            vec![a.clone()],
+            vec![changed.clone()],
+            &synced,
+            &InstanceSyncOptions::default(),
+        );
+        let (_, action) = &plan.actions[0];
+        let SyncAction::Update {
+            to, body, archive, ..
+        } = action
+        else {
+            panic!("expected an update, got {:?}", action);
+        };
+        assert_eq!(*to, Side::A);
+        assert_eq!(body.notes.as_deref(), Some("Read this"));
+        assert_eq!(body.tag_names.as_ref().map(Vec::len), Some(3));
+        assert_eq!(body.title, None);
+        assert_eq!(*archive, Some(true));
+
+        // only reordered tags aren't a change
+        let mut reordered = b.clone();
+        reordered.tag_names.reverse();
+        let plan = InstanceSyncPlan::new(
+            vec![a],
+            vec![reordered],
+            &synced,
+            &InstanceSyncOptions::default(),
+        );
+        assert_eq!(plan.changes().count(), 0);
+    }
+
+    #[test]
+    fn resolves_conflicts_with_the_policy() {
+        let mut a = bookmark(1, "https://example.com", "2025-03-01T00:00:00Z");
+        a.title = "Changed on A".to_string();
+        let mut b = bookmark(2, "https://example.com", "2025-02-01T00:00:00+00:00");
+        b.title = "Changed on B".to_string();
+        let synced = state(
+            &bookmark(1, "https://example.com", "2025-01-01T00:00:00Z"),
+            &bookmark(2, "https://example.com", "2025-01-01T00:00:00Z"),
+        );
+
+        let target = |policy| {
+            let options = InstanceSyncOptions {
+                conflict_policy: policy,
+                ..Default::default()
+            };
+            let plan = InstanceSyncPlan::new(vec![a.clone()], vec![b.clone()], &synced, &options);
+            match &plan.actions[0].1 {
+                SyncAction::Update { to, .. } => Some(*to),
+                _ => None,
+            }
+        };
+        assert_eq!(target(ConflictPolicy::NewestWins), Some(Side::B));
+        assert_eq!(target(ConflictPolicy::PreferB), Some(Side::A));
+        assert_eq!(target(ConflictPolicy::Skip), None);
+    }
+
+    #[test]
+    fn plans_updates_for_existing_bookmarks_outside_the_query() {
+        let source = bookmark(1, "https://example.com", "2025-03-01T00:00:00Z");
+        let mut existing = bookmark(2, "https://example.com", "2025-01-01T00:00:00Z");
+        existing.title = "Old title".to_string();
+        let check = serde_json::json!({
+            "bookmark": existing,
+            "metadata": {
+                "url": "https://example.com",
+                "title": null,
+                "description": null,
+                "preview_image": null
+            },
+            "auto_tags": []
+        });
+        let (a_url, _) = serve_api(vec![
+            Route::ok("GET /api/bookmarks/", page(&[source])),
+            Route::ok("GET /api/bookmarks/archived/", page::<Bookmark>(&[])),
+        ]);
+        let (b_url, _) = serve_api(vec![
+            Route::ok("GET /api/bookmarks/", page::<Bookmark>(&[])),
+            Route::ok("GET /api/bookmarks/archived/", page::<Bookmark>(&[])),
+            Route::ok("GET /api/bookmarks/check/", check.to_string()),
+        ]);
+        let (a, b) = (
+            LinkDingClient::new(&a_url, "token"),
+            LinkDingClient::new(&b_url, "token"),
+        );
+        let sync = InstanceSync::new(&a, &b, InstanceSyncOptions::default());
+
+        let plan = sync.plan(&InstanceSyncState::default()).unwrap();
+        match &plan.actions[..] {
+            [(
+                _,
+                SyncAction::Update {
+                    to,
+                    target_id,
+                    body,
+                    ..
+                },
+            )] => {
+                assert_eq!((*to, *target_id), (Side::B, 2));
+                assert_eq!(body.title.as_deref(), Some("Title"));
+            }
+            actions => panic!("unexpected plan {:?}", actions),
+        }
+    }
+}
@@ -1,24 +1,102 @@
 #[cfg(feature = "ffi")]
 uniffi::setup_scaffolding!();
 
+pub mod asset_cleanup;
+pub mod asset_mirror;
+pub mod asset_transfer;
+pub mod asset_usage;
+mod backoff;
 pub mod bookmark_assets;
 pub mod bookmarks;
+pub mod bulk;
+#[cfg(feature = "declarative")]
+pub mod declarative;
+#[cfg(feature = "epub")]
+pub mod epub;
+#[cfg(feature = "extract")]
+pub mod extract;
+#[cfg(feature = "feed-server")]
+pub mod feed_server;
+pub mod feeds;
+pub mod instance_sync;
+#[cfg(feature = "mirror")]
+pub mod mirror;
+#[cfg(feature = "offline")]
+pub mod offline;
+#[cfg(feature = "search")]
+pub mod search;
+pub mod search_query;
+#[cfg(feature = "extract")]
+mod snapshot_text;
+pub mod tag_changes;
 pub mod tags;
+#[cfg(test)]
+mod test_util;
+mod timestamp;
 pub mod users;
-
-use bookmark_assets::{BookmarkAsset, ListBookmarkAssetsResponse};
+pub mod vault;
+pub mod watcher;
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
+mod xml;
+
+pub use asset_cleanup::{
+    AssetCleanupCandidate, AssetCleanupOptions, AssetCleanupPlan, AssetFilter, AssetIter,
+    CleanupReason,
+};
+pub use asset_mirror::{AssetManifest, AssetMirrorOptions, AssetMirrorReport, ManifestEntry};
+pub use asset_transfer::{verify_sha256, AssetDownload, AssetReader, ResumableDownload};
+pub use asset_usage::{AssetUsageEntry, AssetUsageOptions, AssetUsageReport, UsageTotals};
+use backoff::Backoff;
+use bookmark_assets::{
+    BookmarkAsset, BookmarkAssetStatus, BookmarkAssetType, ListBookmarkAssetsResponse,
+};
 pub use bookmarks::{
-    Bookmark, CheckUrlResponse, CreateBookmarkBody, ListBookmarksArgs, ListBookmarksResponse,
+    Bookmark, BookmarkIter, CheckUrlResponse, CreateBookmarkBody, EnsureBookmarkResult,
+    EnsureOutcome, EnsurePolicy, ListBookmarksArgs, ListBookmarksResponse, PageMetadata,
     UpdateBookmarkBody,
 };
+pub use bulk::{BulkItem, BulkItemOutcome, BulkOptions, BulkReport, OnError};
+#[cfg(feature = "declarative")]
+pub use declarative::{
+    BookmarkDefinition, BookmarkDefinitions, DeclarativeOptions, DeclarativePlan, DeclaredChange,
+    PrunePolicy,
+};
+#[cfg(feature = "epub")]
+pub use epub::{ReadingListOptions, ReadingListReport};
+#[cfg(feature = "extract")]
+pub use extract::{extract_article, Article, NotesUpdate};
+#[cfg(feature = "feed-server")]
+pub use feed_server::{FeedServer, SavedFeed};
+pub use feeds::{Feed, FeedFormat, FeedOptions};
+pub use instance_sync::{
+    normalize_url, ConflictPolicy, InstanceSync, InstanceSyncOptions, InstanceSyncPlan,
+    InstanceSyncReport, InstanceSyncState, Side, SyncAction, SyncDirection, SyncedPair,
+};
+#[cfg(feature = "mirror")]
+pub use mirror::{BookmarkMirror, MirrorSyncOptions, MirrorSyncReport, TagCount};
+#[cfg(feature = "offline")]
+pub use offline::{ConflictResolution, JournalEntry, Mutation, OfflineClient, ReplayReport};
 use reqwest::{
     blocking::multipart::Part,
     header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
     StatusCode,
 };
+#[cfg(feature = "search")]
+pub use search::{IndexedDocument, SearchHit, SearchIndex, SearchIndexUpdate};
+pub use search_query::{SearchClause, SearchQuery, SearchSpecial, SearchSyntax, SearchTerm};
+use std::time::Duration;
+pub use tag_changes::{TagChange, TagChangePlan};
 pub use tags::{ListTagsArgs, ListTagsResponse, TagData};
 use thiserror::Error;
 pub use users::{DateDisplay, LinkTarget, SelectedTheme, SortBy, TagSearchMethod, UserProfile};
+pub use vault::{bookmark_note, note_file_name, VaultExportOptions, VaultExportReport};
+pub use watcher::{BookmarkEvent, BookmarkEventKind, BookmarkWatcher, WatchOptions, WatchState};
+#[cfg(feature = "webhooks")]
+pub use webhooks::{
+    sign, verify_signature, DeadLetter, WebhookDispatcher, WebhookEndpoint, WebhookOptions,
+    WebhookPayload, WebhookReport,
+};
 
 #[derive(Error, Debug)]
 #[cfg_attr(feature = "ffi", derive(uniffi::Error))]
@@ -32,6 +110,29 @@ pub enum LinkDingError {
     ParseResponse(#[from] std::io::Error),
     #[error("Could not serialize JSON body")]
     JsonSerialize(#[from] serde_json::Error),
+    #[error("Bookmark was modified on the server, expected modification date {expected_date_modified} but found {}", current.date_modified)]
+    Conflict {
+        expected_date_modified: String,
+        current: Box<Bookmark>,
+        attempted: Box<UpdateBookmarkBody>,
+    },
+    #[error("Timed out waiting for an asset of bookmark {bookmark_id} to finish processing")]
+    AssetTimeout {
+        bookmark_id: i32,
+        asset_id: Option<i32>,
+    },
+    #[error("Expected {expected} bytes but received {actual}")]
+    ContentLengthMismatch { expected: u64, actual: u64 },
+    #[error("Invalid search query: {0}")]
+    InvalidSearchQuery(String),
+    #[error("Local database error: {0}")]
+    Database(String),
+    #[error("Bookmark {0} is not in the local cache")]
+    NotCached(i32),
+    #[error("Invalid bookmark definitions: {0}")]
+    InvalidDefinitions(String),
+    #[error("Could not {0}, the server did not confirm the request")]
+    Unconfirmed(String),
 }
 
 #[derive(Debug, Clone)]
@@ -183,7 +284,6 @@ impl From<Endpoint> for reqwest::header::HeaderMap {
             | Endpoint::GetUserProfile
             | Endpoint::ListBookmarkAssets(_)
             | Endpoint::RetrieveBookmarkAsset(_, _)
-            | Endpoint::UploadBookmarkAsset(_)
             | Endpoint::DeleteBookmarkAsset(_, _) => {
                 headers.insert(
                     CONTENT_TYPE,
@@ -198,6 +298,15 @@ impl From<Endpoint> for reqwest::header::HeaderMap {
                         .expect("Could not parse accept header value"),
                 );
             }
+            // the multipart form sets its own content type
+            Endpoint::UploadBookmarkAsset(_) => {
+                headers.insert(
+                    ACCEPT,
+                    "application/json"
+                        .parse()
+                        .expect("Could not parse accept header value"),
+                );
+            }
             Endpoint::DownloadBookmarkAsset(_, _) => {
                 headers.insert(ACCEPT, reqwest::header::HeaderValue::from_static("*/*"));
             }
@@ -206,6 +315,9 @@ impl From<Endpoint> for reqwest::header::HeaderMap {
     }
 }
 
+const ASSET_POLL_INITIAL_DELAY: Duration = Duration::from_millis(500);
+const ASSET_POLL_MAX_DELAY: Duration = Duration::from_secs(10);
+
 trait QueryString {
     fn query_string(&self) -> String;
 }
@@ -264,6 +376,57 @@ impl LinkDingClient {
 
         Ok(builder)
     }
+
+    /// Iterate over all unarchived bookmarks matching `args`
+    ///
+    /// Pages are fetched lazily, starting at `args.offset` and using
+    /// `args.limit` as the page size.
+    pub fn iter_bookmarks(&self, args: ListBookmarksArgs) -> BookmarkIter<'_> {
+        BookmarkIter::new(self, args, false)
+    }
+
+    /// Iterate over all archived bookmarks matching `args`
+    pub fn iter_archived_bookmarks(&self, args: ListBookmarksArgs) -> BookmarkIter<'_> {
+        BookmarkIter::new(self, args, true)
+    }
+
+    /// Update a bookmark, resolving conflicting modifications with `merge`
+    ///
+    /// Works like [`LinkDingClient::update_bookmark_if_unmodified`], but when
+    /// the bookmark changed on the server the `merge` function is called with
+    /// the current server version and the attempted update. If it returns a
+    /// new body, that is applied instead (guarded by the current modification
+    /// date), otherwise the conflict is returned as an error.
+    /// [`UpdateBookmarkBody::merge_tags_and_notes`] can be used to build a
+    /// merge function that keeps tag and note edits from both sides.
+    pub fn update_bookmark_with_merge<F>(
+        &self,
+        id: i32,
+        expected_date_modified: &str,
+        body: UpdateBookmarkBody,
+        merge: F,
+    ) -> Result<Bookmark, LinkDingError>
+    where
+        F: FnOnce(&Bookmark, UpdateBookmarkBody) -> Option<UpdateBookmarkBody>,
+    {
+        match self.update_bookmark_if_unmodified(id, expected_date_modified, body) {
+            Err(LinkDingError::Conflict {
+                expected_date_modified,
+                current,
+                attempted,
+            }) => match merge(&current, (*attempted).clone()) {
+                Some(merged) => {
+                    self.update_bookmark_if_unmodified(id, &current.date_modified, merged)
+                }
+                None => Err(LinkDingError::Conflict {
+                    expected_date_modified,
+                    current,
+                    attempted,
+                }),
+            },
+            result => result,
+        }
+    }
 }
 
 #[cfg_attr(feature = "ffi", uniffi::export)]
@@ -335,6 +498,45 @@ impl LinkDingClient {
         Ok(body)
     }
 
+    /// Make sure a bookmark for the URL in `body` exists
+    ///
+    /// Unlike [`LinkDingClient::create_bookmark`], an existing bookmark is
+    /// only changed as allowed by `policy`. The result tells whether the
+    /// bookmark was created, merged, overwritten or skipped, together with the
+    /// page metadata and auto tags linkding determined for the URL.
+    pub fn ensure_bookmark(
+        &self,
+        body: CreateBookmarkBody,
+        policy: EnsurePolicy,
+    ) -> Result<EnsureBookmarkResult, LinkDingError> {
+        let CheckUrlResponse {
+            bookmark,
+            metadata,
+            auto_tags,
+        } = self.check_url(&body.url)?;
+        let (outcome, bookmark) = match bookmark {
+            None => (EnsureOutcome::Created, self.create_bookmark(body)?),
+            Some(existing) => {
+                let update = policy.update_for(&existing, &body);
+                if update.is_empty() {
+                    (EnsureOutcome::Skipped, existing)
+                } else {
+                    let outcome = match policy {
+                        EnsurePolicy::Overwrite => EnsureOutcome::Overwritten,
+                        _ => EnsureOutcome::Merged,
+                    };
+                    (outcome, self.update_bookmark(existing.id, update)?)
+                }
+            }
+        };
+        Ok(EnsureBookmarkResult {
+            outcome,
+            bookmark,
+            metadata,
+            auto_tags,
+        })
+    }
+
     /// Update a bookmark
     ///
     /// Pass only the fields you want to update in the `body` parameter.
@@ -352,6 +554,30 @@ impl LinkDingClient {
         Ok(body)
     }
 
+    /// Update a bookmark only if it hasn't been modified since `expected_date_modified`
+    ///
+    /// The bookmark is re-read before updating and if its `date_modified`
+    /// doesn't match the expected value a [`LinkDingError::Conflict`] is
+    /// returned carrying the current server version and the attempted update.
+    /// The linkding API has no conditional requests, so there is still a small
+    /// window between the check and the update.
+    pub fn update_bookmark_if_unmodified(
+        &self,
+        id: i32,
+        expected_date_modified: &str,
+        body: UpdateBookmarkBody,
+    ) -> Result<Bookmark, LinkDingError> {
+        let current = self.get_bookmark(id)?;
+        if current.date_modified != expected_date_modified {
+            return Err(LinkDingError::Conflict {
+                expected_date_modified: expected_date_modified.to_string(),
+                current: Box::new(current),
+                attempted: Box::new(body),
+            });
+        }
+        self.update_bookmark(id, body)
+    }
+
     /// Archive a bookmark
     pub fn archive_bookmark(&self, id: i32) -> Result<bool, LinkDingError> {
         let endpoint = Endpoint::ArchiveBookmark(id);
@@ -462,6 +688,65 @@ impl LinkDingClient {
         Ok(body)
     }
 
+    /// Wait for an asset to finish processing
+    ///
+    /// Polls the asset with increasing intervals until its status is
+    /// `Complete` or `Failure` and returns it. Fails with
+    /// [`LinkDingError::AssetTimeout`] if it is still pending after `timeout`.
+    pub fn wait_for_asset(
+        &self,
+        bookmark_id: i32,
+        asset_id: i32,
+        timeout: Duration,
+    ) -> Result<BookmarkAsset, LinkDingError> {
+        let mut backoff =
+            Backoff::new(ASSET_POLL_INITIAL_DELAY, ASSET_POLL_MAX_DELAY).with_timeout(timeout);
+        loop {
+            let asset = self.retrieve_bookmark_asset(bookmark_id, asset_id)?;
+            if asset.status != BookmarkAssetStatus::Pending {
+                return Ok(asset);
+            }
+            if !backoff.wait() {
+                return Err(LinkDingError::AssetTimeout {
+                    bookmark_id,
+                    asset_id: Some(asset_id),
+                });
+            }
+        }
+    }
+
+    /// Wait for the latest snapshot of a bookmark to finish processing
+    ///
+    /// Like [`LinkDingClient::wait_for_asset`], also waiting for the snapshot
+    /// asset to show up if linkding hasn't created it yet.
+    pub fn wait_for_snapshot(
+        &self,
+        bookmark_id: i32,
+        timeout: Duration,
+    ) -> Result<BookmarkAsset, LinkDingError> {
+        let mut backoff =
+            Backoff::new(ASSET_POLL_INITIAL_DELAY, ASSET_POLL_MAX_DELAY).with_timeout(timeout);
+        loop {
+            let snapshot = self
+                .list_bookmark_assets(bookmark_id)?
+                .results
+                .into_iter()
+                .filter(|asset| asset.asset_type == BookmarkAssetType::Snapshot)
+                .max_by_key(|asset| asset.id);
+            if let Some(snapshot) = snapshot {
+                if snapshot.status != BookmarkAssetStatus::Pending {
+                    return Ok(snapshot);
+                }
+            }
+            if !backoff.wait() {
+                return Err(LinkDingError::AssetTimeout {
+                    bookmark_id,
+                    asset_id: None,
+                });
+            }
+        }
+    }
+
     /// Delete a bookmark's asset
     pub fn delete_bookmark_asset(
         &self,
@@ -0,0 +1,740 @@
+use std::{collections::BTreeSet, path::Path};
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::{
+    bookmark_assets::{BookmarkAsset, BookmarkAssetStatus, BookmarkAssetType},
+    timestamp::UtcDateTime,
+    Bookmark, LinkDingClient, LinkDingError, ListBookmarksArgs, ListTagsArgs, TagData,
+};
+
+const SCHEMA_VERSION: i32 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS bookmarks (
+    id INTEGER PRIMARY KEY,
+    url TEXT NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    notes TEXT NOT NULL,
+    web_archive_snapshot_url TEXT NOT NULL,
+    favicon_url TEXT,
+    preview_image_url TEXT,
+    is_archived INTEGER NOT NULL,
+    unread INTEGER NOT NULL,
+    shared INTEGER NOT NULL,
+    date_added TEXT NOT NULL,
+    date_modified TEXT NOT NULL,
+    website_title TEXT,
+    website_description TEXT
+);
+CREATE TABLE IF NOT EXISTS tags (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    date_added TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS tags_name ON tags (name COLLATE NOCASE);
+CREATE TABLE IF NOT EXISTS bookmark_tags (
+    bookmark_id INTEGER NOT NULL REFERENCES bookmarks (id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (bookmark_id, tag_id)
+);
+CREATE INDEX IF NOT EXISTS bookmark_tags_tag ON bookmark_tags (tag_id);
+CREATE TABLE IF NOT EXISTS assets (
+    id INTEGER PRIMARY KEY,
+    bookmark_id INTEGER NOT NULL REFERENCES bookmarks (id) ON DELETE CASCADE,
+    asset_type TEXT NOT NULL,
+    date_created TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    display_name TEXT NOT NULL,
+    status TEXT NOT NULL,
+    file_size INTEGER
+);
+CREATE INDEX IF NOT EXISTS assets_bookmark ON assets (bookmark_id);
+CREATE TABLE IF NOT EXISTS sync_state (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+";
+
+const BOOKMARK_COLUMNS: &str = "b.id, b.url, b.title, b.description, b.notes, \
+    b.web_archive_snapshot_url, b.favicon_url, b.preview_image_url, b.is_archived, b.unread, \
+    b.shared, b.date_added, b.date_modified, b.website_title, b.website_description, \
+    (SELECT group_concat(name, char(31)) FROM (\
+        SELECT t.name FROM bookmark_tags bt JOIN tags t ON t.id = bt.tag_id \
+        WHERE bt.bookmark_id = b.id ORDER BY bt.position))";
+
+const ASSET_COLUMNS: &str =
+    "id, bookmark_id, asset_type, date_created, content_type, display_name, status, file_size";
+
+const SYNC_CURSOR: &str = "modified_since";
+
+/// Number of bookmarks carrying a tag
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TagCount {
+    pub name: String,
+    pub bookmarks: i64,
+}
+
+/// A local SQLite copy of the bookmarks, tags and asset metadata
+///
+/// The tables `bookmarks`, `tags`, `bookmark_tags` and `assets` use
+/// linkding's IDs and can be queried directly through
+/// [`BookmarkMirror::connection`]. Keep it up to date with
+/// [`LinkDingClient::sync_mirror`].
+pub struct BookmarkMirror {
+    connection: Connection,
+}
+
+impl BookmarkMirror {
+    /// Open or create a mirror database
+    pub fn open(path: &Path) -> Result<BookmarkMirror, LinkDingError> {
+        BookmarkMirror::init(Connection::open(path).map_err(db_error)?)
+    }
+
+    pub fn open_in_memory() -> Result<BookmarkMirror, LinkDingError> {
+        BookmarkMirror::init(Connection::open_in_memory().map_err(db_error)?)
+    }
+
+    fn init(connection: Connection) -> Result<BookmarkMirror, LinkDingError> {
+        connection
+            .pragma_update(None, "foreign_keys", true)
+            .map_err(db_error)?;
+        let version: i32 = connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(db_error)?;
+        if version > SCHEMA_VERSION {
+            return Err(LinkDingError::Database(format!(
+                "unsupported schema version {}",
+                version
+            )));
+        }
+        connection.execute_batch(SCHEMA).map_err(db_error)?;
+        connection
+            .pragma_update(None, "user_version", SCHEMA_VERSION)
+            .map_err(db_error)?;
+        Ok(BookmarkMirror { connection })
+    }
+
+    /// The underlying connection, for ad-hoc queries
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    pub fn bookmark(&self, id: i32) -> Result<Option<Bookmark>, LinkDingError> {
+        let sql = format!(
+            "SELECT {} FROM bookmarks b WHERE b.id = ?1",
+            BOOKMARK_COLUMNS
+        );
+        self.connection
+            .query_row(&sql, [id], bookmark_from_row)
+            .optional()
+            .map_err(db_error)
+    }
+
+    /// All bookmarks, newest first
+    pub fn bookmarks(&self) -> Result<Vec<Bookmark>, LinkDingError> {
+        let sql = format!(
+            "SELECT {} FROM bookmarks b ORDER BY b.date_added DESC, b.id DESC",
+            BOOKMARK_COLUMNS
+        );
+        self.query(&sql, [], bookmark_from_row)
+    }
+
+    /// Bookmarks carrying a tag, newest first, ignoring case
+    pub fn bookmarks_tagged(&self, tag: &str) -> Result<Vec<Bookmark>, LinkDingError> {
+        let sql = format!(
+            "SELECT {} FROM bookmarks b \
+            WHERE EXISTS (SELECT 1 FROM bookmark_tags bt JOIN tags t ON t.id = bt.tag_id \
+                WHERE bt.bookmark_id = b.id AND t.name = ?1 COLLATE NOCASE) \
+            ORDER BY b.date_added DESC, b.id DESC",
+            BOOKMARK_COLUMNS
+        );
+        self.query(&sql, [tag], bookmark_from_row)
+    }
+
+    /// All tags ordered by name
+    pub fn tags(&self) -> Result<Vec<TagData>, LinkDingError> {
+        self.query(
+            "SELECT id, name, date_added FROM tags ORDER BY name COLLATE NOCASE",
+            [],
+            |row| {
+                Ok(TagData {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    date_added: row.get(2)?,
+                })
+            },
+        )
+    }
+
+    /// Tags with the number of bookmarks carrying them, most used first
+    pub fn tag_counts(&self) -> Result<Vec<TagCount>, LinkDingError> {
+        self.query(
+            "SELECT t.name, count(bt.bookmark_id) AS bookmarks FROM tags t \
+            LEFT JOIN bookmark_tags bt ON bt.tag_id = t.id \
+            GROUP BY t.id ORDER BY bookmarks DESC, t.name COLLATE NOCASE",
+            [],
+            |row| {
+                Ok(TagCount {
+                    name: row.get(0)?,
+                    bookmarks: row.get(1)?,
+                })
+            },
+        )
+    }
+
+    pub fn assets(&self, bookmark_id: i32) -> Result<Vec<BookmarkAsset>, LinkDingError> {
+        let sql = format!(
+            "SELECT {} FROM assets WHERE bookmark_id = ?1 ORDER BY id",
+            ASSET_COLUMNS
+        );
+        self.query(&sql, [bookmark_id], asset_from_row)
+    }
+
+    /// Assets of all bookmarks with a status
+    pub fn assets_with_status(
+        &self,
+        status: BookmarkAssetStatus,
+    ) -> Result<Vec<BookmarkAsset>, LinkDingError> {
+        let sql = format!(
+            "SELECT {} FROM assets WHERE status = ?1 ORDER BY bookmark_id, id",
+            ASSET_COLUMNS
+        );
+        self.query(&sql, [status.as_str()], asset_from_row)
+    }
+
+    /// Modification date of the newest bookmark seen by the last sync
+    pub fn sync_cursor(&self) -> Result<Option<String>, LinkDingError> {
+        self.connection
+            .query_row(
+                "SELECT value FROM sync_state WHERE key = ?1",
+                [SYNC_CURSOR],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)
+    }
+
+    fn set_sync_cursor(&self, cursor: &str) -> Result<(), LinkDingError> {
+        self.connection
+            .execute(
+                "INSERT INTO sync_state (key, value) VALUES (?1, ?2) \
+                ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                [SYNC_CURSOR, cursor],
+            )
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    /// Insert or update a bookmark and its tag links
+    ///
+    /// Returns the names of tags that aren't in the `tags` table and therefore
+    /// couldn't be linked.
+    pub fn upsert_bookmark(&self, bookmark: &Bookmark) -> Result<Vec<String>, LinkDingError> {
+        self.connection
+            .execute(
+                "INSERT INTO bookmarks (id, url, title, description, notes, \
+                    web_archive_snapshot_url, favicon_url, preview_image_url, is_archived, \
+                    unread, shared, date_added, date_modified, website_title, \
+                    website_description) \
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15) \
+                ON CONFLICT (id) DO UPDATE SET url = excluded.url, title = excluded.title, \
+                    description = excluded.description, notes = excluded.notes, \
+                    web_archive_snapshot_url = excluded.web_archive_snapshot_url, \
+                    favicon_url = excluded.favicon_url, \
+                    preview_image_url = excluded.preview_image_url, \
+                    is_archived = excluded.is_archived, unread = excluded.unread, \
+                    shared = excluded.shared, date_added = excluded.date_added, \
+                    date_modified = excluded.date_modified, \
+                    website_title = excluded.website_title, \
+                    website_description = excluded.website_description",
+                params![
+                    bookmark.id,
+                    bookmark.url,
+                    bookmark.title,
+                    bookmark.description,
+                    bookmark.notes,
+                    bookmark.web_archive_snapshot_url,
+                    bookmark.favicon_url,
+                    bookmark.preview_image_url,
+                    bookmark.is_archived,
+                    bookmark.unread,
+                    bookmark.shared,
+                    bookmark.date_added,
+                    bookmark.date_modified,
+                    bookmark.website_title,
+                    bookmark.website_description,
+                ],
+            )
+            .map_err(db_error)?;
+
+        self.connection
+            .execute(
+                "DELETE FROM bookmark_tags WHERE bookmark_id = ?1",
+                [bookmark.id],
+            )
+            .map_err(db_error)?;
+        let mut unknown = Vec::new();
+        for (position, name) in bookmark.tag_names.iter().enumerate() {
+            let linked = self
+                .connection
+                .execute(
+                    "INSERT OR IGNORE INTO bookmark_tags (bookmark_id, tag_id, position) \
+                    SELECT ?1, id, ?2 FROM tags WHERE name = ?3 COLLATE NOCASE LIMIT 1",
+                    params![bookmark.id, position as i64, name],
+                )
+                .map_err(db_error)?;
+            if linked == 0 {
+                unknown.push(name.clone());
+            }
+        }
+        Ok(unknown)
+    }
+
+    /// Remove a bookmark along with its tag links and assets
+    pub fn remove_bookmark(&self, id: i32) -> Result<bool, LinkDingError> {
+        let removed = self
+            .connection
+            .execute("DELETE FROM bookmarks WHERE id = ?1", [id])
+            .map_err(db_error)?;
+        Ok(removed > 0)
+    }
+
+    /// Replace all tags, dropping the ones not in `tags`
+    pub fn replace_tags(&self, tags: &[TagData]) -> Result<(), LinkDingError> {
+        let ids: BTreeSet<i32> = tags.iter().map(|tag| tag.id).collect();
+        for tag in tags {
+            self.connection
+                .execute(
+                    "INSERT INTO tags (id, name, date_added) VALUES (?1, ?2, ?3) \
+                    ON CONFLICT (id) DO UPDATE SET name = excluded.name, \
+                        date_added = excluded.date_added",
+                    params![tag.id, tag.name, tag.date_added],
+                )
+                .map_err(db_error)?;
+        }
+        let stale: Vec<i32> = self.query("SELECT id FROM tags", [], |row| row.get(0))?;
+        for id in stale.into_iter().filter(|id| !ids.contains(id)) {
+            self.connection
+                .execute("DELETE FROM tags WHERE id = ?1", [id])
+                .map_err(db_error)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the assets of a bookmark
+    pub fn replace_assets(
+        &self,
+        bookmark_id: i32,
+        assets: &[BookmarkAsset],
+    ) -> Result<(), LinkDingError> {
+        self.connection
+            .execute("DELETE FROM assets WHERE bookmark_id = ?1", [bookmark_id])
+            .map_err(db_error)?;
+        for asset in assets {
+            self.connection
+                .execute(
+                    "INSERT INTO assets (id, bookmark_id, asset_type, date_created, \
+                        content_type, display_name, status, file_size) \
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        asset.id,
+                        bookmark_id,
+                        asset.asset_type.as_str(),
+                        asset.date_created,
+                        asset.content_type,
+                        asset.display_name,
+                        asset.status.as_str(),
+                        asset.file_size,
+                    ],
+                )
+                .map_err(db_error)?;
+        }
+        Ok(())
+    }
+
+    fn query<T, P: rusqlite::Params>(
+        &self,
+        sql: &str,
+        params: P,
+        map: impl FnMut(&Row<'_>) -> rusqlite::Result<T>,
+    ) -> Result<Vec<T>, LinkDingError> {
+        let mut statement = self.connection.prepare(sql).map_err(db_error)?;
+        let rows = statement.query_map(params, map).map_err(db_error)?;
+        rows.collect::<Result<Vec<T>, _>>().map_err(db_error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MirrorSyncOptions {
+    /// Fetch all bookmarks instead of only the ones modified since the last
+    /// sync, this also removes bookmarks deleted on the server
+    pub full: bool,
+    /// Also sync the asset metadata of fetched bookmarks
+    pub assets: bool,
+}
+
+impl Default for MirrorSyncOptions {
+    fn default() -> Self {
+        MirrorSyncOptions {
+            full: false,
+            assets: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct MirrorSyncReport {
+    /// Whether all bookmarks were fetched, which is always the case with
+    /// linkding versions that don't support the `modified_since` filter
+    pub full: bool,
+    pub bookmarks_updated: usize,
+    pub bookmarks_removed: usize,
+    pub tags: usize,
+    /// Tags of synced bookmarks that still weren't on the server after
+    /// refreshing the tags, they aren't linked to the bookmarks
+    pub unlinked_tags: BTreeSet<String>,
+}
+
+/// Syncing a local SQLite mirror
+impl LinkDingClient {
+    /// Bring a mirror up to date
+    ///
+    /// The first sync fetches everything, later ones only fetch bookmarks
+    /// modified since the previous sync. Such an incremental sync can't see
+    /// deletions: bookmarks deleted on the server stay in the mirror until a
+    /// sync with [`MirrorSyncOptions::full`] set. linkding versions that
+    /// don't support the `modified_since` filter list all bookmarks anyway,
+    /// which is detected and handled as a full sync. Assets are only refreshed
+    /// for fetched bookmarks. Tags are always synced completely, and again
+    /// whenever a fetched bookmark has a tag the mirror doesn't know yet.
+    /// Changes are written in a single transaction, a failed sync leaves the
+    /// mirror untouched.
+    pub fn sync_mirror(
+        &self,
+        mirror: &mut BookmarkMirror,
+        options: &MirrorSyncOptions,
+    ) -> Result<MirrorSyncReport, LinkDingError> {
+        let cursor = match options.full {
+            true => None,
+            false => mirror.sync_cursor()?,
+        };
+        let mut report = MirrorSyncReport {
+            full: cursor.is_none(),
+            ..Default::default()
+        };
+
+        let transaction = mirror
+            .connection
+            .unchecked_transaction()
+            .map_err(db_error)?;
+        let tags = self.all_tags()?;
+        report.tags = tags.len();
+        mirror.replace_tags(&tags)?;
+
+        let args = ListBookmarksArgs {
+            modified_since: cursor.clone(),
+            ..Default::default()
+        };
+        let mut newest = cursor.as_deref().and_then(UtcDateTime::parse_rfc3339);
+        let mut newest_cursor = cursor.clone();
+        let mut seen = BTreeSet::new();
+        for bookmark in self
+            .iter_bookmarks(args.clone())
+            .chain(self.iter_archived_bookmarks(args.clone()))
+        {
+            let bookmark = bookmark?;
+            if args.predates_modified_since(&bookmark) {
+                // older linkding versions ignore the filter and list everything
+                report.full = true;
+            }
+            let unknown = mirror.upsert_bookmark(&bookmark)?;
+            if unknown
+                .iter()
+                .any(|tag| !report.unlinked_tags.contains(tag))
+            {
+                // tags created while syncing
+                let tags = self.all_tags()?;
+                report.tags = tags.len();
+                mirror.replace_tags(&tags)?;
+                report
+                    .unlinked_tags
+                    .extend(mirror.upsert_bookmark(&bookmark)?);
+            }
+            if options.assets {
+                let assets = self.list_bookmark_assets(bookmark.id)?.results;
+                mirror.replace_assets(bookmark.id, &assets)?;
+            }
+            let modified = UtcDateTime::parse_rfc3339(&bookmark.date_modified);
+            if modified.is_some() && modified > newest {
+                newest = modified;
+                newest_cursor = Some(bookmark.date_modified.clone());
+            }
+            seen.insert(bookmark.id);
+            report.bookmarks_updated += 1;
+        }
+
+        if report.full {
+            let stored: Vec<i32> =
+                mirror.query("SELECT id FROM bookmarks", [], |row| row.get(0))?;
+            for id in stored.into_iter().filter(|id| !seen.contains(id)) {
+                mirror.remove_bookmark(id)?;
+                report.bookmarks_removed += 1;
+            }
+        }
+        if let Some(cursor) = newest_cursor {
+            mirror.set_sync_cursor(&cursor)?;
+        }
+        transaction.commit().map_err(db_error)?;
+        Ok(report)
+    }
+
+    fn all_tags(&self) -> Result<Vec<TagData>, LinkDingError> {
+        let mut tags = Vec::new();
+        loop {
+            let response = self.list_tags(ListTagsArgs {
+                limit: Some(1000),
+                offset: Some(tags.len() as i32),
+            })?;
+            let done = response.next.is_none() || response.results.is_empty();
+            tags.extend(response.results);
+            if done {
+                return Ok(tags);
+            }
+        }
+    }
+}
+
+fn bookmark_from_row(row: &Row<'_>) -> rusqlite::Result<Bookmark> {
+    let tags: Option<String> = row.get(15)?;
+    Ok(Bookmark {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        title: row.get(2)?,
+        description: row.get(3)?,
+        notes: row.get(4)?,
+        web_archive_snapshot_url: row.get(5)?,
+        favicon_url: row.get(6)?,
+        preview_image_url: row.get(7)?,
+        is_archived: row.get(8)?,
+        unread: row.get(9)?,
+        shared: row.get(10)?,
+        date_added: row.get(11)?,
+        date_modified: row.get(12)?,
+        website_title: row.get(13)?,
+        website_description: row.get(14)?,
+        tag_names: tags
+            .map(|tags| tags.split('\u{1f}').map(str::to_string).collect())
+            .unwrap_or_default(),
+    })
+}
+
+fn asset_from_row(row: &Row<'_>) -> rusqlite::Result<BookmarkAsset> {
+    let asset_type: String = row.get(2)?;
+    let status: String = row.get(6)?;
+    Ok(BookmarkAsset {
+        id: row.get(0)?,
+        bookmark: row.get(1)?,
+        asset_type: match asset_type.as_str() {
+            "upload" => BookmarkAssetType::Upload,
+            _ => BookmarkAssetType::Snapshot,
+        },
+        date_created: row.get(3)?,
+        content_type: row.get(4)?,
+        display_name: row.get(5)?,
+        status: match status.as_str() {
+            "complete" => BookmarkAssetStatus::Complete,
+            "failure" => BookmarkAssetStatus::Failure,
+            _ => BookmarkAssetStatus::Pending,
+        },
+        file_size: row.get(7)?,
+    })
+}
+
+pub(crate) fn db_error(error: rusqlite::Error) -> LinkDingError {
+    LinkDingError::Database(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, page, serve_api, tags, Route};
+
+    fn tag(id: i32, name: &str) -> TagData {
+        TagData {
+            id,
+            name: name.to_string(),
+            date_added: "2025-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    fn bookmark(id: i32, tag_names: &[&str]) -> Bookmark {
+        Bookmark {
+            title: format!("Bookmark {}", id),
+            preview_image_url: Some("https://example.com/preview.png".to_string()),
+            unread: true,
+            tag_names: tags(tag_names),
+            date_added: format!("2025-01-0{}T00:00:00Z", id),
+            date_modified: format!("2025-02-0{}T00:00:00Z", id),
+            ..test_util::bookmark(id)
+        }
+    }
+
+    fn asset(id: i32, bookmark: i32, status: BookmarkAssetStatus) -> BookmarkAsset {
+        BookmarkAsset {
+            id,
+            bookmark,
+            asset_type: BookmarkAssetType::Snapshot,
+            date_created: "2025-01-01T00:00:00Z".to_string(),
+            content_type: "text/html".to_string(),
+            display_name: "HTML snapshot".to_string(),
+            status,
+            file_size: Some(1024),
+        }
+    }
+
+    #[test]
+    fn stores_and_reads_bookmarks() {
+        let mirror = BookmarkMirror::open_in_memory().unwrap();
+        mirror
+            .replace_tags(&[tag(1, "rust"), tag(2, "cli"), tag(3, "old")])
+            .unwrap();
+        let first = bookmark(1, &["rust", "CLI"]);
+        assert!(mirror.upsert_bookmark(&first).unwrap().is_empty());
+        assert_eq!(
+            mirror
+                .upsert_bookmark(&bookmark(2, &["rust", "new"]))
+                .unwrap(),
+            vec!["new".to_string()]
+        );
+
+        let mut expected = first.clone();
+        expected.tag_names = vec!["rust".to_string(), "cli".to_string()];
+        assert_eq!(mirror.bookmark(1).unwrap(), Some(expected));
+        assert_eq!(mirror.bookmark(5).unwrap(), None);
+        let ids: Vec<i32> = mirror.bookmarks().unwrap().iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+        assert_eq!(mirror.bookmarks_tagged("Cli").unwrap().len(), 1);
+        assert_eq!(
+            mirror.tag_counts().unwrap(),
+            vec![
+                TagCount {
+                    name: "rust".to_string(),
+                    bookmarks: 2
+                },
+                TagCount {
+                    name: "cli".to_string(),
+                    bookmarks: 1
+                },
+                TagCount {
+                    name: "old".to_string(),
+                    bookmarks: 0
+                },
+            ]
+        );
+
+        mirror.replace_tags(&[tag(1, "rust")]).unwrap();
+        assert_eq!(mirror.bookmark(1).unwrap().unwrap().tag_names, vec!["rust"]);
+    }
+
+    #[test]
+    fn removing_bookmarks_removes_their_assets() {
+        let mirror = BookmarkMirror::open_in_memory().unwrap();
+        mirror.upsert_bookmark(&bookmark(1, &[])).unwrap();
+        mirror
+            .replace_assets(
+                1,
+                &[
+                    asset(10, 1, BookmarkAssetStatus::Complete),
+                    asset(11, 1, BookmarkAssetStatus::Failure),
+                ],
+            )
+            .unwrap();
+        assert_eq!(mirror.assets(1).unwrap().len(), 2);
+        assert_eq!(
+            mirror
+                .assets_with_status(BookmarkAssetStatus::Failure)
+                .unwrap(),
+            vec![asset(11, 1, BookmarkAssetStatus::Failure)]
+        );
+        assert!(mirror.remove_bookmark(1).unwrap());
+        assert!(mirror.assets(1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn links_tags_created_while_syncing() {
+        let tags = |names: &[&str]| {
+            let tags: Vec<TagData> = (1..).zip(names).map(|(id, name)| tag(id, name)).collect();
+            page(&tags)
+        };
+        let (url, _) = serve_api(vec![
+            Route::ok("GET /api/tags/", tags(&["rust"])),
+            Route::ok("GET /api/tags/", tags(&["rust", "cli"])),
+            Route::ok("GET /api/tags/", tags(&["rust", "cli", "new"])),
+            Route::ok(
+                "GET /api/bookmarks/",
+                page(&[bookmark(1, &["rust", "cli"]), bookmark(2, &["new", "gone"])]),
+            ),
+            Route::ok("GET /api/bookmarks/archived/", page::<Bookmark>(&[])),
+        ]);
+        let client = LinkDingClient::new(&url, "token");
+        let mut mirror = BookmarkMirror::open_in_memory().unwrap();
+        let options = MirrorSyncOptions {
+            assets: false,
+            ..Default::default()
+        };
+
+        let report = client.sync_mirror(&mut mirror, &options).unwrap();
+        assert_eq!(report.tags, 3);
+        assert_eq!(report.unlinked_tags, BTreeSet::from(["gone".to_string()]));
+        assert_eq!(
+            mirror.bookmark(1).unwrap().unwrap().tag_names,
+            vec!["rust", "cli"]
+        );
+        assert_eq!(mirror.bookmark(2).unwrap().unwrap().tag_names, vec!["new"]);
+    }
+
+    #[test]
+    fn falls_back_to_a_full_sync_when_the_filter_is_ignored() {
+        let (url, requests) = serve_api(vec![
+            Route::ok("GET /api/tags/", page::<TagData>(&[])),
+            Route::ok(
+                "GET /api/bookmarks/",
+                page(&[bookmark(1, &[]), bookmark(2, &[])]),
+            ),
+            Route::ok("GET /api/bookmarks/", page(&[bookmark(1, &[])])),
+            Route::ok("GET /api/bookmarks/archived/", page::<Bookmark>(&[])),
+        ]);
+        let client = LinkDingClient::new(&url, "token");
+        let mut mirror = BookmarkMirror::open_in_memory().unwrap();
+        let options = MirrorSyncOptions {
+            assets: false,
+            ..Default::default()
+        };
+
+        client.sync_mirror(&mut mirror, &options).unwrap();
+        let report = client.sync_mirror(&mut mirror, &options).unwrap();
+        assert!(report.full);
+        assert_eq!(report.bookmarks_removed, 1);
+        assert_eq!(mirror.bookmark(2).unwrap(), None);
+        assert!(requests
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|request| request.contains("modified_since=2025-02-02")));
+    }
+
+    #[test]
+    fn keeps_sync_cursor() {
+        let mirror = BookmarkMirror::open_in_memory().unwrap();
+        assert_eq!(mirror.sync_cursor().unwrap(), None);
+        mirror.set_sync_cursor("2025-01-01T00:00:00Z").unwrap();
+        mirror.set_sync_cursor("2025-02-01T00:00:00Z").unwrap();
+        assert_eq!(
+            mirror.sync_cursor().unwrap().as_deref(),
+            Some("2025-02-01T00:00:00Z")
+        );
+    }
+}
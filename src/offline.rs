@@ -0,0 +1,670 @@
+use std::collections::BTreeSet;
+
+use rusqlite::{params, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    mirror::{db_error, BookmarkMirror},
+    timestamp::UtcDateTime,
+    Bookmark, CreateBookmarkBody, LinkDingClient, LinkDingError, MirrorSyncOptions,
+    MirrorSyncReport, SearchQuery, TagSearchMethod, UpdateBookmarkBody,
+};
+
+const JOURNAL_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS journal (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    bookmark_id INTEGER NOT NULL,
+    mutation TEXT NOT NULL,
+    expected_date_modified TEXT,
+    conflict TEXT,
+    error TEXT
+);
+";
+
+const JOURNAL_COLUMNS: &str = "seq, bookmark_id, mutation, expected_date_modified, conflict, error";
+
+/// A change to a bookmark waiting to be sent to the server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Mutation {
+    Create { body: CreateBookmarkBody },
+    Update { body: UpdateBookmarkBody },
+    Archive,
+    Unarchive,
+    Delete,
+}
+
+/// A queued mutation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// Position in the journal, entries are replayed in this order
+    pub seq: i64,
+    /// The bookmark, negative for bookmarks created offline
+    pub bookmark_id: i32,
+    pub mutation: Mutation,
+    /// Modification date the server copy must still have for updates and
+    /// deletes to be replayed
+    pub expected_date_modified: Option<String>,
+    /// The server copy, when it was modified after the mutation was queued
+    pub conflict: Option<Bookmark>,
+    /// Error of the last replay attempt
+    pub error: Option<String>,
+}
+
+/// How to resolve a conflicting journal entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConflictResolution {
+    /// Drop the queued mutation and keep the server copy
+    KeepServer,
+    /// Replay the queued mutation over the server copy
+    KeepLocal,
+}
+
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    /// Journal entries sent to the server
+    pub applied: Vec<i64>,
+    /// Entries not replayed because the server copy changed meanwhile
+    pub conflicts: Vec<JournalEntry>,
+    pub failed: Vec<(i64, LinkDingError)>,
+    /// Entries not replayed because an earlier entry for the same bookmark
+    /// conflicted or failed
+    pub held_back: Vec<i64>,
+    /// Whether replaying stopped because the server wasn't reachable
+    pub offline: bool,
+    /// The cache refresh done after replaying, if the server was reachable
+    pub refreshed: Option<MirrorSyncReport>,
+}
+
+/// A client working against a local cache while the server is unreachable
+///
+/// Reads are served from a [`BookmarkMirror`]. Mutations are applied to the
+/// cache right away and queued in a journal stored in the same database,
+/// [`OfflineClient::sync`] replays them in order once the server is
+/// reachable. Updates and deletes of bookmarks that were modified on the
+/// server in the meantime aren't replayed but reported as conflicts, see
+/// [`OfflineClient::resolve_conflict`].
+pub struct OfflineClient {
+    client: LinkDingClient,
+    mirror: BookmarkMirror,
+}
+
+impl OfflineClient {
+    pub fn new(client: LinkDingClient, mirror: BookmarkMirror) -> Result<Self, LinkDingError> {
+        mirror
+            .connection()
+            .execute_batch(JOURNAL_SCHEMA)
+            .map_err(db_error)?;
+        Ok(OfflineClient { client, mirror })
+    }
+
+    pub fn client(&self) -> &LinkDingClient {
+        &self.client
+    }
+
+    pub fn mirror(&self) -> &BookmarkMirror {
+        &self.mirror
+    }
+
+    pub fn get_bookmark(&self, id: i32) -> Result<Option<Bookmark>, LinkDingError> {
+        self.mirror.bookmark(id)
+    }
+
+    pub fn bookmarks(&self) -> Result<Vec<Bookmark>, LinkDingError> {
+        self.mirror.bookmarks()
+    }
+
+    /// Cached bookmarks matching a query, evaluated locally
+    pub fn search(
+        &self,
+        query: &SearchQuery,
+        tag_search: TagSearchMethod,
+    ) -> Result<Vec<Bookmark>, LinkDingError> {
+        Ok(self
+            .mirror
+            .bookmarks()?
+            .into_iter()
+            .filter(|bookmark| query.matches(bookmark, tag_search))
+            .collect())
+    }
+
+    /// Queue a new bookmark, it gets a negative ID until it is replayed
+    pub fn create_bookmark(&self, body: CreateBookmarkBody) -> Result<Bookmark, LinkDingError> {
+        let local_id = self.next_local_id()?;
+        let bookmark = self.enqueue(local_id, Mutation::Create { body })?;
+        Ok(bookmark.expect("creating a bookmark always yields it"))
+    }
+
+    pub fn update_bookmark(
+        &self,
+        id: i32,
+        body: UpdateBookmarkBody,
+    ) -> Result<Bookmark, LinkDingError> {
+        let bookmark = self.enqueue(id, Mutation::Update { body })?;
+        Ok(bookmark.expect("updating a bookmark always yields it"))
+    }
+
+    pub fn archive_bookmark(&self, id: i32) -> Result<(), LinkDingError> {
+        self.enqueue(id, Mutation::Archive)?;
+        Ok(())
+    }
+
+    pub fn unarchive_bookmark(&self, id: i32) -> Result<(), LinkDingError> {
+        self.enqueue(id, Mutation::Unarchive)?;
+        Ok(())
+    }
+
+    pub fn delete_bookmark(&self, id: i32) -> Result<(), LinkDingError> {
+        self.enqueue(id, Mutation::Delete)?;
+        Ok(())
+    }
+
+    /// All queued mutations in replay order
+    pub fn pending(&self) -> Result<Vec<JournalEntry>, LinkDingError> {
+        self.query_journal("1 = 1", [])
+    }
+
+    /// Queued mutations that conflict with changes on the server
+    pub fn conflicts(&self) -> Result<Vec<JournalEntry>, LinkDingError> {
+        self.query_journal("conflict IS NOT NULL", [])
+    }
+
+    /// Replay queued mutations, then refresh the cache from the server
+    ///
+    /// Replaying stops at the first entry that can't be sent because the
+    /// server isn't reachable, the remaining entries are kept for the next
+    /// sync. An entry that conflicts or fails holds back the later entries
+    /// for the same bookmark.
+    pub fn sync(&mut self) -> Result<ReplayReport, LinkDingError> {
+        let mut report = ReplayReport::default();
+        let mut blocked = BTreeSet::new();
+        for entry in self.pending()? {
+            if blocked.contains(&entry.bookmark_id) {
+                report.held_back.push(entry.seq);
+                continue;
+            }
+            if entry.conflict.is_some() {
+                blocked.insert(entry.bookmark_id);
+                report.conflicts.push(entry);
+                continue;
+            }
+            match self.replay(&entry) {
+                Ok(bookmark) => {
+                    self.complete(&entry, bookmark)?;
+                    report.applied.push(entry.seq);
+                }
+                Err(LinkDingError::Conflict { current, .. }) => {
+                    let conflict = serde_json::to_string(&current)?;
+                    self.execute(
+                        "UPDATE journal SET conflict = ?1, error = NULL WHERE seq = ?2",
+                        params![conflict, entry.seq],
+                    )?;
+                    blocked.insert(entry.bookmark_id);
+                    report.conflicts.push(JournalEntry {
+                        conflict: Some(*current),
+                        ..entry
+                    });
+                }
+                Err(error) if is_unreachable(&error) => {
+                    report.offline = true;
+                    break;
+                }
+                Err(error) => {
+                    self.execute(
+                        "UPDATE journal SET error = ?1 WHERE seq = ?2",
+                        params![error.to_string(), entry.seq],
+                    )?;
+                    blocked.insert(entry.bookmark_id);
+                    report.failed.push((entry.seq, error));
+                }
+            }
+        }
+
+        if !report.offline {
+            match self
+                .client
+                .sync_mirror(&mut self.mirror, &MirrorSyncOptions::default())
+            {
+                Ok(refreshed) => report.refreshed = Some(refreshed),
+                Err(error) if is_unreachable(&error) => report.offline = true,
+                Err(error) => return Err(error),
+            }
+        }
+        self.reapply_pending()?;
+        Ok(report)
+    }
+
+    /// Resolve a conflicting entry
+    pub fn resolve_conflict(
+        &self,
+        seq: i64,
+        resolution: ConflictResolution,
+    ) -> Result<(), LinkDingError> {
+        let entry = self.entry(seq)?;
+        let Some(server) = entry.conflict else {
+            return Ok(());
+        };
+        match resolution {
+            ConflictResolution::KeepServer => {
+                self.execute("DELETE FROM journal WHERE seq = ?1", [seq])?;
+                self.cache(&server)?;
+                self.guard_next(server.id, &server.date_modified)?;
+                self.reapply_pending()
+            }
+            ConflictResolution::KeepLocal => self.execute(
+                "UPDATE journal SET expected_date_modified = ?1, conflict = NULL, error = NULL \
+                WHERE seq = ?2",
+                params![server.date_modified, seq],
+            ),
+        }
+    }
+
+    /// Drop a queued mutation, the cached copy is corrected by the next sync
+    ///
+    /// Discarding the creation of a bookmark also discards the later entries
+    /// for it.
+    pub fn discard(&self, seq: i64) -> Result<(), LinkDingError> {
+        let entry = self.entry(seq)?;
+        if let Mutation::Create { .. } = entry.mutation {
+            self.execute(
+                "DELETE FROM journal WHERE bookmark_id = ?1",
+                [entry.bookmark_id],
+            )?;
+            self.mirror.remove_bookmark(entry.bookmark_id)?;
+        } else {
+            self.execute("DELETE FROM journal WHERE seq = ?1", [seq])?;
+        }
+        Ok(())
+    }
+
+    /// Send a journal entry, returning the server copy unless it was deleted
+    fn replay(&self, entry: &JournalEntry) -> Result<Option<Bookmark>, LinkDingError> {
+        let id = entry.bookmark_id;
+        match &entry.mutation {
+            Mutation::Create { body } => self.client.create_bookmark(body.clone()).map(Some),
+            Mutation::Update { body } => match &entry.expected_date_modified {
+                Some(expected) => self
+                    .client
+                    .update_bookmark_if_unmodified(id, expected, body.clone())
+                    .map(Some),
+                None => self.client.update_bookmark(id, body.clone()).map(Some),
+            },
+            Mutation::Archive => {
+                self.client.archive_bookmark(id)?;
+                self.client.get_bookmark(id).map(Some)
+            }
+            Mutation::Unarchive => {
+                self.client.unarchive_bookmark(id)?;
+                self.client.get_bookmark(id).map(Some)
+            }
+            Mutation::Delete => {
+                if let Some(expected) = &entry.expected_date_modified {
+                    let current = self.client.get_bookmark(id)?;
+                    if &current.date_modified != expected {
+                        return Err(LinkDingError::Conflict {
+                            expected_date_modified: expected.clone(),
+                            current: Box::new(current),
+                            attempted: Box::default(),
+                        });
+                    }
+                }
+                // deleting a bookmark that is already gone is fine
+                self.client.delete_bookmark(id)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Remove a replayed entry and bring the cache and later entries in line
+    fn complete(
+        &self,
+        entry: &JournalEntry,
+        server: Option<Bookmark>,
+    ) -> Result<(), LinkDingError> {
+        self.execute("DELETE FROM journal WHERE seq = ?1", [entry.seq])?;
+        let Some(server) = server else {
+            self.mirror.remove_bookmark(entry.bookmark_id)?;
+            return Ok(());
+        };
+        if server.id != entry.bookmark_id {
+            // a bookmark created offline got its real ID
+            self.execute(
+                "UPDATE journal SET bookmark_id = ?1 WHERE bookmark_id = ?2",
+                [server.id, entry.bookmark_id],
+            )?;
+            self.mirror.remove_bookmark(entry.bookmark_id)?;
+        }
+        self.cache(&server)?;
+        self.guard_next(server.id, &server.date_modified)
+    }
+
+    /// Make the next entry for a bookmark check against a new server version
+    fn guard_next(&self, bookmark_id: i32, date_modified: &str) -> Result<(), LinkDingError> {
+        self.execute(
+            "UPDATE journal SET expected_date_modified = ?1 WHERE seq = (\
+                SELECT min(seq) FROM journal WHERE bookmark_id = ?2)",
+            params![date_modified, bookmark_id],
+        )
+    }
+
+    /// Apply a mutation to the cache and add it to the journal, both in one
+    /// transaction
+    fn enqueue(
+        &self,
+        bookmark_id: i32,
+        mutation: Mutation,
+    ) -> Result<Option<Bookmark>, LinkDingError> {
+        let transaction = self
+            .mirror
+            .connection()
+            .unchecked_transaction()
+            .map_err(db_error)?;
+        // only the first entry for a bookmark is checked against the cached
+        // version, later ones get checked against the result of replaying it
+        let queued: bool = self
+            .mirror
+            .connection()
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM journal WHERE bookmark_id = ?1)",
+                [bookmark_id],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+        let expected = match mutation {
+            Mutation::Update { .. } | Mutation::Delete if !queued && bookmark_id > 0 => {
+                self.server_version(bookmark_id)?
+            }
+            _ => None,
+        };
+        let bookmark = self.apply_locally(bookmark_id, &mutation)?;
+        self.execute(
+            "INSERT INTO journal (bookmark_id, mutation, expected_date_modified) \
+            VALUES (?1, ?2, ?3)",
+            params![bookmark_id, serde_json::to_string(&mutation)?, expected],
+        )?;
+        transaction.commit().map_err(db_error)?;
+        Ok(bookmark)
+    }
+
+    /// The modification date of the cached server copy of a bookmark
+    fn server_version(&self, bookmark_id: i32) -> Result<Option<String>, LinkDingError> {
+        // mutations applied locally keep the server's date_modified, so the
+        // cached bookmark still carries the version it was based on
+        Ok(self
+            .mirror
+            .bookmark(bookmark_id)?
+            .map(|bookmark| bookmark.date_modified))
+    }
+
+    /// Apply a mutation to the cache, returning the changed bookmark
+    fn apply_locally(
+        &self,
+        bookmark_id: i32,
+        mutation: &Mutation,
+    ) -> Result<Option<Bookmark>, LinkDingError> {
+        let mut bookmark = match mutation {
+            Mutation::Create { body } => local_bookmark(bookmark_id, body),
+            _ => self
+                .mirror
+                .bookmark(bookmark_id)?
+                .ok_or(LinkDingError::NotCached(bookmark_id))?,
+        };
+        match mutation {
+            Mutation::Create { .. } => {}
+            Mutation::Update { body } => {
+                let mut body = body.clone();
+                body.date_modified = None;
+                bookmark.apply(&body);
+            }
+            Mutation::Archive => bookmark.is_archived = true,
+            Mutation::Unarchive => bookmark.is_archived = false,
+            Mutation::Delete => {
+                self.mirror.remove_bookmark(bookmark_id)?;
+                return Ok(None);
+            }
+        }
+        self.cache(&bookmark)?;
+        Ok(Some(bookmark))
+    }
+
+    /// Apply all queued mutations to the cache again, e.g. after a refresh
+    fn reapply_pending(&self) -> Result<(), LinkDingError> {
+        for entry in self.pending()? {
+            match self.apply_locally(entry.bookmark_id, &entry.mutation) {
+                Ok(_) | Err(LinkDingError::NotCached(_)) => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Store a bookmark in the cache, adding placeholders for unknown tags
+    fn cache(&self, bookmark: &Bookmark) -> Result<(), LinkDingError> {
+        let unknown = self.mirror.upsert_bookmark(bookmark)?;
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        for name in &unknown {
+            // placeholders get negative IDs and are replaced by the next sync
+            self.execute(
+                "INSERT INTO tags (id, name, date_added) \
+                SELECT min(coalesce(min(id), 0), 0) - 1, ?1, ?2 FROM tags",
+                params![name, UtcDateTime::now().to_rfc3339()],
+            )?;
+        }
+        self.mirror.upsert_bookmark(bookmark)?;
+        Ok(())
+    }
+
+    fn next_local_id(&self) -> Result<i32, LinkDingError> {
+        self.mirror
+            .connection()
+            .query_row(
+                "SELECT min(coalesce((SELECT min(id) FROM bookmarks), 0), \
+                    coalesce((SELECT min(bookmark_id) FROM journal), 0), 0) - 1",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(db_error)
+    }
+
+    fn entry(&self, seq: i64) -> Result<JournalEntry, LinkDingError> {
+        let sql = format!("SELECT {} FROM journal WHERE seq = ?1", JOURNAL_COLUMNS);
+        self.mirror
+            .connection()
+            .query_row(&sql, [seq], entry_from_row)
+            .optional()
+            .map_err(db_error)?
+            .ok_or_else(|| LinkDingError::Database(format!("no journal entry {}", seq)))
+    }
+
+    fn query_journal<P: rusqlite::Params>(
+        &self,
+        condition: &str,
+        params: P,
+    ) -> Result<Vec<JournalEntry>, LinkDingError> {
+        let sql = format!(
+            "SELECT {} FROM journal WHERE {} ORDER BY seq",
+            JOURNAL_COLUMNS, condition
+        );
+        let mut statement = self.mirror.connection().prepare(&sql).map_err(db_error)?;
+        let entries = statement
+            .query_map(params, entry_from_row)
+            .map_err(db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(db_error)?;
+        Ok(entries)
+    }
+
+    fn execute<P: rusqlite::Params>(&self, sql: &str, params: P) -> Result<(), LinkDingError> {
+        self.mirror
+            .connection()
+            .execute(sql, params)
+            .map_err(db_error)?;
+        Ok(())
+    }
+}
+
+/// The cached copy of a bookmark created offline
+fn local_bookmark(id: i32, body: &CreateBookmarkBody) -> Bookmark {
+    let now = UtcDateTime::now().to_rfc3339();
+    let mut bookmark = Bookmark {
+        id,
+        url: body.url.clone(),
+        title: String::new(),
+        description: String::new(),
+        notes: String::new(),
+        web_archive_snapshot_url: String::new(),
+        favicon_url: None,
+        preview_image_url: None,
+        is_archived: false,
+        unread: false,
+        shared: false,
+        tag_names: Vec::new(),
+        date_added: now.clone(),
+        date_modified: now,
+        website_title: None,
+        website_description: None,
+    };
+    bookmark.apply(&body.clone().into());
+    bookmark
+}
+
+/// Whether an error means the server couldn't be reached at all
+fn is_unreachable(error: &LinkDingError) -> bool {
+    match error {
+        LinkDingError::SendHttpError(error) => error.is_connect() || error.is_timeout(),
+        _ => false,
+    }
+}
+
+fn entry_from_row(row: &Row<'_>) -> rusqlite::Result<JournalEntry> {
+    fn json<T: serde::de::DeserializeOwned>(index: usize, value: &str) -> rusqlite::Result<T> {
+        serde_json::from_str(value).map_err(|error| {
+            rusqlite::Error::FromSqlConversionFailure(
+                index,
+                rusqlite::types::Type::Text,
+                Box::new(error),
+            )
+        })
+    }
+
+    let mutation: String = row.get(2)?;
+    let conflict: Option<String> = row.get(4)?;
+    Ok(JournalEntry {
+        seq: row.get(0)?,
+        bookmark_id: row.get(1)?,
+        mutation: json(2, &mutation)?,
+        expected_date_modified: row.get(3)?,
+        conflict: conflict.map(|value| json(4, &value)).transpose()?,
+        error: row.get(5)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::bookmark;
+
+    fn client() -> OfflineClient {
+        // nothing listens on the discard port, so the server is unreachable
+        let client = LinkDingClient::new("http://127.0.0.1:9", "token");
+        let mirror = BookmarkMirror::open_in_memory().unwrap();
+        mirror
+            .upsert_bookmark(&Bookmark {
+                url: "https://example.com".to_string(),
+                title: "Example".to_string(),
+                unread: true,
+                date_modified: "2025-01-02T00:00:00Z".to_string(),
+                ..bookmark(7)
+            })
+            .unwrap();
+        OfflineClient::new(client, mirror).unwrap()
+    }
+
+    #[test]
+    fn queues_mutations_and_applies_them_locally() {
+        let offline = client();
+        let created = offline
+            .create_bookmark(CreateBookmarkBody {
+                url: "https://rust-lang.org".to_string(),
+                tag_names: Some(vec!["rust".to_string()]),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(created.id, -1);
+        assert_eq!(
+            offline.get_bookmark(-1).unwrap().unwrap().tag_names,
+            vec!["rust"]
+        );
+
+        let updated = offline
+            .update_bookmark(
+                7,
+                UpdateBookmarkBody {
+                    title: Some("Changed".to_string()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(updated.title, "Changed");
+        offline.archive_bookmark(7).unwrap();
+        assert!(offline.get_bookmark(7).unwrap().unwrap().is_archived);
+        offline.delete_bookmark(-1).unwrap();
+        assert_eq!(offline.get_bookmark(-1).unwrap(), None);
+        assert!(matches!(
+            offline.delete_bookmark(99),
+            Err(LinkDingError::NotCached(99))
+        ));
+
+        let pending = offline.pending().unwrap();
+        let queued: Vec<(i32, Option<&str>)> = pending
+            .iter()
+            .map(|entry| (entry.bookmark_id, entry.expected_date_modified.as_deref()))
+            .collect();
+        assert_eq!(
+            queued,
+            vec![
+                (-1, None),
+                (7, Some("2025-01-02T00:00:00Z")),
+                (7, None),
+                (-1, None),
+            ]
+        );
+        assert_eq!(pending[2].mutation, Mutation::Archive);
+
+        offline.discard(pending[0].seq).unwrap();
+        assert_eq!(offline.pending().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn keeps_the_journal_while_offline() {
+        let mut offline = client();
+        offline.unarchive_bookmark(7).unwrap();
+        let report = offline.sync().unwrap();
+        assert!(report.offline);
+        assert!(report.applied.is_empty());
+        assert!(report.refreshed.is_none());
+        assert_eq!(offline.pending().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn failed_journal_writes_leave_the_cache_untouched() {
+        let offline = client();
+        offline
+            .mirror()
+            .connection()
+            .execute_batch(
+                "CREATE TEMP TRIGGER journal_full BEFORE INSERT ON journal \
+                BEGIN SELECT RAISE(ABORT, 'journal full'); END;",
+            )
+            .unwrap();
+        let body = UpdateBookmarkBody {
+            title: Some("Changed".to_string()),
+            ..Default::default()
+        };
+        assert!(offline.update_bookmark(7, body).is_err());
+        assert_eq!(offline.get_bookmark(7).unwrap().unwrap().title, "Example");
+        assert!(offline.pending().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,513 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bookmark_assets::{BookmarkAssetStatus, BookmarkAssetType},
+    extract::extract_article,
+    Bookmark, LinkDingClient, LinkDingError, ListBookmarksArgs,
+};
+
+const TITLE_WEIGHT: f32 = 3.0;
+const TAG_WEIGHT: f32 = 3.0;
+const DESCRIPTION_WEIGHT: f32 = 2.0;
+const NOTES_WEIGHT: f32 = 1.5;
+const URL_WEIGHT: f32 = 1.0;
+const SNAPSHOT_WEIGHT: f32 = 1.0;
+
+// BM25 parameters
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+const SNIPPET_WORDS_BEFORE: usize = 8;
+const SNIPPET_WORDS: usize = 30;
+
+/// A bookmark as stored in the search index
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedDocument {
+    pub bookmark_id: i32,
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    pub notes: String,
+    pub tag_names: Vec<String>,
+    pub date_modified: String,
+    /// The snapshot asset the text was extracted from
+    pub snapshot_asset_id: Option<i32>,
+    pub snapshot_text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub bookmark_id: i32,
+    pub url: String,
+    pub title: String,
+    pub score: f32,
+    /// An excerpt around the first match, from the snapshot text if possible
+    pub snippet: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SearchIndexUpdate {
+    /// Bookmarks that were added or re-indexed
+    pub indexed: Vec<i32>,
+    pub unchanged: usize,
+    /// Bookmarks that were removed since they no longer exist on the server
+    pub removed: Vec<i32>,
+    /// Bookmarks whose snapshot couldn't be listed, downloaded or extracted,
+    /// they are retried on the next update
+    pub failed: Vec<(i32, LinkDingError)>,
+}
+
+/// Weighted term frequencies of a single document
+#[derive(Debug, Clone, Default)]
+struct DocumentTerms {
+    frequencies: HashMap<String, f32>,
+    length: f32,
+}
+
+/// The on-disk format of the index, terms are rebuilt when loading
+#[derive(Serialize, Deserialize)]
+struct StoredIndex {
+    documents: Vec<IndexedDocument>,
+}
+
+/// A local full-text search index over bookmarks and their snapshots
+///
+/// Bookmarks are matched on title, description, notes, tags, URL and the
+/// readable article text of their latest snapshot. Results are ranked with BM25,
+/// giving matches in titles and tags more weight than matches in snapshots.
+/// Keep the index current with [`LinkDingClient::update_search_index`].
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+    documents: BTreeMap<i32, IndexedDocument>,
+    terms: HashMap<i32, DocumentTerms>,
+    postings: HashMap<String, BTreeSet<i32>>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        SearchIndex::default()
+    }
+
+    /// Load an index saved with [`SearchIndex::save`], empty if the file doesn't exist
+    pub fn load(path: &Path) -> Result<SearchIndex, LinkDingError> {
+        if !path.exists() {
+            return Ok(SearchIndex::new());
+        }
+        let stored: StoredIndex = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        let mut index = SearchIndex::new();
+        for document in stored.documents {
+            index.insert(document);
+        }
+        Ok(index)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), LinkDingError> {
+        let stored = StoredIndex {
+            documents: self.documents.values().cloned().collect(),
+        };
+        let mut temp_path = path.as_os_str().to_os_string();
+        temp_path.push(".tmp");
+        serde_json::to_writer(BufWriter::new(File::create(&temp_path)?), &stored)?;
+        fs::rename(temp_path, path)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    pub fn get(&self, bookmark_id: i32) -> Option<&IndexedDocument> {
+        self.documents.get(&bookmark_id)
+    }
+
+    /// Add or update a bookmark, keeping any snapshot text already indexed for it
+    pub fn index_bookmark(&mut self, bookmark: &Bookmark) {
+        let (snapshot_asset_id, snapshot_text) = self
+            .documents
+            .get(&bookmark.id)
+            .map(|d| (d.snapshot_asset_id, d.snapshot_text.clone()))
+            .unwrap_or_default();
+        self.insert(IndexedDocument {
+            bookmark_id: bookmark.id,
+            url: bookmark.url.clone(),
+            title: bookmark.title.clone(),
+            description: bookmark.description.clone(),
+            notes: bookmark.notes.clone(),
+            tag_names: bookmark.tag_names.clone(),
+            date_modified: bookmark.date_modified.clone(),
+            snapshot_asset_id,
+            snapshot_text,
+        });
+    }
+
+    /// Index the text of a snapshot asset for an already indexed bookmark
+    ///
+    /// The snapshot is the content as downloaded with
+    /// [`LinkDingClient::download_bookmark_asset`], gzipped or not. Returns
+    /// `false` if the bookmark isn't in the index.
+    pub fn index_snapshot(
+        &mut self,
+        bookmark_id: i32,
+        asset_id: i32,
+        content: &[u8],
+    ) -> Result<bool, LinkDingError> {
+        let Some(mut document) = self.documents.get(&bookmark_id).cloned() else {
+            return Ok(false);
+        };
+        document.snapshot_asset_id = Some(asset_id);
+        document.snapshot_text = extract_article(content)?.text;
+        self.insert(document);
+        Ok(true)
+    }
+
+    /// Remove the snapshot text of a bookmark whose snapshot no longer exists
+    pub fn clear_snapshot(&mut self, bookmark_id: i32) {
+        if let Some(mut document) = self.documents.get(&bookmark_id).cloned() {
+            document.snapshot_asset_id = None;
+            document.snapshot_text.clear();
+            self.insert(document);
+        }
+    }
+
+    pub fn remove(&mut self, bookmark_id: i32) -> Option<IndexedDocument> {
+        if let Some(terms) = self.terms.remove(&bookmark_id) {
+            for term in terms.frequencies.keys() {
+                if let Some(ids) = self.postings.get_mut(term) {
+                    ids.remove(&bookmark_id);
+                    if ids.is_empty() {
+                        self.postings.remove(term);
+                    }
+                }
+            }
+        }
+        self.documents.remove(&bookmark_id)
+    }
+
+    /// Find bookmarks containing all words of the query, best matches first
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let query_terms: BTreeSet<String> = tokenize(query).collect();
+        if query_terms.is_empty() {
+            return Vec::new();
+        }
+
+        let mut candidates: Option<BTreeSet<i32>> = None;
+        for term in &query_terms {
+            let ids = self.postings.get(term).cloned().unwrap_or_default();
+            candidates = Some(match candidates {
+                Some(candidates) => candidates.intersection(&ids).copied().collect(),
+                None => ids,
+            });
+        }
+
+        let document_count = self.documents.len() as f32;
+        let average_length = self.terms.values().map(|t| t.length).sum::<f32>() / document_count;
+        let mut hits: Vec<SearchHit> = candidates
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| {
+                let terms = &self.terms[&id];
+                let score = query_terms
+                    .iter()
+                    .map(|term| {
+                        let matching = self.postings[term].len() as f32;
+                        let idf = (1.0 + (document_count - matching + 0.5) / (matching + 0.5)).ln();
+                        let frequency = terms.frequencies[term];
+                        let normalization = 1.0 - B + B * terms.length / average_length.max(1.0);
+                        idf * frequency * (K1 + 1.0) / (frequency + K1 * normalization)
+                    })
+                    .sum();
+                let document = &self.documents[&id];
+                SearchHit {
+                    bookmark_id: id,
+                    url: document.url.clone(),
+                    title: document.title.clone(),
+                    score,
+                    snippet: snippet(document, &query_terms),
+                }
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then(a.bookmark_id.cmp(&b.bookmark_id))
+        });
+        hits.truncate(limit);
+        hits
+    }
+
+    fn insert(&mut self, document: IndexedDocument) {
+        self.remove(document.bookmark_id);
+
+        let mut terms = DocumentTerms::default();
+        let tags = document.tag_names.join(" ");
+        for (text, weight) in [
+            (document.title.as_str(), TITLE_WEIGHT),
+            (tags.as_str(), TAG_WEIGHT),
+            (document.description.as_str(), DESCRIPTION_WEIGHT),
+            (document.notes.as_str(), NOTES_WEIGHT),
+            (document.url.as_str(), URL_WEIGHT),
+            (document.snapshot_text.as_str(), SNAPSHOT_WEIGHT),
+        ] {
+            for term in tokenize(text) {
+                *terms.frequencies.entry(term).or_default() += weight;
+                terms.length += weight;
+            }
+        }
+        for term in terms.frequencies.keys() {
+            self.postings
+                .entry(term.clone())
+                .or_default()
+                .insert(document.bookmark_id);
+        }
+        self.terms.insert(document.bookmark_id, terms);
+        self.documents.insert(document.bookmark_id, document);
+    }
+}
+
+/// Keeping a search index up to date
+impl LinkDingClient {
+    /// Bring a search index up to date with the bookmarks on the server
+    ///
+    /// All bookmarks, including archived ones, are checked. Bookmarks that
+    /// were modified or got a new completed snapshot are re-indexed, only
+    /// downloading snapshots that aren't indexed yet. Bookmarks that no longer
+    /// exist are removed from the index.
+    ///
+    /// A snapshot that can't be listed, downloaded or extracted is reported
+    /// in [`SearchIndexUpdate::failed`] and doesn't stop the update. An error
+    /// listing the bookmarks is returned, with the bookmarks indexed so far
+    /// kept in `index`.
+    pub fn update_search_index(
+        &self,
+        index: &mut SearchIndex,
+    ) -> Result<SearchIndexUpdate, LinkDingError> {
+        let mut update = SearchIndexUpdate::default();
+        let mut seen = BTreeSet::new();
+        let bookmarks = self
+            .iter_bookmarks(ListBookmarksArgs::default())
+            .chain(self.iter_archived_bookmarks(ListBookmarksArgs::default()));
+        for bookmark in bookmarks {
+            let bookmark = bookmark?;
+            seen.insert(bookmark.id);
+            let snapshot_id = match self.list_bookmark_assets(bookmark.id) {
+                Ok(assets) => assets
+                    .results
+                    .into_iter()
+                    .filter(|asset| {
+                        asset.asset_type == BookmarkAssetType::Snapshot
+                            && asset.status == BookmarkAssetStatus::Complete
+                    })
+                    .map(|asset| asset.id)
+                    .max(),
+                Err(error) => {
+                    index.index_bookmark(&bookmark);
+                    update.failed.push((bookmark.id, error));
+                    continue;
+                }
+            };
+
+            let indexed = index.get(bookmark.id);
+            let bookmark_changed = match indexed {
+                Some(document) => document.date_modified != bookmark.date_modified,
+                None => true,
+            };
+            let snapshot_changed = match indexed {
+                Some(document) => document.snapshot_asset_id != snapshot_id,
+                None => snapshot_id.is_some(),
+            };
+            if !bookmark_changed && !snapshot_changed {
+                update.unchanged += 1;
+                continue;
+            }
+
+            index.index_bookmark(&bookmark);
+            match snapshot_id.filter(|_| snapshot_changed) {
+                Some(snapshot_id) => {
+                    let indexed = self
+                        .download_bookmark_asset(bookmark.id, snapshot_id)
+                        .and_then(|content| {
+                            index.index_snapshot(bookmark.id, snapshot_id, &content)
+                        });
+                    if let Err(error) = indexed {
+                        update.failed.push((bookmark.id, error));
+                        continue;
+                    }
+                }
+                None if snapshot_changed => index.clear_snapshot(bookmark.id),
+                None => {}
+            }
+            update.indexed.push(bookmark.id);
+        }
+
+        let removed: Vec<i32> = index
+            .documents
+            .keys()
+            .filter(|id| !seen.contains(id))
+            .copied()
+            .collect();
+        for id in removed {
+            index.remove(id);
+            update.removed.push(id);
+        }
+        Ok(update)
+    }
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+}
+
+fn snippet(document: &IndexedDocument, query_terms: &BTreeSet<String>) -> String {
+    for text in [
+        &document.snapshot_text,
+        &document.description,
+        &document.notes,
+    ] {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let position = words
+            .iter()
+            .position(|word| tokenize(word).any(|term| query_terms.contains(&term)));
+        if let Some(position) = position {
+            let start = position.saturating_sub(SNIPPET_WORDS_BEFORE);
+            let end = (start + SNIPPET_WORDS).min(words.len());
+            let mut snippet = words[start..end].join(" ");
+            if start > 0 {
+                snippet.insert_str(0, "… ");
+            }
+            if end < words.len() {
+                snippet.push_str(" …");
+            }
+            return snippet;
+        }
+    }
+    document.description.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bookmark_assets::BookmarkAsset,
+        test_util::{self, page, serve_api, Route},
+    };
+
+    fn document(id: i32, title: &str, snapshot_text: &str) -> IndexedDocument {
+        IndexedDocument {
+            bookmark_id: id,
+            url: format!("https://example.com/{}", id),
+            title: title.to_string(),
+            description: "".to_string(),
+            notes: "".to_string(),
+            tag_names: vec![],
+            date_modified: "".to_string(),
+            snapshot_asset_id: None,
+            snapshot_text: snapshot_text.to_string(),
+        }
+    }
+
+    fn index() -> SearchIndex {
+        let mut index = SearchIndex::new();
+        index.insert(document(
+            1,
+            "Cooking pasta",
+            "Boil water and add the pasta.",
+        ));
+        index.insert(document(
+            2,
+            "Rust ownership",
+            "Ownership is how Rust manages memory without a garbage collector.",
+        ));
+        index.insert(document(3, "Memory in C", "Rust is mentioned once here."));
+        index
+    }
+
+    #[test]
+    fn title_matches_rank_higher() {
+        let hits = index().search("rust", 10);
+        let ids: Vec<i32> = hits.iter().map(|hit| hit.bookmark_id).collect();
+        assert_eq!(ids, vec![2, 3]);
+    }
+
+    #[test]
+    fn all_query_words_must_match() {
+        let hits = index().search("rust garbage", 10);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].bookmark_id, 2);
+        assert!(hits[0].snippet.contains("garbage collector"));
+    }
+
+    #[test]
+    fn removed_documents_are_not_found() {
+        let mut index = index();
+        index.remove(1);
+        assert!(index.search("pasta", 10).is_empty());
+        assert_eq!(index.len(), 2);
+    }
+
+    #[test]
+    fn update_continues_past_failed_snapshots() {
+        let bookmark = |id: i32| Bookmark {
+            title: format!("Bookmark {}", id),
+            ..test_util::bookmark(id)
+        };
+        let snapshot = BookmarkAsset {
+            id: 7,
+            bookmark: 3,
+            asset_type: BookmarkAssetType::Snapshot,
+            date_created: "2025-01-01T00:00:00Z".to_string(),
+            content_type: "text/html".to_string(),
+            display_name: "Snapshot".to_string(),
+            status: BookmarkAssetStatus::Complete,
+            file_size: None,
+        };
+        let (url, _) = serve_api(vec![
+            Route::ok(
+                "GET /api/bookmarks/",
+                page(&[bookmark(1), bookmark(2), bookmark(3)]),
+            ),
+            Route::ok("GET /api/bookmarks/archived/", page::<Bookmark>(&[])),
+            Route {
+                request: "GET /api/bookmarks/1/assets/",
+                status: 500,
+                body: "".to_string(),
+            },
+            Route::ok("GET /api/bookmarks/2/assets/", page::<BookmarkAsset>(&[])),
+            Route::ok("GET /api/bookmarks/3/assets/", page(&[snapshot])),
+            Route::ok(
+                "GET /api/bookmarks/3/assets/7/download/",
+                "<html><body><p>Fresh snapshot text</p></body></html>",
+            ),
+        ]);
+        let client = LinkDingClient::new(&url, "token");
+        let mut index = SearchIndex::new();
+        index.insert(IndexedDocument {
+            snapshot_asset_id: Some(9),
+            date_modified: "2025-01-01T00:00:00Z".to_string(),
+            ..document(2, "Bookmark 2", "Deleted snapshot text")
+        });
+
+        let update = client.update_search_index(&mut index).unwrap();
+        assert_eq!(update.indexed, vec![2, 3]);
+        assert_eq!(
+            update.failed.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![1]
+        );
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.get(2).unwrap().snapshot_asset_id, None);
+        assert!(index.search("deleted", 10).is_empty());
+        assert_eq!(index.search("fresh", 10)[0].bookmark_id, 3);
+    }
+}
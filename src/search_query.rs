@@ -0,0 +1,555 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    tag_changes::contains_tag, Bookmark, LinkDingError, ListBookmarksArgs, TagSearchMethod,
+};
+
+/// Special filters written as `!name` in linkding's search syntax
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SearchSpecial {
+    /// Bookmarks without tags
+    Untagged,
+    Unread,
+    Shared,
+}
+
+impl SearchSpecial {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchSpecial::Untagged => "untagged",
+            SearchSpecial::Unread => "unread",
+            SearchSpecial::Shared => "shared",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<SearchSpecial> {
+        match name.to_lowercase().as_str() {
+            "untagged" => Some(SearchSpecial::Untagged),
+            "unread" => Some(SearchSpecial::Unread),
+            "shared" => Some(SearchSpecial::Shared),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SearchTerm {
+    /// A single word matched against title, description, notes and URL
+    Word(String),
+    /// Several words matched as one, written in double quotes
+    Phrase(String),
+    /// A tag, written as `#name`
+    Tag(String),
+    Special(SearchSpecial),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SearchClause {
+    pub term: SearchTerm,
+    /// Only match bookmarks the term does not match
+    pub negated: bool,
+}
+
+/// Which parts of linkding's search syntax a server understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SearchSyntax {
+    /// Words, `#tags`, `!untagged` and `!unread` separated by spaces, the
+    /// syntax of linkding v1.36 this crate is tested with
+    #[default]
+    Basic,
+    /// Also quoted phrases, `!shared` and negation with `not`, for servers
+    /// whose search supports them. linkding v1.36 reads them as plain words.
+    Extended,
+}
+
+/// A search in linkding's query syntax
+///
+/// All clauses have to match. Build one with the builder methods or parse
+/// one with [`SearchQuery::parse`]. Render it for a server with
+/// [`SearchQuery::render`] or pass it to [`ListBookmarksArgs`] with
+/// `try_into()`, which only accepts the [`SearchSyntax::Basic`] syntax.
+/// `to_string()` renders the [`SearchSyntax::Extended`] syntax.
+///
+/// ```
+/// use linkding::{ListBookmarksArgs, SearchQuery, SearchSyntax};
+///
+/// let query = SearchQuery::new().tag("rust").word("async").unread();
+/// let args: ListBookmarksArgs = query.clone().try_into().unwrap();
+/// assert_eq!(args.query.as_deref(), Some("#rust async !unread"));
+///
+/// let query = query.without_tag("old");
+/// assert!(query.render(SearchSyntax::Basic).is_err());
+/// assert_eq!(query.render(SearchSyntax::Extended).unwrap(), "#rust async !unread not #old");
+/// ```
+///
+/// Alternatives with `or` and grouping with parentheses can't be expressed,
+/// [`SearchQuery::parse`] rejects them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct SearchQuery {
+    pub clauses: Vec<SearchClause>,
+}
+
+const KEYWORDS: [&str; 3] = ["and", "or", "not"];
+
+impl SearchQuery {
+    pub fn new() -> Self {
+        SearchQuery::default()
+    }
+
+    /// Require a term to match
+    pub fn term(mut self, term: SearchTerm) -> Self {
+        self.clauses.push(SearchClause {
+            term,
+            negated: false,
+        });
+        self
+    }
+
+    /// Require a term not to match
+    pub fn exclude(mut self, term: SearchTerm) -> Self {
+        self.clauses.push(SearchClause {
+            term,
+            negated: true,
+        });
+        self
+    }
+
+    pub fn word(self, word: &str) -> Self {
+        self.term(SearchTerm::Word(word.to_string()))
+    }
+
+    pub fn phrase(self, phrase: &str) -> Self {
+        self.term(SearchTerm::Phrase(phrase.to_string()))
+    }
+
+    /// Require a tag, a leading `#` is optional
+    pub fn tag(self, tag: &str) -> Self {
+        self.term(SearchTerm::Tag(strip_hash(tag)))
+    }
+
+    /// Exclude bookmarks with a tag, a leading `#` is optional
+    pub fn without_tag(self, tag: &str) -> Self {
+        self.exclude(SearchTerm::Tag(strip_hash(tag)))
+    }
+
+    pub fn untagged(self) -> Self {
+        self.term(SearchTerm::Special(SearchSpecial::Untagged))
+    }
+
+    pub fn unread(self) -> Self {
+        self.term(SearchTerm::Special(SearchSpecial::Unread))
+    }
+
+    pub fn shared(self) -> Self {
+        self.term(SearchTerm::Special(SearchSpecial::Shared))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clauses.is_empty()
+    }
+
+    /// Parse a query in linkding's syntax
+    ///
+    /// Terms are separated by whitespace and all have to match. A term is
+    /// negated with a `not` in front of it or a leading `-`, an `and` between
+    /// terms is accepted but not needed. `or` and parentheses aren't
+    /// supported.
+    pub fn parse(query: &str) -> Result<SearchQuery, LinkDingError> {
+        let mut clauses = Vec::new();
+        let mut negated = false;
+        for token in tokenize(query)? {
+            let word = match token {
+                Token::Phrase(phrase) => {
+                    clauses.push(SearchClause {
+                        term: SearchTerm::Phrase(phrase),
+                        negated,
+                    });
+                    negated = false;
+                    continue;
+                }
+                Token::Word(word) => word,
+            };
+            match word.to_lowercase().as_str() {
+                "and" if !negated && !clauses.is_empty() => continue,
+                "not" => {
+                    negated = !negated;
+                    continue;
+                }
+                "and" | "or" => {
+                    return Err(invalid(format!("unsupported or misplaced `{}`", word)));
+                }
+                _ => {}
+            }
+            if word.starts_with('(') || word.ends_with(')') {
+                return Err(invalid("parentheses are not supported".to_string()));
+            }
+            let (word, dash) = match word.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => (rest.to_string(), true),
+                _ => (word, false),
+            };
+            let term = if let Some(tag) = word.strip_prefix('#') {
+                SearchTerm::Tag(tag.to_string())
+            } else if let Some(name) = word.strip_prefix('!') {
+                SearchTerm::Special(
+                    SearchSpecial::from_name(name)
+                        .ok_or_else(|| invalid(format!("unknown filter `{}`", word)))?,
+                )
+            } else {
+                SearchTerm::Word(word)
+            };
+            clauses.push(SearchClause {
+                term,
+                negated: negated != dash,
+            });
+            negated = false;
+        }
+        if negated {
+            return Err(invalid("`not` without a term".to_string()));
+        }
+        let query = SearchQuery { clauses };
+        query.validate()?;
+        Ok(query)
+    }
+
+    /// Render the query in the syntax a server understands
+    ///
+    /// Fails if the query is invalid or uses parts of the syntax the server
+    /// doesn't support.
+    pub fn render(&self, syntax: SearchSyntax) -> Result<String, LinkDingError> {
+        self.validate()?;
+        if syntax == SearchSyntax::Basic {
+            let extended = self.clauses.iter().find(|clause| {
+                clause.negated
+                    || matches!(
+                        clause.term,
+                        SearchTerm::Phrase(_) | SearchTerm::Special(SearchSpecial::Shared)
+                    )
+            });
+            if let Some(clause) = extended {
+                return Err(invalid(format!(
+                    "`{}` needs the extended search syntax",
+                    ClauseDisplay(clause)
+                )));
+            }
+        }
+        Ok(self.to_string())
+    }
+
+    /// Check that the query renders to valid syntax and isn't contradictory
+    pub fn validate(&self) -> Result<(), LinkDingError> {
+        let mut tagged = false;
+        let mut untagged = false;
+        for clause in &self.clauses {
+            match &clause.term {
+                SearchTerm::Word(word) => {
+                    if word.is_empty() || word.contains(char::is_whitespace) || word.contains('"') {
+                        return Err(invalid(format!(
+                            "`{}` is not a single word, use a phrase",
+                            word
+                        )));
+                    }
+                    if word.starts_with(['#', '!', '-', '('])
+                        || word.ends_with(')')
+                        || KEYWORDS.contains(&word.to_lowercase().as_str())
+                    {
+                        return Err(invalid(format!(
+                            "`{}` would be read as syntax, use a phrase",
+                            word
+                        )));
+                    }
+                }
+                SearchTerm::Phrase(phrase) => {
+                    if phrase.trim().is_empty() || phrase.contains('"') {
+                        return Err(invalid(format!("invalid phrase `{}`", phrase)));
+                    }
+                }
+                SearchTerm::Tag(tag) => {
+                    if tag.is_empty() || tag.contains(char::is_whitespace) || tag.contains('"') {
+                        return Err(invalid(format!("invalid tag `{}`", tag)));
+                    }
+                    tagged |= !clause.negated;
+                }
+                SearchTerm::Special(SearchSpecial::Untagged) => untagged |= !clause.negated,
+                SearchTerm::Special(_) => {}
+            }
+        }
+        for (i, clause) in self.clauses.iter().enumerate() {
+            let opposite = self.clauses[..i]
+                .iter()
+                .any(|other| other.term == clause.term && other.negated != clause.negated);
+            if opposite {
+                return Err(invalid(format!(
+                    "`{}` is both required and excluded",
+                    TermDisplay(&clause.term)
+                )));
+            }
+        }
+        if tagged && untagged {
+            return Err(invalid(
+                "a tag can't be required together with !untagged".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Evaluating queries locally, following linkding's documented search rules
+impl SearchQuery {
+    /// Whether a bookmark matches the query
+    ///
+    /// Words and phrases match case-insensitively anywhere in the title,
+    /// description, notes or URL. With [`TagSearchMethod::Lax`] they also
+    /// match bookmarks with a tag of the same name. Tags match by name,
+    /// ignoring case.
+    pub fn matches(&self, bookmark: &Bookmark, tag_search: TagSearchMethod) -> bool {
+        self.clauses
+            .iter()
+            .all(|clause| clause.term.matches(bookmark, tag_search) != clause.negated)
+    }
+
+    /// The bookmarks matching the query, in their original order
+    pub fn filter<'a>(
+        &'a self,
+        bookmarks: impl IntoIterator<Item = &'a Bookmark>,
+        tag_search: TagSearchMethod,
+    ) -> impl Iterator<Item = &'a Bookmark> {
+        bookmarks
+            .into_iter()
+            .filter(move |bookmark| self.matches(bookmark, tag_search))
+    }
+}
+
+impl SearchTerm {
+    fn matches(&self, bookmark: &Bookmark, tag_search: TagSearchMethod) -> bool {
+        match self {
+            SearchTerm::Word(text) | SearchTerm::Phrase(text) => {
+                let text = text.to_lowercase();
+                let in_fields = [
+                    &bookmark.title,
+                    &bookmark.description,
+                    &bookmark.notes,
+                    &bookmark.url,
+                ]
+                .iter()
+                .any(|field| field.to_lowercase().contains(&text));
+                in_fields || (tag_search == TagSearchMethod::Lax && has_tag(bookmark, &text))
+            }
+            SearchTerm::Tag(tag) => has_tag(bookmark, tag),
+            SearchTerm::Special(SearchSpecial::Untagged) => bookmark.tag_names.is_empty(),
+            SearchTerm::Special(SearchSpecial::Unread) => bookmark.unread,
+            SearchTerm::Special(SearchSpecial::Shared) => bookmark.shared,
+        }
+    }
+}
+
+fn has_tag(bookmark: &Bookmark, tag: &str) -> bool {
+    contains_tag(&bookmark.tag_names, tag)
+}
+
+/// Renders the [`SearchSyntax::Extended`] syntax
+impl fmt::Display for SearchQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, clause) in self.clauses.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}", ClauseDisplay(clause))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SearchQuery {
+    type Err = LinkDingError;
+
+    fn from_str(query: &str) -> Result<Self, Self::Err> {
+        SearchQuery::parse(query)
+    }
+}
+
+/// Uses the [`SearchSyntax::Basic`] syntax, see [`SearchQuery::render`]
+impl TryFrom<SearchQuery> for ListBookmarksArgs {
+    type Error = LinkDingError;
+
+    fn try_from(query: SearchQuery) -> Result<Self, Self::Error> {
+        Ok(ListBookmarksArgs {
+            query: Some(query.render(SearchSyntax::Basic)?),
+            ..Default::default()
+        })
+    }
+}
+
+struct ClauseDisplay<'a>(&'a SearchClause);
+
+impl fmt::Display for ClauseDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.negated {
+            f.write_str("not ")?;
+        }
+        write!(f, "{}", TermDisplay(&self.0.term))
+    }
+}
+
+struct TermDisplay<'a>(&'a SearchTerm);
+
+impl fmt::Display for TermDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            SearchTerm::Word(word) => f.write_str(word),
+            SearchTerm::Phrase(phrase) => write!(f, "\"{}\"", phrase),
+            SearchTerm::Tag(tag) => write!(f, "#{}", tag),
+            SearchTerm::Special(special) => write!(f, "!{}", special.as_str()),
+        }
+    }
+}
+
+enum Token {
+    Word(String),
+    Phrase(String),
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, LinkDingError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut phrase = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => phrase.push(c),
+                    None => return Err(invalid("unterminated quote".to_string())),
+                }
+            }
+            tokens.push(Token::Phrase(phrase));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+fn strip_hash(tag: &str) -> String {
+    tag.strip_prefix('#').unwrap_or(tag).to_string()
+}
+
+fn invalid(message: String) -> LinkDingError {
+    LinkDingError::InvalidSearchQuery(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_builder_queries() {
+        let query = SearchQuery::new()
+            .word("rust")
+            .tag("#lang")
+            .without_tag("old")
+            .phrase("error handling")
+            .unread()
+            .shared();
+        assert_eq!(
+            query.render(SearchSyntax::Extended).unwrap(),
+            r#"rust #lang not #old "error handling" !unread !shared"#
+        );
+        assert!(ListBookmarksArgs::try_from(query).is_err());
+
+        let query = SearchQuery::new().word("rust").tag("lang").unread();
+        let args = ListBookmarksArgs::try_from(query).unwrap();
+        assert_eq!(args.query.as_deref(), Some("rust #lang !unread"));
+        for query in [
+            SearchQuery::new().without_tag("old"),
+            SearchQuery::new().phrase("error handling"),
+            SearchQuery::new().shared(),
+        ] {
+            assert!(query.render(SearchSyntax::Basic).is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn parses_queries() {
+        let query = SearchQuery::parse(r#"rust and -#old NOT "error handling" !Unread"#).unwrap();
+        assert_eq!(
+            query,
+            SearchQuery::new()
+                .word("rust")
+                .without_tag("old")
+                .exclude(SearchTerm::Phrase("error handling".to_string()))
+                .unread()
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"rust not #old not "error handling" !unread"#
+        );
+        assert_eq!(SearchQuery::parse(&query.to_string()).unwrap(), query);
+        assert!(SearchQuery::parse("  ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_queries() {
+        for query in [
+            "rust or go",
+            "(rust go)",
+            "\"unterminated",
+            "!starred",
+            "rust not",
+            "#",
+            "#rust !untagged",
+            "#rust not #rust",
+        ] {
+            assert!(SearchQuery::parse(query).is_err(), "{}", query);
+        }
+        assert!(SearchQuery::new().word("two words").validate().is_err());
+        assert!(SearchQuery::new().word("not").validate().is_err());
+        assert!(SearchQuery::new().phrase("not").validate().is_ok());
+        assert!(SearchQuery::new().tag("with space").validate().is_err());
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Examples {
+        bookmarks: Vec<Bookmark>,
+        cases: Vec<Example>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Example {
+        query: String,
+        tag_search: TagSearchMethod,
+        expected: Vec<i32>,
+    }
+
+    /// Hand-written examples of the syntax described in linkding's search
+    /// documentation. They weren't recorded from a linkding instance, so they
+    /// show how queries are meant to be read but can't prove the server
+    /// agrees.
+    #[test]
+    fn filters_examples() {
+        let fixture: Examples =
+            serde_json::from_str(include_str!("../tests/fixtures/search_examples.json")).unwrap();
+        for case in fixture.cases {
+            let query = SearchQuery::parse(&case.query).unwrap();
+            let ids: Vec<i32> = query
+                .filter(&fixture.bookmarks, case.tag_search)
+                .map(|bookmark| bookmark.id)
+                .collect();
+            assert_eq!(
+                ids, case.expected,
+                "{:?} with {:?}",
+                case.query, case.tag_search
+            );
+        }
+    }
+}
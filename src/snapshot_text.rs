@@ -0,0 +1,39 @@
+use std::io::{self, Read};
+
+use flate2::read::GzDecoder;
+use scraper::Html;
+
+/// Decompress snapshot content if it is gzipped, as linkding stores snapshots
+pub(crate) fn decompress(content: &[u8]) -> io::Result<Vec<u8>> {
+    if !content.starts_with(&[0x1f, 0x8b]) {
+        return Ok(content.to_vec());
+    }
+    let mut decompressed = Vec::new();
+    GzDecoder::new(content).read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Parse a snapshot asset's HTML, decompressing it first if needed
+pub(crate) fn parse_snapshot(content: &[u8]) -> io::Result<Html> {
+    let html = decompress(content)?;
+    Ok(Html::parse_document(&String::from_utf8_lossy(&html)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    #[test]
+    fn decompresses_gzipped_snapshots() {
+        let html = b"<html><body><p>Hello</p></body></html>";
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(html).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(decompress(&compressed).unwrap(), html);
+        assert_eq!(decompress(html).unwrap(), html);
+    }
+}
@@ -0,0 +1,224 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Bookmark, BulkOptions, BulkReport, LinkDingClient, LinkDingError, ListBookmarksArgs,
+    UpdateBookmarkBody,
+};
+
+/// The tags of a single bookmark before and after a tag change
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TagChange {
+    pub bookmark_id: i32,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+/// A previewable set of tag changes across bookmarks
+///
+/// Plans are created by [`LinkDingClient::rename_tag`],
+/// [`LinkDingClient::merge_tags`] and [`LinkDingClient::remove_tag_everywhere`]
+/// without changing anything on the server. Apply them with
+/// [`LinkDingClient::apply_tag_changes`]. A plan can be serialized and kept as
+/// an undo record, [`TagChangePlan::reversed`] then gives the plan restoring
+/// the previous tags.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct TagChangePlan {
+    pub changes: Vec<TagChange>,
+}
+
+impl TagChangePlan {
+    /// IDs of the bookmarks the plan would change
+    pub fn affected_ids(&self) -> Vec<i32> {
+        self.changes
+            .iter()
+            .map(|change| change.bookmark_id)
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The plan undoing this one
+    pub fn reversed(&self) -> TagChangePlan {
+        TagChangePlan {
+            changes: self
+                .changes
+                .iter()
+                .map(|change| TagChange {
+                    bookmark_id: change.bookmark_id,
+                    before: change.after.clone(),
+                    after: change.before.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    fn from_bookmarks<F>(bookmarks: Vec<Bookmark>, retag: F) -> TagChangePlan
+    where
+        F: Fn(&[String]) -> Vec<String>,
+    {
+        TagChangePlan {
+            changes: bookmarks
+                .into_iter()
+                .filter_map(|bookmark| {
+                    let after = retag(&bookmark.tag_names);
+                    if after == bookmark.tag_names {
+                        return None;
+                    }
+                    Some(TagChange {
+                        bookmark_id: bookmark.id,
+                        before: bookmark.tag_names,
+                        after,
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Tag operations across all bookmarks, which the linkding API doesn't offer
+impl LinkDingClient {
+    /// Plan renaming the tag `from` to `to` on all bookmarks
+    pub fn rename_tag(&self, from: &str, to: &str) -> Result<TagChangePlan, LinkDingError> {
+        self.merge_tags(&[from], to)
+    }
+
+    /// Plan replacing the tags in `from` with the tag `into` on all bookmarks
+    pub fn merge_tags(&self, from: &[&str], into: &str) -> Result<TagChangePlan, LinkDingError> {
+        let bookmarks = self.bookmarks_tagged(from)?;
+        Ok(TagChangePlan::from_bookmarks(bookmarks, |tags| {
+            merge_tag_names(tags, from, into)
+        }))
+    }
+
+    /// Plan removing the tag from all bookmarks
+    pub fn remove_tag_everywhere(&self, tag: &str) -> Result<TagChangePlan, LinkDingError> {
+        let bookmarks = self.bookmarks_tagged(&[tag])?;
+        Ok(TagChangePlan::from_bookmarks(bookmarks, |tags| {
+            tags.iter().filter(|t| !tag_eq(t, tag)).cloned().collect()
+        }))
+    }
+
+    /// Apply the tag changes of a plan, setting each bookmark's tags to the planned ones
+    pub fn apply_tag_changes(
+        &self,
+        plan: &TagChangePlan,
+        options: &BulkOptions,
+    ) -> BulkReport<i32, Bookmark> {
+        let updates = plan
+            .changes
+            .iter()
+            .map(|change| {
+                let body = UpdateBookmarkBody {
+                    tag_names: Some(change.after.clone()),
+                    ..Default::default()
+                };
+                (change.bookmark_id, body)
+            })
+            .collect();
+        self.bulk_update(updates, options)
+    }
+
+    /// All bookmarks, archived or not, carrying any of the tags
+    pub(crate) fn bookmarks_tagged(&self, tags: &[&str]) -> Result<Vec<Bookmark>, LinkDingError> {
+        let mut bookmarks: Vec<Bookmark> = Vec::new();
+        for tag in tags {
+            let args = ListBookmarksArgs {
+                query: Some(format!("#{}", tag)),
+                ..Default::default()
+            };
+            let matches = self
+                .iter_bookmarks(args.clone())
+                .chain(self.iter_archived_bookmarks(args));
+            for bookmark in matches {
+                let bookmark = bookmark?;
+                // the search also matches tags in lax mode, so check the actual tags
+                if bookmark.tag_names.iter().any(|t| tag_eq(t, tag))
+                    && !bookmarks.iter().any(|b| b.id == bookmark.id)
+                {
+                    bookmarks.push(bookmark);
+                }
+            }
+        }
+        Ok(bookmarks)
+    }
+}
+
+fn merge_tag_names(tags: &[String], from: &[&str], into: &str) -> Vec<String> {
+    let mut merged: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = match from.iter().any(|f| tag_eq(f, tag)) {
+            true => into,
+            false => tag.as_str(),
+        };
+        if !merged.iter().any(|t| tag_eq(t, tag)) {
+            merged.push(tag.to_string());
+        }
+    }
+    merged
+}
+
+/// linkding compares tag names case-insensitively
+pub(crate) fn tag_eq(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+pub(crate) fn contains_tag(tags: &[String], tag: &str) -> bool {
+    tags.iter().any(|t| tag_eq(t, tag))
+}
+
+/// Whether both lists hold the same tags, ignoring order, case and duplicates
+pub(crate) fn same_tags(a: &[String], b: &[String]) -> bool {
+    a.iter().all(|tag| contains_tag(b, tag)) && b.iter().all(|tag| contains_tag(a, tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, tags};
+
+    #[test]
+    fn merge_replaces_tags_without_duplicates() {
+        let merged = merge_tag_names(
+            &tags(&["Rust-Lang", "cli", "rustlang", "rust"]),
+            &["rust-lang", "rustlang"],
+            "rust",
+        );
+        assert_eq!(merged, tags(&["rust", "cli"]));
+    }
+
+    #[test]
+    fn reversed_plan_restores_previous_tags() {
+        let plan = TagChangePlan {
+            changes: vec![TagChange {
+                bookmark_id: 7,
+                before: tags(&["rust-lang", "cli"]),
+                after: tags(&["rust", "cli"]),
+            }],
+        };
+        let undo = plan.reversed();
+        assert_eq!(undo.affected_ids(), vec![7]);
+        assert_eq!(undo.changes[0].after, tags(&["rust-lang", "cli"]));
+        assert_eq!(undo.reversed(), plan);
+    }
+
+    #[test]
+    fn unchanged_bookmarks_are_not_in_plan() {
+        let bookmark = |id: i32, tag_names: &[&str]| Bookmark {
+            tag_names: tags(tag_names),
+            ..test_util::bookmark(id)
+        };
+        let plan = TagChangePlan::from_bookmarks(
+            vec![bookmark(1, &["Rust-Lang", "rust"]), bookmark(2, &["web"])],
+            |tags| {
+                tags.iter()
+                    .filter(|t| !tag_eq(t, "rust-lang"))
+                    .cloned()
+                    .collect()
+            },
+        );
+        assert_eq!(plan.affected_ids(), vec![1]);
+        assert_eq!(plan.changes[0].after, tags(&["rust"]));
+    }
+}
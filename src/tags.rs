@@ -11,7 +11,7 @@ pub struct ListTagsResponse {
     pub results: Vec<TagData>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[cfg_attr(feature = "ffi", derive(uniffi::Record))]
 pub struct TagData {
     pub id: i32,
@@ -0,0 +1,157 @@
+//! Helpers shared by the unit tests
+
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::Bookmark;
+
+/// A bookmark with only its ID and URL set, override fields with struct
+/// update syntax
+pub(crate) fn bookmark(id: i32) -> Bookmark {
+    Bookmark {
+        id,
+        url: format!("https://example.com/{}", id),
+        title: "".to_string(),
+        description: "".to_string(),
+        notes: "".to_string(),
+        web_archive_snapshot_url: "".to_string(),
+        favicon_url: None,
+        preview_image_url: None,
+        is_archived: false,
+        unread: false,
+        shared: false,
+        tag_names: vec![],
+        date_added: "2025-01-01T00:00:00Z".to_string(),
+        date_modified: "2025-01-01T00:00:00Z".to_string(),
+        website_title: None,
+        website_description: None,
+    }
+}
+
+pub(crate) fn tags(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+/// Create a new, empty directory for a test, never reusing an existing one
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let dir = std::env::temp_dir().join(format!(
+            "linkding-rs-{}-{}-{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        match std::fs::create_dir(&dir) {
+            Ok(()) => return dir,
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(error) => panic!("Could not create test directory: {}", error),
+        }
+    }
+}
+
+/// A canned response of [`serve_api`], for requests like `GET /api/tags/`
+#[derive(Clone)]
+pub(crate) struct Route {
+    pub request: &'static str,
+    pub status: u16,
+    pub body: String,
+}
+
+impl Route {
+    pub fn ok(request: &'static str, body: impl Into<String>) -> Route {
+        Route {
+            request,
+            status: 200,
+            body: body.into(),
+        }
+    }
+}
+
+/// Serve canned responses until the test ends, matching requests on method
+/// and path without the query
+///
+/// Several routes for the same request answer it in turn, the last one is
+/// kept for all later requests. Returns the server's URL and the requests it
+/// received, including their query. Unknown requests are answered with a 404.
+pub(crate) fn serve_api(mut routes: Vec<Route>) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let received = requests.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { return };
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            let head = loop {
+                let read = stream.read(&mut buffer).unwrap_or(0);
+                if read == 0 {
+                    break None;
+                }
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break Some(head.lines().next().unwrap_or_default().to_string());
+                    }
+                }
+            };
+            let Some(head) = head else { continue };
+            let mut parts = head.split(' ');
+            let method = parts.next().unwrap_or_default();
+            let target = parts.next().unwrap_or_default();
+            let path = target.split('?').next().unwrap_or_default();
+            received
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", method, target));
+            let request = format!("{} {}", method, path);
+            let matching: Vec<usize> = (0..routes.len())
+                .filter(|&i| routes[i].request == request)
+                .collect();
+            let route = match matching[..] {
+                [] => None,
+                [only] => Some(routes[only].clone()),
+                [first, ..] => Some(routes.remove(first)),
+            };
+            let (status, body) =
+                route.map_or((404, String::new()), |route| (route.status, route.body));
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {} Canned\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+        }
+    });
+    (url, requests)
+}
+
+/// A paginated API response listing `results`
+pub(crate) fn page<T: serde::Serialize>(results: &[T]) -> String {
+    serde_json::json!({
+        "count": results.len(),
+        "next": null,
+        "previous": null,
+        "results": results,
+    })
+    .to_string()
+}
@@ -0,0 +1,191 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Civil date and time in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct UtcDateTime {
+    pub(crate) year: i64,
+    pub(crate) month: u32,
+    pub(crate) day: u32,
+    pub(crate) hour: u32,
+    pub(crate) minute: u32,
+    pub(crate) second: u32,
+}
+
+impl UtcDateTime {
+    pub(crate) fn now() -> Self {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        UtcDateTime::from_unix(seconds)
+    }
+
+    pub(crate) fn from_unix(seconds: i64) -> Self {
+        let days = seconds.div_euclid(86_400);
+        let time = seconds.rem_euclid(86_400) as u32;
+        // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        UtcDateTime {
+            year,
+            month,
+            day,
+            hour: time / 3600,
+            minute: time % 3600 / 60,
+            second: time % 60,
+        }
+    }
+
+    pub(crate) fn to_unix(self) -> i64 {
+        // days from civil, the inverse of `from_unix`
+        let year = self.year - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let month = i64::from(self.month);
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + i64::from(self.day) - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+        days * 86_400
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second)
+    }
+
+    /// Parse an RFC 3339 timestamp as returned by linkding, e.g.
+    /// `2025-01-31T13:00:00.123456+01:00`, converting it to UTC
+    pub(crate) fn parse_rfc3339(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let number = |range: std::ops::Range<usize>| -> Option<u32> {
+            let digits = value.get(range)?;
+            if !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            digits.parse().ok()
+        };
+        let separators = value.as_bytes();
+        if separators.len() < 19
+            || separators[4] != b'-'
+            || separators[7] != b'-'
+            || !matches!(separators[10], b'T' | b't' | b' ')
+            || separators[13] != b':'
+            || separators[16] != b':'
+        {
+            return None;
+        }
+        let date_time = UtcDateTime {
+            year: i64::from(number(0..4)?),
+            month: number(5..7)?,
+            day: number(8..10)?,
+            hour: number(11..13)?,
+            minute: number(14..16)?,
+            second: number(17..19)?,
+        };
+        if !(1..=12).contains(&date_time.month) || !(1..=31).contains(&date_time.day) {
+            return None;
+        }
+
+        let mut rest = &value[19..];
+        if let Some(fraction) = rest.strip_prefix('.') {
+            rest = fraction.trim_start_matches(|c: char| c.is_ascii_digit());
+        }
+        let offset = match rest {
+            "" | "Z" | "z" => 0,
+            _ => {
+                let sign = match rest.as_bytes()[0] {
+                    b'+' => 1,
+                    b'-' => -1,
+                    _ => return None,
+                };
+                let (hours, minutes) = rest[1..].split_once(':')?;
+                if hours.len() != 2 || minutes.len() != 2 {
+                    return None;
+                }
+                let hours: i64 = hours.parse().ok()?;
+                let minutes: i64 = minutes.parse().ok()?;
+                sign * (hours * 3600 + minutes * 60)
+            }
+        };
+        Some(UtcDateTime::from_unix(date_time.to_unix() - offset))
+    }
+
+    /// Format as RFC 3339, e.g. `2025-01-31T12:00:00Z`
+    pub(crate) fn to_rfc3339(self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+
+    /// Format as RFC 2822, e.g. `Fri, 31 Jan 2025 12:00:00 +0000`
+    pub(crate) fn to_rfc2822(self) -> String {
+        const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+        // the epoch was a Thursday
+        let weekday = WEEKDAYS[self.to_unix().div_euclid(86_400).rem_euclid(7) as usize];
+        format!(
+            "{}, {:02} {} {:04} {:02}:{:02}:{:02} +0000",
+            weekday,
+            self.day,
+            MONTHS[self.month as usize - 1],
+            self.year,
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_unix_timestamps() {
+        assert_eq!(
+            UtcDateTime::from_unix(0).to_rfc3339(),
+            "1970-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            UtcDateTime::from_unix(1_709_210_096).to_rfc3339(),
+            "2024-02-29T12:34:56Z"
+        );
+        assert_eq!(
+            UtcDateTime::from_unix(1_709_210_096).to_unix(),
+            1_709_210_096
+        );
+    }
+
+    #[test]
+    fn parses_rfc3339() {
+        let parsed = UtcDateTime::parse_rfc3339("2024-02-29T13:34:56.123456+01:00").unwrap();
+        assert_eq!(parsed.to_rfc3339(), "2024-02-29T12:34:56Z");
+        assert_eq!(
+            UtcDateTime::parse_rfc3339("2024-02-29T12:34:56Z"),
+            Some(parsed)
+        );
+        assert_eq!(UtcDateTime::parse_rfc3339("2024-02-29"), None);
+        assert_eq!(UtcDateTime::parse_rfc3339("2024-02-29T12:34:56+1"), None);
+    }
+
+    #[test]
+    fn formats_rfc2822() {
+        assert_eq!(
+            UtcDateTime::from_unix(0).to_rfc2822(),
+            "Thu, 01 Jan 1970 00:00:00 +0000"
+        );
+        assert_eq!(
+            UtcDateTime::from_unix(1_709_210_096).to_rfc2822(),
+            "Thu, 29 Feb 2024 12:34:56 +0000"
+        );
+    }
+}
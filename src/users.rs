@@ -18,6 +18,13 @@ pub struct UserProfile {
     search_preferences: UserSearchPreferences,
 }
 
+impl UserProfile {
+    /// How search terms are matched against tags
+    pub fn tag_search(&self) -> TagSearchMethod {
+        self.tag_search
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[cfg_attr(feature = "ffi", derive(uniffi::Record))]
 pub struct UserSearchPreferences {
@@ -92,11 +99,13 @@ impl<'de> Deserialize<'de> for LinkTarget {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
 #[serde(rename_all = "snake_case")]
 #[cfg_attr(feature = "ffi", derive(uniffi::Enum))]
 pub enum TagSearchMethod {
     Strict,
+    /// Search terms also match bookmarks with a tag of the same name
+    #[default]
     Lax,
 }
 
@@ -124,6 +133,7 @@ where
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
@@ -0,0 +1,537 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "extract")]
+use crate::{
+    bookmark_assets::{BookmarkAssetStatus, BookmarkAssetType},
+    extract::extract_article,
+};
+use sha2::{Digest, Sha256};
+
+use crate::{tag_changes::tag_eq, Bookmark, LinkDingClient, LinkDingError, ListBookmarksArgs};
+
+/// Longest file name stem written, leaving room for a hash suffix
+const MAX_NAME_LENGTH: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VaultExportOptions {
+    /// linkding search query selecting the bookmarks, all if not set
+    pub query: Option<String>,
+    pub include_archived: bool,
+    /// Folder of the bookmark notes, relative to the vault
+    pub bookmarks_dir: String,
+    /// Folder of the tag index pages, relative to the vault, `None` to not
+    /// write any
+    pub tags_dir: Option<String>,
+    /// Add the article extracted from each bookmark's latest snapshot,
+    /// exporting fails when set without the `extract` feature
+    pub snapshot_text: bool,
+    /// Remove previously exported notes and tag pages that weren't exported
+    /// this time
+    pub prune: bool,
+}
+
+impl Default for VaultExportOptions {
+    fn default() -> Self {
+        VaultExportOptions {
+            query: None,
+            include_archived: true,
+            bookmarks_dir: "Bookmarks".to_string(),
+            tags_dir: Some("Tags".to_string()),
+            snapshot_text: false,
+            prune: false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct VaultExportReport {
+    /// Notes and tag pages that were created or changed
+    pub written: Vec<PathBuf>,
+    /// Notes and tag pages that were already up to date
+    pub unchanged: usize,
+    /// Files removed because an earlier export wrote them under another
+    /// name, or with [`VaultExportOptions::prune`], no longer exported
+    pub removed: Vec<PathBuf>,
+    /// Bookmarks whose snapshot couldn't be downloaded or extracted, their
+    /// notes are written without the article
+    pub failed_snapshots: Vec<(i32, LinkDingError)>,
+}
+
+/// Which exported file a previous export wrote
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum ExportedFile {
+    Bookmark(i32),
+    Tag(String),
+}
+
+/// The file name of a bookmark's note, made from its ID
+///
+/// The name stays the same when the bookmark's title or URL change, so links
+/// to the note keep working. The title is in the note's front matter and
+/// heading instead.
+pub fn note_file_name(bookmark: &Bookmark) -> String {
+    format!("{}.md", bookmark.id)
+}
+
+/// The bookmark's title, falling back to the website's title and the URL
+fn note_title(bookmark: &Bookmark) -> &str {
+    [bookmark.title.as_str()]
+        .into_iter()
+        .chain(bookmark.website_title.as_deref())
+        .find(|title| !title.trim().is_empty())
+        .unwrap_or(&bookmark.url)
+}
+
+fn file_stem(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']' => ' ',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    let mut stem = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    if let Some((index, _)) = stem.char_indices().nth(MAX_NAME_LENGTH) {
+        stem.truncate(index);
+    }
+    let stem = stem.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    match stem {
+        "" => "Untitled".to_string(),
+        stem => stem.to_string(),
+    }
+}
+
+/// The file name of a tag's index page
+///
+/// Tags that lose characters to [`file_stem`] get a short hash of the tag
+/// appended, so `c#` and `c` don't share a page. Tags can't contain spaces,
+/// so the suffixed names never clash with a plain tag's.
+fn tag_file_name(tag: &str) -> String {
+    let stem = file_stem(tag);
+    if stem == tag {
+        return format!("{}.md", stem);
+    }
+    let hash = format!("{:x}", Sha256::digest(tag.to_lowercase()));
+    format!("{} {}.md", stem, &hash[..8])
+}
+
+/// Quote a string for YAML, JSON strings are valid YAML scalars
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).expect("Strings always serialize")
+}
+
+/// A bookmark as a Markdown note with YAML front matter, the notes as body
+/// and the snapshot's article, if given, below them
+pub fn bookmark_note(bookmark: &Bookmark, article: Option<&str>) -> String {
+    let tags: Vec<String> = bookmark.tag_names.iter().map(|t| yaml_string(t)).collect();
+    let mut note = String::from("---\n");
+    note.push_str(&format!("linkding_id: {}\n", bookmark.id));
+    note.push_str(&format!("url: {}\n", yaml_string(&bookmark.url)));
+    if !bookmark.title.is_empty() {
+        note.push_str(&format!("title: {}\n", yaml_string(&bookmark.title)));
+    }
+    if !bookmark.description.is_empty() {
+        note.push_str(&format!(
+            "description: {}\n",
+            yaml_string(&bookmark.description)
+        ));
+    }
+    note.push_str(&format!("tags: [{}]\n", tags.join(", ")));
+    note.push_str(&format!(
+        "date_added: {}\n",
+        yaml_string(&bookmark.date_added)
+    ));
+    note.push_str(&format!(
+        "date_modified: {}\n",
+        yaml_string(&bookmark.date_modified)
+    ));
+    note.push_str(&format!("archived: {}\n", bookmark.is_archived));
+    note.push_str(&format!("unread: {}\n", bookmark.unread));
+    note.push_str(&format!("shared: {}\n", bookmark.shared));
+    note.push_str("---\n\n");
+    note.push_str(&format!(
+        "# {}\n\n<{}>\n",
+        note_title(bookmark),
+        bookmark.url
+    ));
+    if !bookmark.notes.trim().is_empty() {
+        note.push_str(&format!("\n{}\n", bookmark.notes.trim()));
+    }
+    if let Some(article) = article.filter(|a| !a.trim().is_empty()) {
+        note.push_str(&format!("\n## Snapshot\n\n{}\n", article.trim()));
+    }
+    note
+}
+
+/// An index page linking to the notes of all bookmarks with the tag
+fn tag_page(tag: &str, bookmarks_dir: &str, notes: &[(String, String)]) -> String {
+    let mut page = format!(
+        "---\nlinkding_tag: {}\ntags: [{}]\n---\n\n# {}\n\n",
+        yaml_string(tag),
+        yaml_string(tag),
+        tag
+    );
+    for (file_name, title) in notes {
+        let target = format!(
+            "{}/{}",
+            bookmarks_dir.trim_end_matches('/'),
+            file_name.trim_end_matches(".md")
+        );
+        page.push_str(&format!(
+            "- [[{}|{}]]\n",
+            target,
+            title.replace(['[', ']', '|'], "")
+        ));
+    }
+    page
+}
+
+/// Read which bookmark or tag an exported file belongs to from its front
+/// matter, `None` for files not written by an export
+fn exported_file(path: &Path) -> io::Result<Option<ExportedFile>> {
+    let mut lines = io::BufReader::new(fs::File::open(path)?).lines();
+    if lines.next().transpose()?.as_deref() != Some("---") {
+        return Ok(None);
+    }
+    for line in lines {
+        let line = line?;
+        if line == "---" {
+            break;
+        }
+        if let Some(id) = line.strip_prefix("linkding_id: ") {
+            return Ok(id.trim().parse().ok().map(ExportedFile::Bookmark));
+        }
+        if let Some(tag) = line.strip_prefix("linkding_tag: ") {
+            return Ok(serde_json::from_str(tag.trim()).ok().map(ExportedFile::Tag));
+        }
+    }
+    Ok(None)
+}
+
+/// The exported Markdown files in a folder
+fn exported_files(dir: &Path) -> io::Result<Vec<(PathBuf, ExportedFile)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "md") && path.is_file() {
+            // files that can't be read as text weren't written by an export
+            if let Ok(Some(exported)) = exported_file(&path) {
+                files.push((path, exported));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Write a file unless it already has the contents
+fn write_if_changed(
+    path: PathBuf,
+    contents: &str,
+    report: &mut VaultExportReport,
+) -> io::Result<()> {
+    match fs::read_to_string(&path) {
+        Ok(existing) if existing == contents => report.unchanged += 1,
+        _ => {
+            fs::write(&path, contents)?;
+            report.written.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Exporting bookmarks as Markdown notes, e.g. into an Obsidian vault
+impl LinkDingClient {
+    /// Write a Markdown note per bookmark and an index page per tag
+    ///
+    /// Notes are named by [`note_file_name`] and carry the bookmark ID in
+    /// their front matter, so exporting again updates them in place. Notes
+    /// an earlier version wrote under another name are moved to it. Files
+    /// are only rewritten when their contents change, and files not written
+    /// by an export are never touched. A bookmark whose snapshot can't be
+    /// downloaded or extracted is exported without the article and listed
+    /// in [`VaultExportReport::failed_snapshots`].
+    pub fn export_vault(
+        &self,
+        vault: &Path,
+        options: &VaultExportOptions,
+    ) -> Result<VaultExportReport, LinkDingError> {
+        #[cfg(not(feature = "extract"))]
+        if options.snapshot_text {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "exporting snapshot text needs the extract feature",
+            )
+            .into());
+        }
+        let mut report = VaultExportReport::default();
+        let bookmarks_dir = vault.join(&options.bookmarks_dir);
+        fs::create_dir_all(&bookmarks_dir)?;
+        let mut previous: HashMap<ExportedFile, Vec<PathBuf>> = HashMap::new();
+        for (path, exported) in exported_files(&bookmarks_dir)? {
+            previous.entry(exported).or_default().push(path);
+        }
+
+        let args = ListBookmarksArgs {
+            query: options.query.clone(),
+            ..Default::default()
+        };
+        let mut bookmarks: Box<dyn Iterator<Item = _>> =
+            Box::new(self.iter_bookmarks(args.clone()));
+        if options.include_archived {
+            bookmarks = Box::new(bookmarks.chain(self.iter_archived_bookmarks(args)));
+        }
+
+        // tag names as first seen, with the file names and titles of their notes
+        let mut tags: BTreeMap<String, (String, Vec<(String, String)>)> = BTreeMap::new();
+        for bookmark in bookmarks {
+            let bookmark = bookmark?;
+            #[cfg(feature = "extract")]
+            let article = match options.snapshot_text {
+                true => self.snapshot_markdown(bookmark.id).unwrap_or_else(|error| {
+                    report.failed_snapshots.push((bookmark.id, error));
+                    None
+                }),
+                false => None,
+            };
+            #[cfg(not(feature = "extract"))]
+            let article: Option<String> = None;
+
+            let file_name = note_file_name(&bookmark);
+            let path = bookmarks_dir.join(&file_name);
+            let note = bookmark_note(&bookmark, article.as_deref());
+            for old in previous
+                .remove(&ExportedFile::Bookmark(bookmark.id))
+                .unwrap_or_default()
+            {
+                if old != path {
+                    fs::remove_file(&old)?;
+                    report.removed.push(old);
+                }
+            }
+            write_if_changed(path, &note, &mut report)?;
+
+            let title = note_title(&bookmark).to_string();
+            for tag in &bookmark.tag_names {
+                let (_, notes) = tags
+                    .entry(tag.to_lowercase())
+                    .or_insert_with(|| (tag.clone(), Vec::new()));
+                notes.push((file_name.clone(), title.clone()));
+            }
+        }
+
+        if let Some(tags_dir) = &options.tags_dir {
+            let tags_dir = vault.join(tags_dir);
+            fs::create_dir_all(&tags_dir)?;
+            for (path, exported) in exported_files(&tags_dir)? {
+                previous.entry(exported).or_default().push(path);
+            }
+            for (tag, mut notes) in tags.into_values() {
+                notes.sort_by_key(|(_, title)| title.to_lowercase());
+                let path = tags_dir.join(tag_file_name(&tag));
+                let renamed: Vec<ExportedFile> = previous
+                    .keys()
+                    .filter(|exported| matches!(exported, ExportedFile::Tag(t) if tag_eq(t, &tag)))
+                    .cloned()
+                    .collect();
+                for exported in renamed {
+                    for old in previous.remove(&exported).unwrap_or_default() {
+                        if old != path {
+                            fs::remove_file(&old)?;
+                            report.removed.push(old);
+                        }
+                    }
+                }
+                write_if_changed(
+                    path,
+                    &tag_page(&tag, &options.bookmarks_dir, &notes),
+                    &mut report,
+                )?;
+            }
+        }
+
+        if options.prune {
+            for path in previous.into_values().flatten() {
+                fs::remove_file(&path)?;
+                report.removed.push(path);
+            }
+        }
+        Ok(report)
+    }
+
+    /// The article of a bookmark's latest completed snapshot as Markdown
+    #[cfg(feature = "extract")]
+    fn snapshot_markdown(&self, bookmark_id: i32) -> Result<Option<String>, LinkDingError> {
+        let snapshot = self
+            .list_bookmark_assets(bookmark_id)?
+            .results
+            .into_iter()
+            .filter(|asset| {
+                asset.asset_type == BookmarkAssetType::Snapshot
+                    && asset.status == BookmarkAssetStatus::Complete
+            })
+            .max_by_key(|asset| asset.id);
+        let Some(snapshot) = snapshot else {
+            return Ok(None);
+        };
+        let mut content = Vec::new();
+        self.download_bookmark_asset_to(bookmark_id, snapshot.id, &mut content)?;
+        Ok(Some(extract_article(&content)?.markdown))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, tags, test_dir};
+
+    fn bookmark() -> Bookmark {
+        Bookmark {
+            url: "https://example.com/a".to_string(),
+            title: "What's new: Rust 1.80 [beta]".to_string(),
+            description: "Release \"notes\"".to_string(),
+            notes: "Worth a read.".to_string(),
+            unread: true,
+            tag_names: tags(&["rust", "release-notes"]),
+            date_added: "2024-07-25T10:00:00Z".to_string(),
+            date_modified: "2024-07-26T10:00:00Z".to_string(),
+            ..test_util::bookmark(42)
+        }
+    }
+
+    #[test]
+    fn names_notes_by_id_only() {
+        assert_eq!(note_file_name(&bookmark()), "42.md");
+        let renamed = Bookmark {
+            title: "Another title".to_string(),
+            url: "https://example.com/b".to_string(),
+            ..bookmark()
+        };
+        assert_eq!(note_file_name(&renamed), "42.md");
+    }
+
+    #[test]
+    fn cleans_file_stems() {
+        assert_eq!(
+            file_stem("What's new: Rust 1.80 [beta]"),
+            "What's new Rust 1.80 beta"
+        );
+        assert_eq!(file_stem("..."), "Untitled");
+        assert_eq!(file_stem(&"é".repeat(150)), "é".repeat(100));
+    }
+
+    #[test]
+    fn renders_front_matter_notes_and_snapshot() {
+        let note = bookmark_note(&bookmark(), Some("Article text"));
+        assert_eq!(
+            note,
+            r#"---
+linkding_id: 42
+url: "https://example.com/a"
+title: "What's new: Rust 1.80 [beta]"
+description: "Release \"notes\""
+tags: ["rust", "release-notes"]
+date_added: "2024-07-25T10:00:00Z"
+date_modified: "2024-07-26T10:00:00Z"
+archived: false
+unread: true
+shared: false
+---
+
+# What's new: Rust 1.80 [beta]
+
+<https://example.com/a>
+
+Worth a read.
+
+## Snapshot
+
+Article text
+"#
+        );
+    }
+
+    #[test]
+    fn recognizes_exported_files() {
+        let dir = test_dir("vault");
+        let note = dir.join("note.md");
+        fs::write(&note, bookmark_note(&bookmark(), None)).unwrap();
+        let tag = dir.join("rust.md");
+        fs::write(&tag, tag_page("Rust", "Bookmarks", &[])).unwrap();
+        let own = dir.join("mine.md");
+        fs::write(&own, "---\ntags: [rust]\n---\n# Mine\n").unwrap();
+
+        let mut files = exported_files(&dir).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                (note, ExportedFile::Bookmark(42)),
+                (tag, ExportedFile::Tag("Rust".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn tag_pages_link_to_notes() {
+        let page = tag_page(
+            "rust",
+            "Bookmarks/",
+            &[("42.md".to_string(), "Rust 1.80 [beta]".to_string())],
+        );
+        assert!(page.starts_with("---\nlinkding_tag: \"rust\"\n"));
+        assert!(page.ends_with("- [[Bookmarks/42|Rust 1.80 beta]]\n"));
+    }
+
+    #[test]
+    fn tag_page_names_dont_collide() {
+        assert_eq!(tag_file_name("rust"), "rust.md");
+        assert_ne!(tag_file_name("c#"), tag_file_name("c"));
+        assert!(tag_file_name("c#").starts_with("c "));
+    }
+
+    #[cfg(feature = "extract")]
+    #[test]
+    fn failed_snapshots_dont_stop_the_export() {
+        use crate::test_util::{page, serve_api, Route};
+        use crate::BookmarkAsset;
+
+        let snapshot = BookmarkAsset {
+            id: 2,
+            bookmark: 1,
+            asset_type: BookmarkAssetType::Snapshot,
+            date_created: "2025-01-01T00:00:00Z".to_string(),
+            content_type: "text/html".to_string(),
+            display_name: "Snapshot".to_string(),
+            status: BookmarkAssetStatus::Complete,
+            file_size: None,
+        };
+        // the snapshot download isn't served and fails with a 404
+        let (url, _) = serve_api(vec![
+            Route::ok("GET /api/bookmarks/", page(&[test_util::bookmark(1)])),
+            Route::ok("GET /api/bookmarks/1/assets/", page(&[snapshot])),
+        ]);
+        let client = LinkDingClient::new(&url, "token");
+        let vault = test_dir("vault-failed-snapshot");
+        let options = VaultExportOptions {
+            include_archived: false,
+            snapshot_text: true,
+            ..Default::default()
+        };
+
+        let report = client.export_vault(&vault, &options).unwrap();
+        assert_eq!(report.failed_snapshots.len(), 1);
+        assert_eq!(report.failed_snapshots[0].0, 1);
+        assert!(vault.join("Bookmarks").join("1.md").exists());
+    }
+}
@@ -0,0 +1,510 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufWriter},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    tag_changes::tag_eq, timestamp::UtcDateTime, Bookmark, LinkDingClient, LinkDingError,
+    ListBookmarksArgs, UpdateBookmarkBody,
+};
+
+/// The kinds of [`BookmarkEvent`], e.g. for filtering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BookmarkEventKind {
+    Created,
+    Updated,
+    Archived,
+    Unarchived,
+    Deleted,
+    TagAdded,
+    TagRemoved,
+}
+
+impl BookmarkEventKind {
+    /// The name used in serialized events
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BookmarkEventKind::Created => "created",
+            BookmarkEventKind::Updated => "updated",
+            BookmarkEventKind::Archived => "archived",
+            BookmarkEventKind::Unarchived => "unarchived",
+            BookmarkEventKind::Deleted => "deleted",
+            BookmarkEventKind::TagAdded => "tag_added",
+            BookmarkEventKind::TagRemoved => "tag_removed",
+        }
+    }
+}
+
+/// A change to a bookmark noticed by a [`BookmarkWatcher`]
+///
+/// Events serialize to JSON objects with the kind in the `event` field.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum BookmarkEvent {
+    Created {
+        bookmark: Bookmark,
+    },
+    /// Fields other than the tags and the archived state changed, `changes`
+    /// holds their new values
+    Updated {
+        bookmark: Bookmark,
+        changes: Box<UpdateBookmarkBody>,
+    },
+    Archived {
+        bookmark: Bookmark,
+    },
+    Unarchived {
+        bookmark: Bookmark,
+    },
+    /// The bookmark as it was last seen
+    Deleted {
+        bookmark: Bookmark,
+    },
+    TagAdded {
+        bookmark: Bookmark,
+        tag: String,
+    },
+    TagRemoved {
+        bookmark: Bookmark,
+        tag: String,
+    },
+}
+
+impl BookmarkEvent {
+    pub fn kind(&self) -> BookmarkEventKind {
+        match self {
+            BookmarkEvent::Created { .. } => BookmarkEventKind::Created,
+            BookmarkEvent::Updated { .. } => BookmarkEventKind::Updated,
+            BookmarkEvent::Archived { .. } => BookmarkEventKind::Archived,
+            BookmarkEvent::Unarchived { .. } => BookmarkEventKind::Unarchived,
+            BookmarkEvent::Deleted { .. } => BookmarkEventKind::Deleted,
+            BookmarkEvent::TagAdded { .. } => BookmarkEventKind::TagAdded,
+            BookmarkEvent::TagRemoved { .. } => BookmarkEventKind::TagRemoved,
+        }
+    }
+
+    pub fn bookmark(&self) -> &Bookmark {
+        match self {
+            BookmarkEvent::Created { bookmark }
+            | BookmarkEvent::Updated { bookmark, .. }
+            | BookmarkEvent::Archived { bookmark }
+            | BookmarkEvent::Unarchived { bookmark }
+            | BookmarkEvent::Deleted { bookmark }
+            | BookmarkEvent::TagAdded { bookmark, .. }
+            | BookmarkEvent::TagRemoved { bookmark, .. } => bookmark,
+        }
+    }
+}
+
+/// What a [`BookmarkWatcher`] has seen so far, persisted between polls
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct WatchState {
+    /// When the last poll finished, `None` before the first one
+    pub polled_at: Option<String>,
+    pub bookmarks: BTreeMap<i32, Bookmark>,
+    /// The newest modification date seen, incremental polls only list
+    /// bookmarks modified since
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// Incremental polls since the last one listing all bookmarks
+    #[serde(default)]
+    pub incremental_polls: u32,
+}
+
+impl WatchState {
+    /// Load the state from a file, empty if the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<WatchState, LinkDingError> {
+        match File::open(path) {
+            Ok(file) => Ok(serde_json::from_reader(io::BufReader::new(file))?),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(WatchState::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), LinkDingError> {
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        serde_json::to_writer(BufWriter::new(File::create(&temp_path)?), self)?;
+        fs::rename(temp_path, path)?;
+        Ok(())
+    }
+
+    /// Record the listed `bookmarks`, returning the events since the
+    /// previous update
+    ///
+    /// A `full` listing replaces the seen bookmarks, so the ones missing
+    /// from it are reported as deleted. Otherwise only the listed bookmarks
+    /// are updated. The first update only records the bookmarks, unless
+    /// `emit_existing` is set to report them all as created.
+    fn advance(
+        &mut self,
+        bookmarks: Vec<Bookmark>,
+        full: bool,
+        emit_existing: bool,
+    ) -> Vec<BookmarkEvent> {
+        let current: BTreeMap<i32, Bookmark> = bookmarks
+            .into_iter()
+            .map(|bookmark| (bookmark.id, bookmark))
+            .collect();
+        let mut events = Vec::new();
+        if self.polled_at.is_some() || emit_existing {
+            for bookmark in current.values() {
+                match self.bookmarks.get(&bookmark.id) {
+                    None => events.push(BookmarkEvent::Created {
+                        bookmark: bookmark.clone(),
+                    }),
+                    Some(previous) => events.extend(changes_between(previous, bookmark)),
+                }
+            }
+            if full {
+                for (id, bookmark) in &self.bookmarks {
+                    if !current.contains_key(id) {
+                        events.push(BookmarkEvent::Deleted {
+                            bookmark: bookmark.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut newest = self.cursor.as_deref().and_then(UtcDateTime::parse_rfc3339);
+        for bookmark in current.values() {
+            let modified = UtcDateTime::parse_rfc3339(&bookmark.date_modified);
+            if modified.is_some() && modified > newest {
+                newest = modified;
+                self.cursor = Some(bookmark.date_modified.clone());
+            }
+        }
+        if full {
+            self.bookmarks = current;
+            self.incremental_polls = 0;
+        } else {
+            self.bookmarks.extend(current);
+            self.incremental_polls += 1;
+        }
+        self.polled_at = Some(UtcDateTime::now().to_rfc3339());
+        events
+    }
+}
+
+/// The events turning `previous` into `current`
+fn changes_between(previous: &Bookmark, current: &Bookmark) -> Vec<BookmarkEvent> {
+    let mut events = Vec::new();
+    if previous == current {
+        return events;
+    }
+    let changes = UpdateBookmarkBody {
+        is_archived: None,
+        tag_names: None,
+        ..previous.diff(current)
+    };
+    if !changes.is_empty() {
+        events.push(BookmarkEvent::Updated {
+            bookmark: current.clone(),
+            changes: Box::new(changes),
+        });
+    }
+    match (previous.is_archived, current.is_archived) {
+        (false, true) => events.push(BookmarkEvent::Archived {
+            bookmark: current.clone(),
+        }),
+        (true, false) => events.push(BookmarkEvent::Unarchived {
+            bookmark: current.clone(),
+        }),
+        _ => {}
+    }
+    let missing_from = |tags: &[String], tag: &str| !tags.iter().any(|t| tag_eq(t, tag));
+    for tag in &current.tag_names {
+        if missing_from(&previous.tag_names, tag) {
+            events.push(BookmarkEvent::TagAdded {
+                bookmark: current.clone(),
+                tag: tag.clone(),
+            });
+        }
+    }
+    for tag in &previous.tag_names {
+        if missing_from(&current.tag_names, tag) {
+            events.push(BookmarkEvent::TagRemoved {
+                bookmark: current.clone(),
+                tag: tag.clone(),
+            });
+        }
+    }
+    events
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WatchOptions {
+    /// How long to wait between polls when running continuously
+    pub interval: Duration,
+    /// Report the bookmarks found by the very first poll as created
+    pub emit_existing: bool,
+    /// List all bookmarks on every n-th poll, the polls in between only
+    /// list the ones modified since and can't notice deletions. `0` and `1`
+    /// list all bookmarks on every poll.
+    pub full_poll_every: u32,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            interval: Duration::from_secs(60),
+            emit_existing: false,
+            full_poll_every: 10,
+        }
+    }
+}
+
+/// Polls an instance and turns the differences between polls into events
+///
+/// linkding has no webhooks, so a watcher lists the bookmarks modified since
+/// its previous poll, archived or not, and compares them with what it has
+/// seen. Deleted bookmarks only show up missing from a full listing, which
+/// happens every [`WatchOptions::full_poll_every`] polls, or on every poll
+/// with linkding versions that don't support the `modified_since` filter. The seen state is
+/// saved to a file once the events of a poll are handled, so a restarted
+/// watcher continues where it left off. Events that weren't handled before
+/// a crash are delivered again.
+pub struct BookmarkWatcher<'a> {
+    client: &'a LinkDingClient,
+    state_path: PathBuf,
+    state: WatchState,
+    /// The state after the last poll, until it is committed
+    pending: Option<WatchState>,
+    options: WatchOptions,
+}
+
+impl<'a> BookmarkWatcher<'a> {
+    /// Create a watcher keeping its state in the file at `state_path`
+    pub fn new(
+        client: &'a LinkDingClient,
+        state_path: impl Into<PathBuf>,
+        options: WatchOptions,
+    ) -> Result<BookmarkWatcher<'a>, LinkDingError> {
+        let state_path = state_path.into();
+        let state = WatchState::load(&state_path)?;
+        Ok(BookmarkWatcher {
+            client,
+            state_path,
+            state,
+            pending: None,
+            options,
+        })
+    }
+
+    pub fn state(&self) -> &WatchState {
+        &self.state
+    }
+
+    /// Poll once, returning the events since the last committed poll
+    ///
+    /// The state only moves on with [`BookmarkWatcher::commit`], to be called
+    /// once the events are delivered. Polling again without committing
+    /// returns the same events again, along with any new ones.
+    pub fn poll(&mut self) -> Result<Vec<BookmarkEvent>, LinkDingError> {
+        let full = self.state.polled_at.is_none()
+            || self.state.cursor.is_none()
+            || self.state.incremental_polls + 1 >= self.options.full_poll_every;
+        let args = ListBookmarksArgs {
+            modified_since: match full {
+                true => None,
+                false => self.state.cursor.clone(),
+            },
+            ..Default::default()
+        };
+        let bookmarks = self
+            .client
+            .iter_bookmarks(args.clone())
+            .chain(self.client.iter_archived_bookmarks(args.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        // older linkding versions ignore the filter and list everything
+        let full = full
+            || bookmarks
+                .iter()
+                .any(|bookmark| args.predates_modified_since(bookmark));
+        let mut state = self.state.clone();
+        let events = state.advance(bookmarks, full, self.options.emit_existing);
+        self.pending = Some(state);
+        Ok(events)
+    }
+
+    /// Save the state of the last poll, so its events aren't returned again
+    pub fn commit(&mut self) -> Result<(), LinkDingError> {
+        if let Some(state) = self.pending.take() {
+            state.save(&self.state_path)?;
+            self.state = state;
+        }
+        Ok(())
+    }
+
+    /// Poll every [`WatchOptions::interval`], passing each event to `handler`
+    /// until it breaks or a poll fails
+    ///
+    /// The state is committed after all events of a poll were handled. When
+    /// the handler breaks, the events of that poll are returned again by the
+    /// next one.
+    pub fn run<F>(&mut self, mut handler: F) -> Result<(), LinkDingError>
+    where
+        F: FnMut(BookmarkEvent) -> ControlFlow<()>,
+    {
+        loop {
+            for event in self.poll()? {
+                if handler(event).is_break() {
+                    return Ok(());
+                }
+            }
+            self.commit()?;
+            std::thread::sleep(self.options.interval);
+        }
+    }
+
+    /// Poll every [`WatchOptions::interval`], sending the events to `sender`
+    /// until the receiver is dropped or a poll fails
+    pub fn run_with_channel(
+        &mut self,
+        sender: &mpsc::Sender<BookmarkEvent>,
+    ) -> Result<(), LinkDingError> {
+        self.run(|event| match sender.send(event) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(_) => ControlFlow::Break(()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, page, serve_api, tags, test_dir, Route};
+
+    fn bookmark(id: i32, tag_names: &[&str]) -> Bookmark {
+        Bookmark {
+            title: "Title".to_string(),
+            tag_names: tags(tag_names),
+            ..test_util::bookmark(id)
+        }
+    }
+
+    fn kinds(events: &[BookmarkEvent]) -> Vec<BookmarkEventKind> {
+        events.iter().map(BookmarkEvent::kind).collect()
+    }
+
+    #[test]
+    fn first_poll_only_records_unless_emitting_existing() {
+        let mut state = WatchState::default();
+        assert!(state
+            .advance(vec![bookmark(1, &[])], true, false)
+            .is_empty());
+        assert!(state.polled_at.is_some());
+
+        let mut state = WatchState::default();
+        let events = state.advance(vec![bookmark(1, &[])], true, true);
+        assert_eq!(kinds(&events), vec![BookmarkEventKind::Created]);
+    }
+
+    #[test]
+    fn emits_events_for_changes_between_polls() {
+        let mut state = WatchState::default();
+        state.advance(
+            vec![bookmark(1, &["rust", "cli"]), bookmark(2, &[])],
+            true,
+            false,
+        );
+
+        let mut changed = bookmark(1, &["Rust", "web"]);
+        changed.title = "New title".to_string();
+        changed.is_archived = true;
+        changed.date_modified = "2025-02-01T00:00:00Z".to_string();
+        let events = state.advance(vec![changed, bookmark(3, &[])], true, false);
+        assert_eq!(
+            kinds(&events),
+            vec![
+                BookmarkEventKind::Updated,
+                BookmarkEventKind::Archived,
+                BookmarkEventKind::TagAdded,
+                BookmarkEventKind::TagRemoved,
+                BookmarkEventKind::Created,
+                BookmarkEventKind::Deleted,
+            ]
+        );
+        let BookmarkEvent::Updated { changes, .. } = &events[0] else {
+            panic!("expected an update");
+        };
+        assert_eq!(
+            **changes,
+            UpdateBookmarkBody {
+                title: Some("New title".to_string()),
+                ..Default::default()
+            }
+        );
+        assert!(matches!(&events[2], BookmarkEvent::TagAdded { tag, .. } if tag == "web"));
+        assert!(matches!(&events[3], BookmarkEvent::TagRemoved { tag, .. } if tag == "cli"));
+        assert_eq!(events[5].bookmark().id, 2);
+
+        assert!(state
+            .advance(state.bookmarks.values().cloned().collect(), true, false)
+            .is_empty());
+    }
+
+    #[test]
+    fn polls_incrementally_and_commits_after_delivery() {
+        let mut changed = bookmark(1, &[]);
+        changed.title = "New title".to_string();
+        changed.date_modified = "2025-02-01T00:00:00Z".to_string();
+        let (url, requests) = serve_api(vec![
+            Route::ok(
+                "GET /api/bookmarks/",
+                page(&[bookmark(1, &[]), bookmark(2, &[])]),
+            ),
+            Route::ok("GET /api/bookmarks/", page(&[changed])),
+            Route::ok("GET /api/bookmarks/archived/", page::<Bookmark>(&[])),
+        ]);
+        let client = LinkDingClient::new(&url, "token");
+        let state_path = test_dir("watcher").join("state.json");
+        let mut watcher =
+            BookmarkWatcher::new(&client, &state_path, WatchOptions::default()).unwrap();
+
+        assert!(watcher.poll().unwrap().is_empty());
+        watcher.commit().unwrap();
+        assert_eq!(
+            watcher.state().cursor.as_deref(),
+            Some("2025-01-01T00:00:00Z")
+        );
+
+        let events = watcher.poll().unwrap();
+        assert_eq!(kinds(&events), vec![BookmarkEventKind::Updated]);
+        assert_eq!(WatchState::load(&state_path).unwrap(), *watcher.state());
+        assert_eq!(watcher.poll().unwrap(), events);
+        watcher.commit().unwrap();
+        let state = WatchState::load(&state_path).unwrap();
+        assert_eq!(state.bookmarks.len(), 2);
+        assert_eq!(state.bookmarks[&1].title, "New title");
+        assert_eq!(state.cursor.as_deref(), Some("2025-02-01T00:00:00Z"));
+
+        let requests = requests.lock().unwrap();
+        assert!(!requests[0].contains("modified_since"));
+        assert!(requests[2].contains("modified_since=2025-01-01T00%3A00%3A00Z"));
+    }
+
+    #[test]
+    fn events_serialize_with_their_kind() {
+        let event = BookmarkEvent::TagAdded {
+            bookmark: bookmark(1, &["rust"]),
+            tag: "rust".to_string(),
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["event"], "tag_added");
+        assert_eq!(json["tag"], "rust");
+        assert_eq!(
+            serde_json::from_value::<BookmarkEvent>(json).unwrap(),
+            event
+        );
+    }
+}
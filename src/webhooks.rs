@@ -0,0 +1,611 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufWriter, Write},
+    ops::ControlFlow,
+    path::PathBuf,
+    time::Duration,
+};
+
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    backoff::{is_retryable, Backoff},
+    tag_changes::tag_eq,
+    timestamp::UtcDateTime,
+    BookmarkEvent, BookmarkEventKind, BookmarkWatcher, LinkDingError, SearchQuery, TagSearchMethod,
+};
+
+/// Header carrying the event kind, e.g. `tag_added`
+pub const EVENT_HEADER: &str = "X-Linkding-Event";
+/// Header carrying an ID that stays the same when a payload is redelivered
+/// or its event is dispatched again, for receivers to drop duplicates
+pub const DELIVERY_HEADER: &str = "X-Linkding-Delivery";
+/// Header carrying `sha256=` and the hex HMAC-SHA256 of the body
+pub const SIGNATURE_HEADER: &str = "X-Linkding-Signature-256";
+
+/// An HTTP endpoint receiving bookmark events
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    /// Sign payloads with this secret, see [`verify_signature`]
+    pub secret: Option<String>,
+    /// Only these kinds of events, all if empty
+    pub events: Vec<BookmarkEventKind>,
+    /// Only events of bookmarks with any of these tags, all if empty
+    pub tags: Vec<String>,
+    /// Only events of bookmarks matching the query
+    pub query: Option<SearchQuery>,
+}
+
+impl WebhookEndpoint {
+    /// An endpoint receiving all events, unsigned
+    pub fn new(url: &str) -> WebhookEndpoint {
+        WebhookEndpoint {
+            url: url.to_string(),
+            secret: None,
+            events: Vec::new(),
+            tags: Vec::new(),
+            query: None,
+        }
+    }
+
+    /// Whether the endpoint's filters let the event through
+    ///
+    /// A tag filter also matches the tag of a [`BookmarkEvent::TagRemoved`],
+    /// which the bookmark no longer carries.
+    pub fn accepts(&self, event: &BookmarkEvent, tag_search: TagSearchMethod) -> bool {
+        if !self.events.is_empty() && !self.events.contains(&event.kind()) {
+            return false;
+        }
+        let bookmark = event.bookmark();
+        if !self.tags.is_empty() {
+            let removed = match event {
+                BookmarkEvent::TagRemoved { tag, .. } => Some(tag.as_str()),
+                _ => None,
+            };
+            let tagged = self.tags.iter().any(|wanted| {
+                removed.is_some_and(|tag| tag_eq(tag, wanted))
+                    || bookmark.tag_names.iter().any(|tag| tag_eq(tag, wanted))
+            });
+            if !tagged {
+                return false;
+            }
+        }
+        self.query
+            .as_ref()
+            .is_none_or(|query| query.matches(bookmark, tag_search))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WebhookOptions {
+    /// Deliveries per payload before it goes to the dead-letter file
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// Timeout of a single delivery request
+    pub timeout: Duration,
+    /// File collecting payloads that couldn't be delivered, as JSON lines
+    ///
+    /// Without one, [`WebhookDispatcher::run`] stops at the first payload it
+    /// can't deliver, before the watcher state moves past its event.
+    pub dead_letter_path: Option<PathBuf>,
+    /// How query filters match tags, see [`SearchQuery::matches`]
+    pub tag_search: TagSearchMethod,
+}
+
+impl Default for WebhookOptions {
+    fn default() -> Self {
+        WebhookOptions {
+            max_attempts: 5,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            timeout: Duration::from_secs(10),
+            dead_letter_path: None,
+            tag_search: TagSearchMethod::default(),
+        }
+    }
+}
+
+/// The JSON body posted to endpoints
+///
+/// The event's fields are inlined, so the body has the kind in its `event`
+/// field next to `delivery` and `timestamp`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub delivery: String,
+    /// When the event was dispatched, in RFC 3339
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub event: BookmarkEvent,
+}
+
+impl WebhookPayload {
+    fn new(event: &BookmarkEvent) -> WebhookPayload {
+        WebhookPayload {
+            delivery: delivery_id(event),
+            timestamp: UtcDateTime::now().to_rfc3339(),
+            event: event.clone(),
+        }
+    }
+}
+
+/// An ID derived only from what the event is about, so an event the watcher
+/// returns again after a restart keeps its ID
+fn delivery_id(event: &BookmarkEvent) -> String {
+    let bookmark = event.bookmark();
+    let tag = match event {
+        BookmarkEvent::TagAdded { tag, .. } | BookmarkEvent::TagRemoved { tag, .. } => tag,
+        _ => "",
+    };
+    let key = [
+        event.kind().as_str(),
+        &bookmark.id.to_string(),
+        &bookmark.date_modified,
+        tag,
+    ]
+    .join("\n");
+    let hash = format!("{:x}", Sha256::digest(key));
+    hash[..32].to_string()
+}
+
+/// A payload that couldn't be delivered, as stored in the dead-letter file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub endpoint: String,
+    pub payload: WebhookPayload,
+    pub attempts: u32,
+    pub error: String,
+    pub failed_at: String,
+}
+
+#[derive(Debug, Default)]
+pub struct WebhookReport {
+    /// Endpoint URL and delivery ID of each delivered payload
+    pub delivered: Vec<(String, String)>,
+    /// Endpoint URL and last error of each payload given up on
+    pub failed: Vec<(String, LinkDingError)>,
+}
+
+/// Posts bookmark events to HTTP endpoints
+///
+/// Each payload is retried with exponential backoff on connection errors,
+/// server errors, `408` and `429`. Payloads still failing are appended to
+/// the dead-letter file, from where [`WebhookDispatcher::redeliver_dead_letters`]
+/// can try them again.
+#[derive(Debug, Clone)]
+pub struct WebhookDispatcher {
+    endpoints: Vec<WebhookEndpoint>,
+    options: WebhookOptions,
+    client: reqwest::blocking::Client,
+}
+
+impl WebhookDispatcher {
+    pub fn new(endpoints: Vec<WebhookEndpoint>, options: WebhookOptions) -> WebhookDispatcher {
+        WebhookDispatcher {
+            client: reqwest::blocking::Client::builder()
+                .timeout(options.timeout)
+                .build()
+                .expect("Could not create web client"),
+            endpoints,
+            options,
+        }
+    }
+
+    /// Deliver the event to all endpoints accepting it
+    ///
+    /// Only failing to write the dead-letter file is an error, failed
+    /// deliveries are listed in the report.
+    pub fn dispatch(&self, event: &BookmarkEvent) -> Result<WebhookReport, LinkDingError> {
+        let mut report = WebhookReport::default();
+        let endpoints: Vec<&WebhookEndpoint> = self
+            .endpoints
+            .iter()
+            .filter(|endpoint| endpoint.accepts(event, self.options.tag_search))
+            .collect();
+        if endpoints.is_empty() {
+            return Ok(report);
+        }
+        let payload = WebhookPayload::new(event);
+        for endpoint in endpoints {
+            self.deliver_or_dead_letter(endpoint, &payload, &mut report)?;
+        }
+        Ok(report)
+    }
+
+    /// Dispatch the events of a watcher until a poll or writing the
+    /// dead-letter file fails
+    ///
+    /// The watcher state is only committed once every event of a poll was
+    /// delivered or written to the dead-letter file. Without a dead-letter
+    /// file a failed delivery stops dispatching with its error, and the
+    /// watcher returns the event again after a restart.
+    pub fn run(&self, watcher: &mut BookmarkWatcher) -> Result<(), LinkDingError> {
+        let mut failure = None;
+        watcher.run(|event| {
+            let error = match self.dispatch(&event) {
+                Ok(_) if self.options.dead_letter_path.is_some() => {
+                    return ControlFlow::Continue(())
+                }
+                Ok(report) => match report.failed.into_iter().next() {
+                    Some((_, error)) => error,
+                    None => return ControlFlow::Continue(()),
+                },
+                Err(error) => error,
+            };
+            failure = Some(error);
+            ControlFlow::Break(())
+        })?;
+        failure.map_or(Ok(()), Err)
+    }
+
+    /// Try the payloads in the dead-letter file again
+    ///
+    /// Letters for endpoints that are no longer configured are kept as they
+    /// are, and so are the ones failing again, with their error updated.
+    pub fn redeliver_dead_letters(&self) -> Result<WebhookReport, LinkDingError> {
+        let mut report = WebhookReport::default();
+        let Some(path) = &self.options.dead_letter_path else {
+            return Ok(report);
+        };
+        let letters = match File::open(path) {
+            Ok(file) => io::BufReader::new(file)
+                .lines()
+                .filter(|line| line.as_ref().is_ok_and(|line| !line.trim().is_empty()))
+                .map(|line| Ok(serde_json::from_str::<DeadLetter>(&line?)?))
+                .collect::<Result<Vec<_>, LinkDingError>>()?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(report),
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut remaining = Vec::new();
+        for mut letter in letters {
+            let Some(endpoint) = self.endpoints.iter().find(|e| e.url == letter.endpoint) else {
+                remaining.push(letter);
+                continue;
+            };
+            match self.deliver(endpoint, &letter.payload) {
+                Ok(_) => report
+                    .delivered
+                    .push((letter.endpoint, letter.payload.delivery)),
+                Err((error, attempts)) => {
+                    letter.attempts += attempts;
+                    letter.error = error.to_string();
+                    letter.failed_at = UtcDateTime::now().to_rfc3339();
+                    report.failed.push((letter.endpoint.clone(), error));
+                    remaining.push(letter);
+                }
+            }
+        }
+
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        for letter in &remaining {
+            serde_json::to_writer(&mut writer, letter)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        fs::rename(temp_path, path)?;
+        Ok(report)
+    }
+
+    fn deliver_or_dead_letter(
+        &self,
+        endpoint: &WebhookEndpoint,
+        payload: &WebhookPayload,
+        report: &mut WebhookReport,
+    ) -> Result<(), LinkDingError> {
+        let (error, attempts) = match self.deliver(endpoint, payload) {
+            Ok(_) => {
+                report
+                    .delivered
+                    .push((endpoint.url.clone(), payload.delivery.clone()));
+                return Ok(());
+            }
+            Err(failure) => failure,
+        };
+        if let Some(path) = &self.options.dead_letter_path {
+            let letter = DeadLetter {
+                endpoint: endpoint.url.clone(),
+                payload: payload.clone(),
+                attempts,
+                error: error.to_string(),
+                failed_at: UtcDateTime::now().to_rfc3339(),
+            };
+            let mut line = serde_json::to_vec(&letter)?;
+            line.push(b'\n');
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?
+                .write_all(&line)?;
+        }
+        report.failed.push((endpoint.url.clone(), error));
+        Ok(())
+    }
+
+    /// Post the payload, retrying with backoff, returning the number of
+    /// attempts made
+    fn deliver(
+        &self,
+        endpoint: &WebhookEndpoint,
+        payload: &WebhookPayload,
+    ) -> Result<u32, (LinkDingError, u32)> {
+        let body = serde_json::to_vec(payload).map_err(|e| (e.into(), 0))?;
+        let mut backoff = Backoff::new(self.options.initial_delay, self.options.max_delay);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let mut request = self
+                .client
+                .post(&endpoint.url)
+                .header(CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, payload.event.kind().as_str())
+                .header(DELIVERY_HEADER, &payload.delivery);
+            if let Some(secret) = &endpoint.secret {
+                request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+            }
+            let error = match request.body(body.clone()).send() {
+                Ok(response) => match response.error_for_status() {
+                    Ok(_) => return Ok(attempts),
+                    Err(error) => error,
+                },
+                Err(error) => error,
+            };
+            if !is_retryable(&error) || attempts >= self.options.max_attempts {
+                return Err((error.into(), attempts));
+            }
+            backoff.wait();
+        }
+    }
+}
+
+/// The signature header value for a body, `sha256=` and the hex HMAC-SHA256
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+/// Check a received signature header against the body, for receivers
+pub fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(expected) = signature
+        .strip_prefix("sha256=")
+        .and_then(|hex| decode_hex(hex.trim()))
+    else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::*;
+    use crate::{
+        test_util::{self, page, serve_api, tags, test_dir, Route},
+        Bookmark, LinkDingClient, WatchOptions,
+    };
+
+    fn bookmark(tag_names: &[&str]) -> Bookmark {
+        Bookmark {
+            title: "Rust release notes".to_string(),
+            tag_names: tags(tag_names),
+            ..test_util::bookmark(1)
+        }
+    }
+
+    fn options() -> WebhookOptions {
+        WebhookOptions {
+            max_attempts: 2,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+            timeout: Duration::from_secs(5),
+            ..Default::default()
+        }
+    }
+
+    /// Answer a single request with `status`, returning the raw request
+    fn serve_once(status: u16) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            loop {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+            }
+            write!(
+                stream,
+                "HTTP/1.1 {} OK\r\ncontent-length: 0\r\n\r\n",
+                status
+            )
+            .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        // RFC 4231, test case 2
+        let signature = sign("Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            signature,
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert!(verify_signature(
+            "Jefe",
+            b"what do ya want for nothing?",
+            &signature
+        ));
+        assert!(!verify_signature("Jefe", b"something else", &signature));
+        assert!(!verify_signature("Jefe", b"", "sha256=zz"));
+    }
+
+    #[test]
+    fn endpoints_filter_by_kind_tag_and_query() {
+        let added = BookmarkEvent::TagAdded {
+            bookmark: bookmark(&["rust"]),
+            tag: "rust".to_string(),
+        };
+        let removed = BookmarkEvent::TagRemoved {
+            bookmark: bookmark(&[]),
+            tag: "Rust".to_string(),
+        };
+        let lax = TagSearchMethod::Lax;
+
+        let mut endpoint = WebhookEndpoint::new("http://localhost/");
+        assert!(endpoint.accepts(&added, lax));
+        endpoint.tags = vec!["rust".to_string()];
+        assert!(endpoint.accepts(&added, lax) && endpoint.accepts(&removed, lax));
+        endpoint.events = vec![BookmarkEventKind::TagRemoved];
+        assert!(!endpoint.accepts(&added, lax) && endpoint.accepts(&removed, lax));
+        endpoint.query = Some("release !unread".parse().unwrap());
+        assert!(!endpoint.accepts(&removed, lax));
+        endpoint.query = Some("release".parse().unwrap());
+        assert!(endpoint.accepts(&removed, lax));
+    }
+
+    #[test]
+    fn redelivered_events_keep_their_id() {
+        let added = |tag: &str| BookmarkEvent::TagAdded {
+            bookmark: bookmark(&["rust", "cli"]),
+            tag: tag.to_string(),
+        };
+        let first = WebhookPayload::new(&added("rust"));
+        assert_eq!(WebhookPayload::new(&added("rust")).delivery, first.delivery);
+        assert_ne!(WebhookPayload::new(&added("cli")).delivery, first.delivery);
+        assert_eq!(first.delivery.len(), 32);
+    }
+
+    #[test]
+    fn posts_signed_payloads() {
+        let (url, server) = serve_once(200);
+        let endpoint = WebhookEndpoint {
+            secret: Some("secret".to_string()),
+            ..WebhookEndpoint::new(&url)
+        };
+        let dispatcher = WebhookDispatcher::new(vec![endpoint], options());
+        let event = BookmarkEvent::Created {
+            bookmark: bookmark(&[]),
+        };
+        let report = dispatcher.dispatch(&event).unwrap();
+        assert_eq!(report.delivered.len(), 1);
+        assert!(report.failed.is_empty());
+
+        let request = server.join().unwrap();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        let header = |name: &str| {
+            head.lines()
+                .find_map(|line| {
+                    let (key, value) = line.split_once(": ")?;
+                    key.eq_ignore_ascii_case(name).then(|| value.to_string())
+                })
+                .unwrap()
+        };
+        assert_eq!(header(EVENT_HEADER), "created");
+        assert_eq!(header(DELIVERY_HEADER), report.delivered[0].1);
+        assert!(verify_signature(
+            "secret",
+            body.as_bytes(),
+            &header(SIGNATURE_HEADER)
+        ));
+        let payload: WebhookPayload = serde_json::from_str(body).unwrap();
+        assert_eq!(payload.event, event);
+    }
+
+    #[test]
+    fn failed_payloads_go_to_the_dead_letter_file() {
+        let path = test_dir("webhooks").join("dead-letters.jsonl");
+        let options = WebhookOptions {
+            dead_letter_path: Some(path.clone()),
+            ..options()
+        };
+        let (url, server) = serve_once(400);
+        let event = BookmarkEvent::Deleted {
+            bookmark: bookmark(&[]),
+        };
+        let dispatcher = WebhookDispatcher::new(vec![WebhookEndpoint::new(&url)], options.clone());
+        let report = dispatcher.dispatch(&event).unwrap();
+        server.join().unwrap();
+        assert_eq!(report.failed.len(), 1);
+
+        // a client error isn't retried
+        let letter: DeadLetter =
+            serde_json::from_str(fs::read_to_string(&path).unwrap().trim()).unwrap();
+        assert_eq!(letter.attempts, 1);
+        assert_eq!(letter.endpoint, url);
+
+        let (url, server) = serve_once(200);
+        let dispatcher = WebhookDispatcher::new(vec![WebhookEndpoint::new(&url)], options.clone());
+        let mut contents = fs::read_to_string(&path).unwrap();
+        contents = contents.replace(&letter.endpoint, &url);
+        fs::write(&path, contents).unwrap();
+        let report = dispatcher.redeliver_dead_letters().unwrap();
+        server.join().unwrap();
+        assert_eq!(report.delivered, vec![(url, letter.payload.delivery)]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    }
+
+    #[test]
+    fn undelivered_events_keep_the_watcher_state() {
+        let (api_url, _) = serve_api(vec![
+            Route::ok("GET /api/bookmarks/", page(&[bookmark(&[])])),
+            Route::ok("GET /api/bookmarks/archived/", page::<Bookmark>(&[])),
+        ]);
+        let client = LinkDingClient::new(&api_url, "token");
+        let state_path = test_dir("webhooks").join("state.json");
+        let watch_options = WatchOptions {
+            emit_existing: true,
+            ..Default::default()
+        };
+        let mut watcher = BookmarkWatcher::new(&client, &state_path, watch_options).unwrap();
+
+        let (url, server) = serve_once(400);
+        let dispatcher = WebhookDispatcher::new(vec![WebhookEndpoint::new(&url)], options());
+        assert!(dispatcher.run(&mut watcher).is_err());
+        server.join().unwrap();
+        assert!(!state_path.exists());
+        assert_eq!(watcher.poll().unwrap().len(), 1);
+    }
+}